@@ -1,45 +1,325 @@
+use crate::filewalker::{CollectOptions, CollectedFile, FileOrder, IgnorePolicy, SymlinkPolicy};
+use crate::format::BundleFormat;
+use crate::preamble::Preamble;
+use crate::redact::Redactor;
+use crate::writer::{BinaryMode, DEFAULT_MAX_EMBED_SIZE, FileLimits, PartBudget, default_jobs};
 use anyhow::Result;
 use chrono::Utc;
-use clap::{Arg, Command};
+use clap::{Arg, ArgGroup, Command};
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Configuration for src2md operations.
+///
+/// Fields not relevant to a given run can be left at their defaults with
+/// `..Default::default()`.
+#[derive(Debug, Default)]
 pub struct Config {
     /// Output markdown file path.
     pub output_path: PathBuf,
-    /// Optional custom ignore file path.
-    pub ignore_file: Option<PathBuf>,
+    /// Additional ignore files (like .gitignore), honored in any case.
+    pub ignore_files: Vec<PathBuf>,
+    /// Which `.gitignore` and `.src2mdignore` files found in the project
+    /// are honored.
+    pub ignore: IgnorePolicy,
+    /// Print why each of these paths is or is not collected instead of
+    /// writing a bundle.
+    pub explain: Vec<PathBuf>,
     /// Specific files or directories to include (if empty, includes all).
     pub specific_paths: HashSet<PathBuf>,
     /// Root directory to process.
     pub project_root: PathBuf,
-    /// If set, restore files from this markdown file instead of generating.
+    /// Name of the project recorded in the manifest instead of the name of
+    /// the root directory, e.g. of a repository cloned into a temporary
+    /// directory.
+    pub root_name: Option<String>,
+    /// If non-empty, restore files from these bundle files (or parts of a
+    /// split bundle, in order) instead of generating.
     #[cfg(feature = "restore")]
-    pub restore_input: Option<PathBuf>,
+    pub restore_inputs: Vec<PathBuf>,
     /// Target directory for restoration.
     #[cfg(feature = "restore")]
     pub restore_path: Option<PathBuf>,
+    /// Restore files with default permissions instead of the Unix
+    /// permissions recorded in the bundle, which are applied by default on
+    /// Unix (`--no-restore-permissions`).
+    #[cfg(feature = "restore")]
+    pub ignore_permissions: bool,
     /// Verbosity level (0-3).
     pub verbosity: u8,
     /// Stop on first error if true.
     pub fail_fast: bool,
+    /// Number of files read concurrently (`None` for [`default_jobs`]).
+    pub jobs: Option<usize>,
     /// File extensions to include (if empty, includes all non-excluded).
     /// Extensions should be lowercase without the leading dot (e.g., "rs", "ts", "js").
     pub extensions: HashSet<String>,
+    /// Gitignore-style globs of files to include, relative to the project
+    /// root (if empty, includes all non-excluded).
+    pub include: Vec<String>,
+    /// Gitignore-style globs of files and directories to exclude, relative
+    /// to the project root. Excludes win over includes.
+    pub exclude: Vec<String>,
+    /// Collect hidden files and directories, except `.git` and files that
+    /// commonly hold credentials.
+    pub hidden: bool,
+    /// Hidden files and directories to collect, relative to the project root.
+    pub include_hidden: Vec<PathBuf>,
+    /// Collect lock files, which are skipped by default (see
+    /// [`LOCK_FILE_NAMES`](crate::filewalker::LOCK_FILE_NAMES)).
+    pub include_lockfiles: bool,
+    /// Collect private keys and credential files, which are skipped by
+    /// default (see
+    /// [`SENSITIVE_FILE_NAMES`](crate::filewalker::SENSITIVE_FILE_NAMES)).
+    pub allow_sensitive: bool,
+    /// Globs of generated files to skip, e.g. `*.pb.go`, `*_generated.rs`
+    /// or `*.min.js`; globs containing a `/`, such as `docs/*.md`, match the
+    /// path relative to the project root.
+    pub skip_patterns: Vec<String>,
+    /// Collect minified files, source maps and files marked as generated,
+    /// which are skipped by default.
+    pub include_generated: bool,
+    /// How symbolic links are handled during collection.
+    pub symlinks: SymlinkPolicy,
+    /// Order of the files in the bundle.
+    pub order: FileOrder,
+    /// List the contents of a directory before the files next to it.
+    pub directories_first: bool,
     /// Git repository URL to clone and process (requires `git` feature).
     #[cfg(feature = "git")]
     pub git_url: Option<String>,
     /// Git branch to checkout (requires `git` feature).
     #[cfg(feature = "git")]
     pub git_branch: Option<String>,
+    /// Only collect files changed since the merge base of this revision and
+    /// `HEAD`, including uncommitted changes (requires `git` feature).
+    #[cfg(feature = "git")]
+    pub changed_since: Option<String>,
+    /// Only collect files with staged changes, reading them from the index
+    /// (requires `git` feature).
+    #[cfg(feature = "git")]
+    pub staged: bool,
+    /// Bundle the project as of this commit, tag or branch, read from the
+    /// git object database instead of the working tree (requires `git`
+    /// feature).
+    #[cfg(feature = "git")]
+    pub rev: Option<String>,
+    /// Write the changes in this revision range as a diff bundle instead of
+    /// bundling files (see [`RevRange::parse`](crate::diff::RevRange::parse);
+    /// requires `git` feature).
+    #[cfg(feature = "git")]
+    pub diff: Option<String>,
+    /// Follow each diff in a diff bundle with the full content of the file
+    /// after the change (requires `git` feature).
+    #[cfg(feature = "git")]
+    pub diff_content: bool,
     /// Output directory for mdbook format (requires `mdbook` feature).
     #[cfg(feature = "mdbook")]
     pub mdbook_output: Option<PathBuf>,
+    /// Format of the generated bundle.
+    pub format: BundleFormat,
+    /// Split the bundle into parts of at most this many estimated tokens.
+    pub max_tokens: Option<usize>,
+    /// Split the bundle into parts of at most this many bytes.
+    pub max_bytes: Option<usize>,
+    /// Print a per-file size and token report instead of writing a bundle.
+    pub stats: bool,
+    /// Start the bundle with a tree of the collected files.
+    pub tree: bool,
+    /// End every part with a table of contents linking to the files in it.
+    pub toc: bool,
+    /// Embed binary files as base64 instead of omitting them.
+    pub embed_binary: bool,
+    /// Largest binary file to embed in bytes; `None` uses
+    /// [`DEFAULT_MAX_EMBED_SIZE`].
+    pub max_embed_size: Option<u64>,
+    /// Leave out files larger than this many bytes.
+    pub max_file_size: Option<u64>,
+    /// Leave out text files with more than this many lines.
+    pub max_lines: Option<usize>,
+    /// Keep the first and last lines of text files over `max_file_size` or
+    /// `max_lines` instead of leaving them out.
+    pub truncate: bool,
+    /// Leave secrets found by the built-in detectors in the bundle instead
+    /// of replacing them with `[REDACTED:kind]`, which is done by default
+    /// (`--no-redact`).
+    pub allow_secrets: bool,
+    /// Regular expressions of additional secrets to redact.
+    pub redact_patterns: Vec<String>,
+    /// Fail instead of writing a bundle if any secret is found.
+    pub fail_on_secrets: bool,
+}
+
+impl Config {
+    /// Returns the restore options configured by `ignore_permissions`.
+    #[cfg(feature = "restore")]
+    pub fn restore_options(&self) -> crate::extractor::RestoreOptions {
+        crate::extractor::RestoreOptions {
+            restore_permissions: cfg!(unix) && !self.ignore_permissions,
+        }
+    }
+
+    /// Returns the collection filters configured by `ignore_files`, `ignore`,
+    /// `specific_paths`, `output_path`, `extensions`, `include`, `exclude`,
+    /// `hidden`, `include_hidden`, `include_lockfiles`, `allow_sensitive`,
+    /// `skip_patterns`, `include_generated`, `symlinks`, `order` and
+    /// `directories_first`.
+    pub fn collect_options(&self) -> CollectOptions {
+        CollectOptions {
+            ignore_files: self.ignore_files.clone(),
+            ignore: self.ignore,
+            specific_paths: self.specific_paths.clone(),
+            only_files: None,
+            output_path: Some(self.output_path.clone()),
+            extensions: self.extensions.clone(),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            hidden: self.hidden,
+            include_hidden: self.include_hidden.clone(),
+            include_lockfiles: self.include_lockfiles,
+            allow_sensitive: self.allow_sensitive,
+            skip_patterns: self.skip_patterns.clone(),
+            symlinks: self.symlinks,
+            order: self.order,
+            directories_first: self.directories_first,
+        }
+    }
+
+    /// Returns the per-part size limits configured by `max_tokens` and `max_bytes`.
+    pub fn part_budget(&self) -> PartBudget {
+        PartBudget {
+            max_tokens: self.max_tokens,
+            max_bytes: self.max_bytes,
+        }
+    }
+
+    /// Returns how binary files are written, as configured by
+    /// `embed_binary` and `max_embed_size`.
+    pub fn binary_mode(&self) -> BinaryMode {
+        if self.embed_binary {
+            BinaryMode::Embed {
+                max_size: Some(self.max_embed_size.unwrap_or(DEFAULT_MAX_EMBED_SIZE)),
+            }
+        } else {
+            BinaryMode::Omit
+        }
+    }
+
+    /// Returns the single-file limits configured by `max_file_size`,
+    /// `max_lines` and `truncate`.
+    pub fn file_limits(&self) -> FileLimits {
+        FileLimits {
+            max_size: self.max_file_size,
+            max_lines: self.max_lines,
+            truncate: self.truncate,
+        }
+    }
+
+    /// Returns the number of files read concurrently, as configured by `jobs`.
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(default_jobs)
+    }
+
+    /// Returns the redactor configured by `allow_secrets`,
+    /// `redact_patterns` and `fail_on_secrets`, or `None` if nothing is
+    /// redacted.
+    pub fn redactor(&self) -> Result<Option<Redactor>> {
+        let redactor = Redactor::new(
+            !self.allow_secrets || self.fail_on_secrets,
+            &self.redact_patterns,
+        )?;
+        Ok((!redactor.is_empty()).then_some(redactor))
+    }
+
+    /// Returns the mdbook options configured by `include_generated` and the
+    /// redaction settings.
+    #[cfg(feature = "mdbook")]
+    pub fn mdbook_options(&self) -> Result<crate::mdbook::MdbookOptions> {
+        Ok(crate::mdbook::MdbookOptions {
+            include_generated: self.include_generated,
+            redactor: self.redactor()?,
+            fail_on_secrets: self.fail_on_secrets,
+        })
+    }
+
+    /// Returns the diff bundle options configured by `diff_content`,
+    /// `include_generated`, the collection filters and the redaction
+    /// settings.
+    #[cfg(feature = "git")]
+    pub fn diff_bundle_options(&self) -> Result<crate::diff::DiffBundleOptions> {
+        Ok(crate::diff::DiffBundleOptions {
+            collect: self.collect_options(),
+            include_generated: self.include_generated,
+            full_content: self.diff_content,
+            redactor: self.redactor()?,
+        })
+    }
+
+    /// Restricts `options` to the files changed as configured by
+    /// `changed_since` and `staged`, and returns the deleted files relative
+    /// to `project_root`. Does nothing if neither is set.
+    #[cfg_attr(not(feature = "git"), allow(unused_variables))]
+    pub fn restrict_to_changes(
+        &self,
+        options: &mut CollectOptions,
+        project_root: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        #[cfg(feature = "git")]
+        {
+            use crate::git::{ChangeBase, changed_files};
+
+            let base = match (&self.changed_since, self.staged) {
+                (Some(rev), _) => ChangeBase::Since(rev.clone()),
+                (None, true) => ChangeBase::Staged,
+                (None, false) => return Ok(Vec::new()),
+            };
+            let changes = changed_files(project_root, &base)?;
+            log::info!(
+                "{} changed and {} deleted files",
+                changes.changed.len(),
+                changes.deleted.len()
+            );
+            options.only_files = Some(changes.changed.into_iter().collect());
+            return Ok(changes.deleted);
+        }
+        #[allow(unreachable_code)]
+        Ok(Vec::new())
+    }
+
+    /// Returns the preamble configured by `tree` and `toc` for the collected
+    /// entries and the `deleted` files, or `None` if there is nothing to
+    /// show.
+    pub fn preamble(
+        &self,
+        entries: &[impl CollectedFile],
+        project_root: &Path,
+        deleted: &[PathBuf],
+    ) -> Option<Preamble> {
+        if !self.tree && !self.toc && deleted.is_empty() {
+            return None;
+        }
+
+        let paths = entries
+            .iter()
+            .map(|entry| {
+                let path = entry.path();
+                path.strip_prefix(project_root)
+                    .unwrap_or(path)
+                    .to_path_buf()
+            })
+            .collect();
+
+        Some(Preamble {
+            paths,
+            tree: self.tree,
+            toc: self.toc,
+            deleted: deleted.to_vec(),
+        })
+    }
 }
 
 /// Parses command-line arguments and returns a Config.
-#[allow(unused_mut)] // mut is needed when features are enabled
+#[allow(unused_mut)] // needed when features are enabled
 pub fn parse_args() -> Result<Config> {
     let mut cmd = Command::new("src2md")
         .version(env!("CARGO_PKG_VERSION"))
@@ -57,7 +337,28 @@ pub fn parse_args() -> Result<Config> {
             Arg::new("ignore-file")
                 .long("ignore-file")
                 .value_name("FILE")
-                .help("Path to ignore file (like .gitignore)")
+                .help("Path to an additional ignore file like .gitignore (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("no-ignore")
+                .long("no-ignore")
+                .help("Do not honor .gitignore and .src2mdignore files (--ignore-file still applies)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-gitignore")
+                .long("no-gitignore")
+                .help("Do not honor .gitignore files and git excludes")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .value_name("PATH")
+                .help("Print which rule includes or excludes PATH instead of writing a bundle (repeatable)")
+                .action(clap::ArgAction::Append)
                 .num_args(1),
         )
         .arg(
@@ -81,6 +382,206 @@ pub fn parse_args() -> Result<Config> {
                 .help("Only include files with these extensions (comma-separated, e.g., rs,ts,js)")
                 .num_args(1),
         )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .help("Only include files matching this gitignore-style glob, relative to the project root (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Exclude files and directories matching this gitignore-style glob (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Output format: markdown, json, jsonl or xml")
+                .value_parser(clap::builder::PossibleValuesParser::new(
+                    BundleFormat::NAMES,
+                ))
+                .default_value("markdown"),
+        )
+        .arg(
+            Arg::new("hidden")
+                .long("hidden")
+                .help("Include hidden files and directories, except .git and credential files like .env")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("include-hidden")
+                .long("include-hidden")
+                .value_name("PATH")
+                .help("Include this hidden file or directory, relative to the project root (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("include-lockfiles")
+                .long("include-lockfiles")
+                .help("Include lock files like Cargo.lock and package-lock.json")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("allow-sensitive")
+                .long("allow-sensitive")
+                .help("Include private keys and credential files like id_rsa, *.pem and secrets.yaml")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .value_name("GLOB")
+                .help("Skip files matching this glob, e.g. '*.pb.go' or 'docs/*.md'; globs without '/' match the file name (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("include-generated")
+                .long("include-generated")
+                .help("Include minified files, source maps and files marked as generated")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("symlinks")
+                .long("symlinks")
+                .value_name("POLICY")
+                .help("How to handle symbolic links: skip, follow (only inside the project) or record")
+                .value_parser(clap::builder::PossibleValuesParser::new(
+                    SymlinkPolicy::NAMES,
+                ))
+                .default_value("follow"),
+        )
+        .arg(
+            Arg::new("order")
+                .long("order")
+                .value_name("ORDER")
+                .help("Order of files in the bundle: path, size (largest first), mtime (newest first) or priority (README, manifests and entry points first)")
+                .value_parser(clap::builder::PossibleValuesParser::new(FileOrder::NAMES))
+                .default_value("path"),
+        )
+        .arg(
+            Arg::new("directories-first")
+                .long("directories-first")
+                .help("List the contents of each directory before the files next to it")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-tokens")
+                .long("max-tokens")
+                .value_name("N")
+                .help("Split output into numbered parts of at most N estimated tokens")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("max-bytes")
+                .long("max-bytes")
+                .value_name("N")
+                .help("Split output into numbered parts of at most N bytes")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("tree")
+                .long("tree")
+                .help("Start the bundle with a tree of the included files")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("toc")
+                .long("toc")
+                .help("End each part with a table of contents linking to the files in it")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("embed-binary")
+                .long("embed-binary")
+                .help("Embed binary files as base64 so they can be restored")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-embed-size")
+                .long("max-embed-size")
+                .value_name("BYTES")
+                .help("Omit binary files larger than this when embedding (default: 1048576)")
+                .value_parser(clap::value_parser!(u64))
+                .requires("embed-binary")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("max-file-size")
+                .long("max-file-size")
+                .value_name("BYTES")
+                .help("Leave out files larger than BYTES, writing a placeholder instead")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("max-lines")
+                .long("max-lines")
+                .value_name("N")
+                .help("Leave out text files with more than N lines, writing a placeholder instead")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .num_args(1),
+        )
+        .group(
+            ArgGroup::new("file-limits")
+                .args(["max-file-size", "max-lines"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::new("truncate")
+                .long("truncate")
+                .help("Keep the first and last lines of files over --max-file-size or --max-lines instead of leaving them out")
+                .requires("file-limits")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-redact")
+                .long("no-redact")
+                .help("Do not redact private keys, access tokens and passwords from the bundle")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("redact-pattern")
+                .long("redact-pattern")
+                .value_name("REGEX")
+                .help("Also redact matches of this regular expression, or of its `secret` group (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("fail-on-secrets")
+                .long("fail-on-secrets")
+                .help("Fail without writing a bundle if any secret is found")
+                .conflicts_with("no-redact")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("stats")
+                .long("stats")
+                .help(
+                    "Print bytes, lines and estimated tokens per file instead of writing a bundle",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Read up to N files concurrently (default: number of CPUs)")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .num_args(1),
+        )
         .arg(
             Arg::new("fail-fast")
                 .long("fail-fast")
@@ -96,8 +597,42 @@ pub fn parse_args() -> Result<Config> {
                 Arg::new("restore")
                     .long("restore")
                     .value_name("MARKDOWN")
-                    .help("Restore files from a src2md Markdown file back to filesystem")
-                    .conflicts_with_all(["output", "ignore-file", "paths", "ext"]),
+                    .help("Restore files from a src2md bundle (or all parts of a split bundle) back to filesystem")
+                    .num_args(1..)
+                    .conflicts_with_all([
+                        "output",
+                        "ignore-file",
+                        "no-ignore",
+                        "no-gitignore",
+                        "explain",
+                        "paths",
+                        "ext",
+                        "include",
+                        "exclude",
+                        "hidden",
+                        "include-hidden",
+                        "include-lockfiles",
+                        "allow-sensitive",
+                        "skip",
+                        "include-generated",
+                        "symlinks",
+                        "order",
+                        "directories-first",
+                        "format",
+                        "max-tokens",
+                        "max-bytes",
+                        "stats",
+                        "tree",
+                        "toc",
+                        "embed-binary",
+                        "max-file-size",
+                        "max-lines",
+                        "truncate",
+                        "no-redact",
+                        "redact-pattern",
+                        "fail-on-secrets",
+                        "jobs",
+                    ]),
             )
             .arg(
                 Arg::new("restore-path")
@@ -105,6 +640,22 @@ pub fn parse_args() -> Result<Config> {
                     .value_name("DIR")
                     .help("Target directory to restore files into (preserves relative paths)")
                     .requires("restore"),
+            )
+            .arg(
+                Arg::new("restore-permissions")
+                    .long("restore-permissions")
+                    .help("Apply recorded Unix file permissions to restored files (default on Unix)")
+                    .requires("restore")
+                    .overrides_with("no-restore-permissions")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("no-restore-permissions")
+                    .long("no-restore-permissions")
+                    .help("Restore files with default permissions instead of the recorded ones")
+                    .requires("restore")
+                    .overrides_with("restore-permissions")
+                    .action(clap::ArgAction::SetTrue),
             );
     }
 
@@ -116,6 +667,11 @@ pub fn parse_args() -> Result<Config> {
         } else {
             &["paths"]
         };
+        let restore_conflicts: &[&str] = if cfg!(feature = "restore") {
+            &["restore"]
+        } else {
+            &[]
+        };
         cmd = cmd
             .arg(
                 Arg::new("git")
@@ -131,17 +687,102 @@ pub fn parse_args() -> Result<Config> {
                     .value_name("BRANCH")
                     .help("Git branch to checkout (default: repository's default branch)")
                     .requires("git"),
+            )
+            .arg(
+                Arg::new("changed-since")
+                    .long("changed-since")
+                    .value_name("REV")
+                    .help("Only include files changed since the merge base of REV and HEAD, including uncommitted changes")
+                    .conflicts_with_all(restore_conflicts)
+                    .conflicts_with_all(["git", "staged"])
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new("staged")
+                    .long("staged")
+                    .help("Only include files with changes staged for commit, as they are in the index")
+                    .conflicts_with_all(restore_conflicts)
+                    .conflicts_with_all(["git", "stats"])
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("rev")
+                    .long("rev")
+                    .value_name("REV")
+                    .help("Bundle a commit, tag or branch of the local repository without checking it out")
+                    .conflicts_with_all(restore_conflicts)
+                    .conflicts_with_all([
+                        "git",
+                        "changed-since",
+                        "staged",
+                        "diff",
+                        "explain",
+                        "stats",
+                    ])
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new("diff")
+                    .long("diff")
+                    .value_name("RANGE")
+                    .help("Write the changes between two revisions as Markdown diffs instead of bundling files; RANGE is FROM..TO, FROM...TO (since their merge base) or FROM (to HEAD)")
+                    .conflicts_with_all(restore_conflicts)
+                    .conflicts_with_all([
+                        "git",
+                        "changed-since",
+                        "staged",
+                        "explain",
+                        "ignore-file",
+                        "no-ignore",
+                        "no-gitignore",
+                        "symlinks",
+                        "order",
+                        "directories-first",
+                        "format",
+                        "max-tokens",
+                        "max-bytes",
+                        "stats",
+                        "tree",
+                        "toc",
+                        "embed-binary",
+                        "max-file-size",
+                        "max-lines",
+                        "truncate",
+                        "jobs",
+                    ])
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new("diff-content")
+                    .long("diff-content")
+                    .help("With --diff, follow each diff with the full content of the file after the change")
+                    .requires("diff")
+                    .action(clap::ArgAction::SetTrue),
             );
     }
 
     // Add mdbook-specific arguments when the feature is enabled
     #[cfg(feature = "mdbook")]
     {
-        let mut conflicts = vec!["output"];
+        let mut conflicts = vec![
+            "output",
+            "explain",
+            "format",
+            "max-tokens",
+            "max-bytes",
+            "stats",
+            "tree",
+            "toc",
+            "embed-binary",
+            "max-file-size",
+            "max-lines",
+            "truncate",
+            "jobs",
+        ];
         #[cfg(feature = "restore")]
         conflicts.push("restore");
         #[cfg(feature = "git")]
-        conflicts.push("git");
+        conflicts.extend(["git", "changed-since", "staged", "rev", "diff"]);
 
         cmd = cmd.arg(
             Arg::new("mdbook")
@@ -172,26 +813,75 @@ pub fn parse_args() -> Result<Config> {
         })
         .unwrap_or_default();
 
+    let format = matches
+        .get_one::<String>("format")
+        .and_then(|name| BundleFormat::from_name(name))
+        .unwrap_or_default();
+    let values = |name: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(name)
+            .map(|vals| vals.cloned().collect())
+            .unwrap_or_default()
+    };
+    let ignore_files: Vec<PathBuf> = values("ignore-file")
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    let ignore = if matches.get_flag("no-ignore") {
+        IgnorePolicy::NONE
+    } else {
+        IgnorePolicy {
+            gitignore: !matches.get_flag("no-gitignore"),
+            ..IgnorePolicy::default()
+        }
+    };
+    let explain: Vec<PathBuf> = values("explain").into_iter().map(PathBuf::from).collect();
+    let include = values("include");
+    let exclude = values("exclude");
+    let hidden = matches.get_flag("hidden");
+    let include_hidden: Vec<PathBuf> = values("include-hidden")
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    let include_lockfiles = matches.get_flag("include-lockfiles");
+    let allow_sensitive = matches.get_flag("allow-sensitive");
+    let skip_patterns = values("skip");
+    let include_generated = matches.get_flag("include-generated");
+    let symlinks = matches
+        .get_one::<String>("symlinks")
+        .and_then(|name| SymlinkPolicy::from_name(name))
+        .unwrap_or_default();
+    let order = matches
+        .get_one::<String>("order")
+        .and_then(|name| FileOrder::from_name(name))
+        .unwrap_or_default();
+    let directories_first = matches.get_flag("directories-first");
+    let max_tokens = matches.get_one::<u64>("max-tokens").map(|&n| n as usize);
+    let max_bytes = matches.get_one::<u64>("max-bytes").map(|&n| n as usize);
+    let stats = matches.get_flag("stats");
+    let tree = matches.get_flag("tree");
+    let toc = matches.get_flag("toc");
+    let embed_binary = matches.get_flag("embed-binary");
+    let max_embed_size = matches.get_one::<u64>("max-embed-size").copied();
+    let max_file_size = matches.get_one::<u64>("max-file-size").copied();
+    let max_lines = matches.get_one::<u64>("max-lines").map(|&n| n as usize);
+    let truncate = matches.get_flag("truncate");
+    let allow_secrets = matches.get_flag("no-redact");
+    let redact_patterns = values("redact-pattern");
+    let fail_on_secrets = matches.get_flag("fail-on-secrets");
+    let jobs = matches.get_one::<u64>("jobs").map(|&n| n as usize);
+
     // Handle restore mode
     #[cfg(feature = "restore")]
-    if let Some(md_path) = matches.get_one::<String>("restore") {
+    if let Some(md_paths) = matches.get_many::<String>("restore") {
         let restore_path = matches.get_one::<String>("restore-path").map(PathBuf::from);
         return Ok(Config {
-            output_path: PathBuf::new(),
-            ignore_file: None,
-            specific_paths: HashSet::new(),
-            project_root: PathBuf::new(),
-            restore_input: Some(PathBuf::from(md_path)),
+            restore_inputs: md_paths.map(PathBuf::from).collect(),
             restore_path,
+            ignore_permissions: matches.get_flag("no-restore-permissions"),
             verbosity,
             fail_fast: matches.get_flag("fail-fast"),
-            extensions: HashSet::new(),
-            #[cfg(feature = "git")]
-            git_url: None,
-            #[cfg(feature = "git")]
-            git_branch: None,
-            #[cfg(feature = "mdbook")]
-            mdbook_output: None,
+            ..Default::default()
         });
     }
 
@@ -204,7 +894,7 @@ pub fn parse_args() -> Result<Config> {
         let repo_name =
             crate::git::repo_name_from_url(git_url).unwrap_or_else(|| "repo".to_string());
         let timestamp = Utc::now().timestamp();
-        let default_filename = format!("{repo_name}_content_{timestamp}.md");
+        let default_filename = format!("{repo_name}_content_{timestamp}.{}", format.extension());
 
         let output_path = matches
             .get_one::<String>("output")
@@ -215,24 +905,47 @@ pub fn parse_args() -> Result<Config> {
                     .join(default_filename)
             });
 
-        let ignore_file = matches.get_one::<String>("ignore-file").map(PathBuf::from);
-
         return Ok(Config {
             output_path,
-            ignore_file,
-            specific_paths: HashSet::new(),
+            ignore_files,
+            ignore,
+            explain,
             project_root: PathBuf::new(), // Will be set after cloning
-            #[cfg(feature = "restore")]
-            restore_input: None,
-            #[cfg(feature = "restore")]
-            restore_path: None,
+            // The clone lives in a temporary directory, so name the bundle
+            // after the repository
+            root_name: crate::git::repo_name_from_url(git_url),
             verbosity,
             fail_fast: matches.get_flag("fail-fast"),
+            jobs,
             extensions,
+            include,
+            exclude,
+            hidden,
+            include_hidden,
+            include_lockfiles,
+            allow_sensitive,
+            skip_patterns,
+            include_generated,
+            symlinks,
+            order,
+            directories_first,
             git_url: Some(git_url.clone()),
             git_branch,
-            #[cfg(feature = "mdbook")]
-            mdbook_output: None,
+            format,
+            max_tokens,
+            max_bytes,
+            stats,
+            tree,
+            toc,
+            embed_binary,
+            max_embed_size,
+            max_file_size,
+            max_lines,
+            truncate,
+            allow_secrets,
+            redact_patterns,
+            fail_on_secrets,
+            ..Default::default()
         });
     }
 
@@ -240,7 +953,6 @@ pub fn parse_args() -> Result<Config> {
     #[cfg(feature = "mdbook")]
     if let Some(mdbook_dir) = matches.get_one::<String>("mdbook") {
         let project_root = std::env::current_dir()?;
-        let ignore_file = matches.get_one::<String>("ignore-file").map(PathBuf::from);
         let specific_paths: HashSet<_> = matches
             .get_many::<String>("paths")
             .map(|vals| vals.map(|s| project_root.join(s)).collect())
@@ -248,21 +960,29 @@ pub fn parse_args() -> Result<Config> {
 
         return Ok(Config {
             output_path: PathBuf::new(), // Not used in mdbook mode
-            ignore_file,
+            ignore_files,
+            ignore,
             specific_paths,
             project_root,
-            #[cfg(feature = "restore")]
-            restore_input: None,
-            #[cfg(feature = "restore")]
-            restore_path: None,
             verbosity,
             fail_fast: matches.get_flag("fail-fast"),
             extensions,
-            #[cfg(feature = "git")]
-            git_url: None,
-            #[cfg(feature = "git")]
-            git_branch: None,
+            include,
+            exclude,
+            hidden,
+            include_hidden,
+            include_lockfiles,
+            allow_sensitive,
+            skip_patterns,
+            include_generated,
+            symlinks,
+            order,
+            directories_first,
+            allow_secrets,
+            redact_patterns,
+            fail_on_secrets,
             mdbook_output: Some(PathBuf::from(mdbook_dir)),
+            ..Default::default()
         });
     }
 
@@ -274,7 +994,12 @@ pub fn parse_args() -> Result<Config> {
             .and_then(|n| n.to_str())
             .unwrap_or("project");
         let timestamp = Utc::now().timestamp();
-        format!("{folder_name}_content_{timestamp}.md")
+        let kind = if cfg!(feature = "git") && matches.contains_id("diff") {
+            "diff"
+        } else {
+            "content"
+        };
+        format!("{folder_name}_{kind}_{timestamp}.{}", format.extension())
     };
 
     let output_path = matches
@@ -282,8 +1007,6 @@ pub fn parse_args() -> Result<Config> {
         .map(PathBuf::from)
         .unwrap_or_else(|| project_root.join(default_filename));
 
-    let ignore_file = matches.get_one::<String>("ignore-file").map(PathBuf::from);
-
     let specific_paths: HashSet<_> = matches
         .get_many::<String>("paths")
         .map(|vals| vals.map(|s| project_root.join(s)).collect())
@@ -291,20 +1014,61 @@ pub fn parse_args() -> Result<Config> {
 
     Ok(Config {
         output_path,
-        ignore_file,
+        ignore_files,
+        ignore,
+        explain,
         specific_paths,
         project_root,
-        #[cfg(feature = "restore")]
-        restore_input: None,
-        #[cfg(feature = "restore")]
-        restore_path: None,
+        root_name: None,
         verbosity,
         fail_fast: matches.get_flag("fail-fast"),
+        jobs,
         extensions,
+        include,
+        exclude,
+        hidden,
+        include_hidden,
+        include_lockfiles,
+        allow_sensitive,
+        skip_patterns,
+        include_generated,
+        symlinks,
+        order,
+        directories_first,
+        format,
+        max_tokens,
+        max_bytes,
+        stats,
+        tree,
+        toc,
+        embed_binary,
+        max_embed_size,
+        max_file_size,
+        max_lines,
+        truncate,
+        allow_secrets,
+        redact_patterns,
+        fail_on_secrets,
+        #[cfg(feature = "git")]
+        changed_since: matches.get_one::<String>("changed-since").cloned(),
+        #[cfg(feature = "git")]
+        staged: matches.get_flag("staged"),
+        #[cfg(feature = "git")]
+        rev: matches.get_one::<String>("rev").cloned(),
+        #[cfg(feature = "git")]
+        diff: matches.get_one::<String>("diff").cloned(),
+        #[cfg(feature = "git")]
+        diff_content: matches.get_flag("diff-content"),
         #[cfg(feature = "git")]
         git_url: None,
         #[cfg(feature = "git")]
         git_branch: None,
+        #[cfg(feature = "restore")]
+        restore_inputs: Vec::new(),
+        #[cfg(feature = "restore")]
+        restore_path: None,
+        #[cfg(feature = "restore")]
+        ignore_permissions: false,
         #[cfg(feature = "mdbook")]
         mdbook_output: None,
     })
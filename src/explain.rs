@@ -0,0 +1,529 @@
+//! Explanations of why a path is or is not collected.
+//!
+//! [`explain_path`] walks down to a single path with the walker and file
+//! filters of
+//! [`collect_files_with_options`](crate::filewalker::collect_files_with_options),
+//! followed by the generated file detection of the writer, and reports the
+//! first check that leaves it out, such as the ignore file
+//! and rule that matched it or one of its parent directories:
+//!
+//! ```text
+//! target/debug/app: excluded (rule "target/" in .gitignore, via target)
+//! ```
+
+use crate::filewalker::{
+    CollectOptions, EntryFilter, FileFilter, SRC2MD_IGNORE_FILENAME, SymlinkPolicy,
+    build_overrides, is_hidden, walk_builder,
+};
+use crate::generated::{GeneratedKind, detect_generated};
+use anyhow::{Context, Result, bail};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::Override;
+use ignore::{DirEntry, Match};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Why a path is left out of the bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exclusion {
+    /// Matched by this `--exclude` glob.
+    ExcludePattern(String),
+    /// Not matched by any `--include` glob.
+    NotIncluded,
+    /// Matched by a rule in an ignore file.
+    IgnoreRule {
+        /// The ignore file containing the rule, relative to the project root
+        /// if it is inside of it.
+        file: Option<PathBuf>,
+        /// The rule as written in the ignore file.
+        rule: String,
+    },
+    /// A hidden file or directory.
+    Hidden,
+    /// A symbolic link left out under this policy.
+    Symlink(SymlinkPolicy),
+    /// Below a symbolic link collected with [`SymlinkPolicy::Record`].
+    InsideRecordedSymlink,
+    /// A private key or credential file.
+    Sensitive,
+    /// A lock file.
+    LockFile,
+    /// A file whose name matches this skip pattern.
+    SkipPattern(String),
+    /// A file whose extension is not selected.
+    Extension,
+    /// Outside of the specific paths to include.
+    NotInPaths,
+    /// Not in [`CollectOptions::only_files`], the changed files.
+    Unchanged,
+    /// The output file being written.
+    OutputFile,
+    /// A bundle generated by src2md.
+    Src2mdOutput,
+    /// A minified or generated file.
+    Generated(GeneratedKind),
+}
+
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExcludePattern(glob) => write!(f, "matches --exclude {glob:?}"),
+            Self::NotIncluded => f.write_str("matches no --include pattern"),
+            Self::IgnoreRule {
+                file: Some(file),
+                rule,
+            } => write!(f, "rule {rule:?} in {}", file.display()),
+            Self::IgnoreRule { file: None, rule } => write!(f, "rule {rule:?}"),
+            Self::Hidden => f.write_str("hidden"),
+            Self::Symlink(SymlinkPolicy::FollowWithinRoot) => {
+                f.write_str("symbolic link pointing outside the project root")
+            }
+            Self::Symlink(_) => f.write_str("symbolic link"),
+            Self::InsideRecordedSymlink => f.write_str("inside a recorded symbolic link"),
+            Self::Sensitive => f.write_str("sensitive file"),
+            Self::LockFile => f.write_str("lock file"),
+            Self::SkipPattern(pattern) => write!(f, "matches --skip {pattern:?}"),
+            Self::Extension => f.write_str("extension not selected"),
+            Self::NotInPaths => f.write_str("not within the given paths"),
+            Self::Unchanged => f.write_str("not among the changed files"),
+            Self::OutputFile => f.write_str("the output file"),
+            Self::Src2mdOutput => f.write_str("a src2md bundle"),
+            Self::Generated(kind) => write!(f, "{kind}"),
+        }
+    }
+}
+
+/// Whether a path is collected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The file is collected, or the directory is searched.
+    Included,
+    /// The path is left out.
+    Excluded {
+        /// The path the exclusion applies to, relative to the project root:
+        /// the explained path itself or one of its parent directories.
+        at: PathBuf,
+        /// Why it is left out.
+        reason: Exclusion,
+    },
+}
+
+/// The result of [`explain_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The explained path, relative to the project root.
+    pub path: PathBuf,
+    /// Whether the path is collected.
+    pub verdict: Verdict,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.verdict {
+            Verdict::Included => write!(f, "{}: included", self.path.display()),
+            Verdict::Excluded { at, reason } if *at == self.path => {
+                write!(f, "{}: excluded ({reason})", self.path.display())
+            }
+            Verdict::Excluded { at, reason } => write!(
+                f,
+                "{}: excluded ({reason}, via {})",
+                self.path.display(),
+                at.display()
+            ),
+        }
+    }
+}
+
+/// Explains whether `path` is collected from `project_root` with `options`
+/// and written to the bundle. Minified files, source maps and files marked
+/// as generated are reported as excluded unless `include_generated` is true.
+///
+/// Relative paths are resolved against the project root. Fails if the path
+/// does not exist or is outside of the project root.
+pub fn explain_path(
+    project_root: &Path,
+    options: &CollectOptions,
+    include_generated: bool,
+    path: &Path,
+) -> Result<Explanation> {
+    let root = std::path::absolute(project_root)
+        .with_context(|| format!("Invalid project root: {}", project_root.display()))?;
+    let full = root.join(path);
+    let rel_path = match full.strip_prefix(&root) {
+        Ok(rel) if !rel.components().any(|c| c == Component::ParentDir) => rel.to_path_buf(),
+        _ => bail!("{} is outside the project root", path.display()),
+    };
+    if rel_path.as_os_str().is_empty() {
+        bail!("{} is the project root", path.display());
+    }
+    std::fs::symlink_metadata(&full)
+        .with_context(|| format!("Cannot explain {}", path.display()))?;
+
+    let excluded = |at: &Path, reason| {
+        Ok(Explanation {
+            path: rel_path.clone(),
+            verdict: Verdict::Excluded {
+                at: at.strip_prefix(&root).unwrap_or(at).to_path_buf(),
+                reason,
+            },
+        })
+    };
+
+    // Walk the way down to the path with the walker used for collection;
+    // the first entry it does not reach is where the path is left out
+    let target = full.clone();
+    let reached: HashSet<PathBuf> = walk_builder(&root, options, move |entry| {
+        target.starts_with(entry.path())
+    })?
+    .build()
+    .filter_map(Result::ok)
+    .map(DirEntry::into_path)
+    .collect();
+
+    let mut current = root.clone();
+    for component in rel_path.components() {
+        if current != root
+            && options.symlinks == SymlinkPolicy::Record
+            && std::fs::symlink_metadata(&current).is_ok_and(|m| m.file_type().is_symlink())
+        {
+            return excluded(&current, Exclusion::InsideRecordedSymlink);
+        }
+        current.push(component);
+        if !reached.contains(&current) {
+            let reason = walker_exclusion(&root, options, &current)?;
+            return excluded(&current, reason);
+        }
+    }
+
+    let recorded_link = options.symlinks == SymlinkPolicy::Record
+        && std::fs::symlink_metadata(&full).is_ok_and(|m| m.file_type().is_symlink());
+    if (full.is_file() || recorded_link)
+        && let Some(reason) = FileFilter::new(&root, options)?.exclusion(&full)
+    {
+        return excluded(&full, reason);
+    }
+    // The writer recognizes generated files while reading them
+    if full.is_file()
+        && !recorded_link
+        && !include_generated
+        && let Some(kind) = detect_generated(&full)
+    {
+        return excluded(&full, Exclusion::Generated(kind));
+    }
+
+    Ok(Explanation {
+        path: rel_path,
+        verdict: Verdict::Included,
+    })
+}
+
+/// Finds out why the walker skipped `path`, checking its filters in the
+/// order the walker applies them.
+fn walker_exclusion(root: &Path, options: &CollectOptions, path: &Path) -> Result<Exclusion> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?;
+    let is_symlink = metadata.file_type().is_symlink();
+    let is_dir = if is_symlink && options.symlinks == SymlinkPolicy::FollowWithinRoot {
+        path.is_dir()
+    } else {
+        metadata.is_dir()
+    };
+
+    if !options.exclude.is_empty()
+        && build_overrides(root, &options.exclude)?
+            .matched(path, is_dir)
+            .is_ignore()
+    {
+        return Ok(override_exclusion(root, options, path, is_dir));
+    }
+    if let Some(reason) = ignore_rule(root, options, path, is_dir) {
+        return Ok(reason);
+    }
+    if let Some(reason) = EntryFilter::new(root, options).exclusion(path, is_dir, is_symlink) {
+        return Ok(reason);
+    }
+    // Unless some hidden entries are collected, the walker skips all of them
+    if is_hidden(path) {
+        return Ok(Exclusion::Hidden);
+    }
+    bail!("Cannot tell why {} is left out", path.display())
+}
+
+/// Finds the `--exclude` glob that excludes `path`, which the combined
+/// [`Override`] does not report.
+fn override_exclusion(
+    root: &Path,
+    options: &CollectOptions,
+    path: &Path,
+    is_dir: bool,
+) -> Exclusion {
+    options
+        .exclude
+        .iter()
+        .rev()
+        .find(|glob| {
+            build_overrides(root, std::slice::from_ref(glob))
+                .is_ok_and(|single: Override| single.matched(path, is_dir).is_ignore())
+        })
+        .map_or(Exclusion::NotIncluded, |glob| {
+            Exclusion::ExcludePattern(glob.clone())
+        })
+}
+
+/// Names the ignore file rule that makes the walker skip `path`.
+///
+/// The walker decides whether the path is skipped; this only looks up the
+/// rule, trying the ignore files in the order of their precedence:
+/// `.src2mdignore` files, `.gitignore` files and `.git/info/exclude` up to
+/// the repository root, the global git ignore file, then the files of
+/// [`CollectOptions::ignore_files`].
+fn ignore_rule(
+    root: &Path,
+    options: &CollectOptions,
+    path: &Path,
+    is_dir: bool,
+) -> Option<Exclusion> {
+    let policy = options.ignore;
+    let dirs: Vec<&Path> = path.ancestors().skip(1).collect();
+    // Git ignore files above the repository root do not apply
+    let git_dirs = dirs
+        .iter()
+        .position(|dir| dir.join(".git").exists())
+        .map_or(&dirs[..], |idx| &dirs[..=idx]);
+
+    let mut matchers = Vec::new();
+    if policy.src2mdignore {
+        matchers.extend(
+            dirs.iter()
+                .map(|dir| (dir.to_path_buf(), dir.join(SRC2MD_IGNORE_FILENAME))),
+        );
+    }
+    if policy.gitignore {
+        matchers.extend(
+            git_dirs
+                .iter()
+                .map(|dir| (dir.to_path_buf(), dir.join(".gitignore"))),
+        );
+        matchers.extend(
+            git_dirs
+                .iter()
+                .map(|dir| (dir.to_path_buf(), dir.join(".git/info/exclude"))),
+        );
+    }
+    let mut rules: Vec<Gitignore> = matchers
+        .into_iter()
+        .filter(|(_, file)| file.is_file())
+        .map(|(dir, file)| build_gitignore(&dir, &file))
+        .collect();
+    if policy.gitignore {
+        rules.push(Gitignore::global().0);
+    }
+    rules.extend(options.ignore_files.iter().rev().filter_map(|file| {
+        let file = std::path::absolute(file).ok()?;
+        Some(build_gitignore(file.parent()?, &file))
+    }));
+
+    rules
+        .iter()
+        .find_map(|rules| match rules.matched(path, is_dir) {
+            Match::Ignore(glob) => Some(Exclusion::IgnoreRule {
+                file: glob
+                    .from()
+                    .map(|file| file.strip_prefix(root).unwrap_or(file).to_path_buf()),
+                rule: glob.original().to_string(),
+            }),
+            _ => None,
+        })
+}
+
+/// Builds the rules of the ignore file `file`, relative to `dir`.
+fn build_gitignore(dir: &Path, file: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    builder.add(file);
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filewalker::{IgnorePolicy, collect_files_with_options};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn explain(root: &Path, options: &CollectOptions, path: &str) -> Result<String> {
+        Ok(explain_path(root, options, false, Path::new(path))?.to_string())
+    }
+
+    #[test]
+    fn test_explain_ignore_rules() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("target/debug"))?;
+        fs::create_dir_all(root.join("docs/drafts"))?;
+        fs::write(root.join(".gitignore"), "target/\n*.log\n")?;
+        fs::write(root.join("docs/.src2mdignore"), "drafts/\n!keep.log\n")?;
+        fs::write(root.join("target/debug/app"), "bin")?;
+        fs::write(root.join("docs/drafts/idea.md"), "idea")?;
+        fs::write(root.join("docs/keep.log"), "kept")?;
+        fs::write(root.join("docs/build.log"), "log")?;
+        fs::write(root.join("README.md"), "readme")?;
+
+        let options = CollectOptions::default();
+        assert_eq!(
+            explain(root, &options, "target/debug/app")?,
+            "target/debug/app: excluded (rule \"target/\" in .gitignore, via target)"
+        );
+        assert_eq!(
+            explain(root, &options, "docs/drafts/idea.md")?,
+            "docs/drafts/idea.md: excluded (rule \"drafts/\" in docs/.src2mdignore, via docs/drafts)"
+        );
+        assert_eq!(
+            explain(root, &options, "docs/build.log")?,
+            "docs/build.log: excluded (rule \"*.log\" in .gitignore)"
+        );
+        // .src2mdignore rules win over .gitignore rules
+        assert_eq!(
+            explain(root, &options, "docs/keep.log")?,
+            "docs/keep.log: included"
+        );
+        assert_eq!(explain(root, &options, "README.md")?, "README.md: included");
+        assert_eq!(
+            explain(root, &options, ".gitignore")?,
+            ".gitignore: excluded (hidden)"
+        );
+
+        // The explanations agree with collection
+        let collected: Vec<_> = collect_files_with_options(root, &options)?
+            .iter()
+            .map(|entry| entry.path().strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(collected.len(), 2);
+        assert!(collected.contains(&PathBuf::from("docs/keep.log")));
+
+        let hidden = CollectOptions {
+            include_hidden: vec![PathBuf::from(".gitignore")],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &hidden, ".gitignore")?,
+            ".gitignore: included"
+        );
+
+        let no_ignore = CollectOptions {
+            ignore: IgnorePolicy::NONE,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &no_ignore, "target/debug/app")?,
+            "target/debug/app: included"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_filters() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src/generated"))?;
+        fs::write(root.join("src/generated/api.rs"), "// api")?;
+        fs::write(root.join("src/lib.rs"), "// lib")?;
+        fs::write(root.join("notes.txt"), "notes")?;
+        fs::write(root.join("Cargo.lock"), "lock")?;
+        fs::create_dir_all(root.join("certs"))?;
+        fs::write(root.join("certs/server.pem"), "cert")?;
+        fs::write(root.join("extra.ignore"), "notes.txt\n")?;
+
+        let options = CollectOptions {
+            ignore_files: vec![root.join("extra.ignore")],
+            include: vec!["src/**".to_string(), "*.txt".to_string()],
+            exclude: vec!["generated/".to_string()],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &options, "src/generated/api.rs")?,
+            "src/generated/api.rs: excluded (matches --exclude \"generated/\", via src/generated)"
+        );
+        assert_eq!(
+            explain(root, &options, "Cargo.lock")?,
+            "Cargo.lock: excluded (matches no --include pattern)"
+        );
+        assert_eq!(
+            explain(root, &options, "src/lib.rs")?,
+            "src/lib.rs: included"
+        );
+
+        let options = CollectOptions {
+            ignore_files: vec![root.join("extra.ignore")],
+            extensions: ["rs".to_string()].into(),
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &options, "notes.txt")?,
+            "notes.txt: excluded (rule \"notes.txt\" in extra.ignore)"
+        );
+        assert_eq!(
+            explain(root, &options, "Cargo.lock")?,
+            "Cargo.lock: excluded (lock file)"
+        );
+        assert_eq!(
+            explain(root, &options, "certs/server.pem")?,
+            "certs/server.pem: excluded (sensitive file)"
+        );
+        assert_eq!(
+            explain(root, &options, "extra.ignore")?,
+            "extra.ignore: excluded (extension not selected)"
+        );
+
+        let options = CollectOptions {
+            include_lockfiles: true,
+            allow_sensitive: true,
+            skip_patterns: vec!["*.txt".to_string()],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &options, "Cargo.lock")?,
+            "Cargo.lock: included"
+        );
+        assert_eq!(
+            explain(root, &options, "certs/server.pem")?,
+            "certs/server.pem: included"
+        );
+        assert_eq!(
+            explain(root, &options, "notes.txt")?,
+            "notes.txt: excluded (matches --skip \"*.txt\")"
+        );
+
+        let changed = CollectOptions {
+            only_files: Some([PathBuf::from("src/lib.rs")].into()),
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            explain(root, &changed, "src/lib.rs")?,
+            "src/lib.rs: included"
+        );
+        assert_eq!(
+            explain(root, &changed, "src/generated/api.rs")?,
+            "src/generated/api.rs: excluded (not among the changed files)"
+        );
+
+        fs::write(
+            root.join("api.pb.go"),
+            "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n",
+        )?;
+        assert_eq!(
+            explain(root, &options, "api.pb.go")?,
+            "api.pb.go: excluded (marked as generated)"
+        );
+        assert_eq!(
+            explain_path(root, &options, true, Path::new("api.pb.go"))?.to_string(),
+            "api.pb.go: included"
+        );
+
+        assert!(explain(root, &options, "missing.rs").is_err());
+        assert!(explain(root, &options, "../outside.rs").is_err());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,18 @@
+use crate::json::{JSON_MAGIC_BYTES, JsonEntry};
+use crate::manifest::{FileMeta, Manifest};
+use crate::utils::{decode_base64, encode_text, sha256_hex};
+use crate::writer::{
+    BASE64_INFO_STRING, BINARY_OMITTED_MARKER, OUTPUT_MAGIC_BYTES, OUTPUT_MAGIC_BYTES_V1,
+    OVERSIZED_OMITTED_PREFIX,
+};
 use anyhow::{Context, Result};
-use log::debug;
+use encoding_rs::Encoding;
+use log::{debug, info, warn};
 use memmap2::MmapOptions;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
 use std::fs::File as StdFile;
 use std::path::Component;
 use std::path::{Path, PathBuf};
@@ -21,12 +31,122 @@ struct HeaderSection {
     block_start: usize,
 }
 
-/// Extracts files from a Markdown file generated by src2md.
+/// A file recovered from a bundle, before it is written to disk.
+#[derive(Debug)]
+struct RestoredEntry {
+    path: String,
+    /// `None` for entries without restorable content (e.g. omitted binary files).
+    content: Option<Vec<u8>>,
+    /// Checksum recorded when the bundle was generated (format v2).
+    sha256: Option<String>,
+    /// Unix permission bits recorded when the bundle was generated (format v2).
+    mode: Option<u32>,
+    /// Target of a symbolic link to recreate instead of a file.
+    symlink: Option<String>,
+    /// Whether the file was truncated or omitted for its size; such entries
+    /// are never restored.
+    incomplete: bool,
+    /// Whether secrets were redacted from the content; such entries differ
+    /// from the original file and their checksum is not verified.
+    redacted: bool,
+}
+
+/// Options for restoring files from a bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Apply the Unix permission bits recorded in the bundle to restored
+    /// files (on by default on Unix). Has no effect on other platforms.
+    /// Setuid, setgid and sticky bits are never restored.
+    pub restore_permissions: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            restore_permissions: cfg!(unix),
+        }
+    }
+}
+
+/// Outcome of restoring a single bundle.
+#[derive(Debug, Default)]
+struct ExtractSummary {
+    extracted: usize,
+    /// Paths of restored files whose content does not match the recorded checksum.
+    mismatched: Vec<String>,
+    /// Paths of files that were not restored because the bundle holds only
+    /// part of them, or none of their content.
+    incomplete: Vec<String>,
+    /// Paths of restored files whose secrets were redacted when bundling.
+    redacted: Vec<String>,
+}
+
+/// Extracts files from a bundle generated by src2md.
 /// Optionally takes a target root path to relocate the extracted files.
+///
+/// Markdown, JSON and JSON Lines bundles are all accepted; the format is
+/// detected from the file content.
+///
+/// Files that carry a checksum (format v2) are verified after restoring. A
+/// text file that was corrupted or edited after bundling is still restored,
+/// but reported with a warning. Embedded binary files that do not match
+/// their checksum fail the restore before anything is written.
+///
+/// Files that were truncated or left out because they exceeded the
+/// [`FileLimits`](crate::writer::FileLimits) are not restored; each of them
+/// is reported with a warning.
+///
+/// Files whose secrets were redacted are restored with the
+/// `[REDACTED:kind]` placeholders in place; their checksum is not verified
+/// and each of them is reported with a warning.
+///
+/// Uses the default [`RestoreOptions`]; see
+/// [`extract_from_markdown_with_options`].
 pub async fn extract_from_markdown(
     md_path: &PathBuf,
     extract_root: Option<&PathBuf>,
 ) -> Result<()> {
+    extract_from_markdown_with_options(md_path, extract_root, &RestoreOptions::default()).await
+}
+
+/// Like [`extract_from_markdown`], with explicit restore options.
+pub async fn extract_from_markdown_with_options(
+    md_path: &PathBuf,
+    extract_root: Option<&PathBuf>,
+    options: &RestoreOptions,
+) -> Result<()> {
+    let summary = extract_bundle(md_path, extract_root, options).await?;
+
+    if !summary.mismatched.is_empty() {
+        warn!(
+            "{} restored file(s) from {} do not match their checksums",
+            summary.mismatched.len(),
+            md_path.display()
+        );
+    }
+    if !summary.incomplete.is_empty() {
+        warn!(
+            "{} file(s) in {} were truncated or omitted for their size and were NOT restored",
+            summary.incomplete.len(),
+            md_path.display()
+        );
+    }
+    if !summary.redacted.is_empty() {
+        warn!(
+            "{} file(s) from {} had secrets redacted and differ from the originals",
+            summary.redacted.len(),
+            md_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Restores every entry of a bundle and verifies recorded checksums.
+async fn extract_bundle(
+    md_path: &PathBuf,
+    extract_root: Option<&PathBuf>,
+    options: &RestoreOptions,
+) -> Result<ExtractSummary> {
     let file = StdFile::open(md_path)
         .with_context(|| format!("Failed to open markdown file: {}", md_path.display()))?;
 
@@ -39,14 +159,325 @@ pub async fn extract_from_markdown(
     let content = str::from_utf8(&mmap)
         .with_context(|| format!("Markdown file is not valid UTF-8: {}", md_path.display()))?;
 
-    let headers = find_top_level_headers(content);
-    let mut extracted_count = 0;
+    if is_xml_bundle(content) {
+        anyhow::bail!(
+            "XML bundles cannot be restored; use the markdown or json format: {}",
+            md_path.display()
+        );
+    }
 
-    for (idx, header) in headers.iter().enumerate() {
-        let file_path_str = header.path.as_str();
+    let entries = if content.as_bytes().starts_with(JSON_MAGIC_BYTES) {
+        parse_json_bundle(content)
+            .with_context(|| format!("Invalid JSON bundle: {}", md_path.display()))?
+    } else {
+        check_manifest(content)?;
+        parse_markdown_bundle(content)
+            .with_context(|| format!("Invalid markdown bundle: {}", md_path.display()))?
+    };
+
+    let mut summary = ExtractSummary::default();
+
+    // Links are created last, so no file is ever written through one
+    let (links, files): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|entry| entry.symlink.is_some());
+
+    for entry in files {
+        let file_path_str = entry.path.as_str();
         let out_path = build_output_path(file_path_str, extract_root)
             .with_context(|| format!("Invalid restore path in header: {file_path_str:?}"))?;
 
+        if entry.incomplete {
+            warn!(
+                "Not restoring {}: the bundle holds only part of the file, it was truncated or omitted for its size",
+                entry.path
+            );
+            summary.incomplete.push(entry.path);
+            continue;
+        }
+
+        let Some(code) = entry.content else {
+            debug!("Skipping binary file: {}", out_path.display());
+            continue;
+        };
+
+        debug!("Extracting: {} ({} bytes)", out_path.display(), code.len());
+
+        if entry.redacted {
+            warn!(
+                "Restoring {} with redacted secrets: it differs from the original file",
+                entry.path
+            );
+            summary.redacted.push(entry.path.clone());
+        } else if let Some(expected) = &entry.sha256 {
+            if !sha256_hex(&code).eq_ignore_ascii_case(expected) {
+                warn!(
+                    "Checksum mismatch for {}: the file was corrupted or edited after bundling",
+                    entry.path
+                );
+                summary.mismatched.push(entry.path.clone());
+            }
+        }
+
+        check_no_symlinks(&out_path, extract_root).await?;
+        if let Ok(metadata) = tokio_fs::symlink_metadata(&out_path).await
+            && metadata.is_symlink()
+        {
+            // Replace the link instead of writing through it
+            tokio_fs::remove_file(&out_path)
+                .await
+                .with_context(|| format!("Failed to replace {}", out_path.display()))?;
+        }
+        if let Some(parent) = out_path.parent() {
+            tokio_fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+
+        make_writable(&out_path).await?;
+        let mut file = tokio_fs::File::create(&out_path)
+            .await
+            .with_context(|| format!("Failed to create file: {}", out_path.display()))?;
+
+        file.write_all(&code)
+            .await
+            .with_context(|| format!("Failed to write content to: {}", out_path.display()))?;
+
+        // Ensure data is flushed to disk before returning
+        file.sync_all()
+            .await
+            .with_context(|| format!("Failed to sync file: {}", out_path.display()))?;
+
+        if let Some(mode) = entry.mode.filter(|_| options.restore_permissions) {
+            apply_mode(&out_path, mode).await?;
+        }
+
+        summary.extracted += 1;
+    }
+
+    for entry in links {
+        let target = entry.symlink.as_deref().unwrap_or_default();
+        let out_path = build_output_path(&entry.path, extract_root)
+            .with_context(|| format!("Invalid restore path in header: {:?}", entry.path))?;
+        validate_link_target(&entry.path, target)
+            .with_context(|| format!("Invalid link target for {}: {target:?}", entry.path))?;
+        check_no_symlinks(&out_path, extract_root).await?;
+
+        debug!("Linking: {} -> {target}", out_path.display());
+        if create_symlink(target, &out_path).await? {
+            summary.extracted += 1;
+        }
+    }
+
+    debug!("Extracted {} files", summary.extracted);
+    Ok(summary)
+}
+
+/// Refuses to restore `out_path` if one of its existing parent directories
+/// below `extract_root` is a symbolic link, which could redirect the write
+/// outside the restore root.
+///
+/// The directories are checked on disk, so links restored from an earlier
+/// part or bundle, or created by anyone else, are caught as well.
+async fn check_no_symlinks(out_path: &Path, extract_root: Option<&PathBuf>) -> Result<()> {
+    let root = extract_root.map_or(Path::new(""), PathBuf::as_path);
+    let Some(parent) = out_path.strip_prefix(root).ok().and_then(Path::parent) else {
+        return Ok(());
+    };
+
+    let mut dir = root.to_path_buf();
+    for component in parent.components() {
+        dir.push(component);
+        match tokio_fs::symlink_metadata(&dir).await {
+            Ok(metadata) if metadata.is_symlink() => anyhow::bail!(
+                "Refusing to restore {}: {} is a symbolic link",
+                out_path.display(),
+                dir.display()
+            ),
+            Ok(_) => {}
+            // Nothing below a missing directory exists either
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a link target is relative and resolves inside the restore
+/// root, like [`validate_relative_restore_path`] does for paths.
+///
+/// `..` is only allowed at the start of the target, so a target can never
+/// climb back out through another link.
+fn validate_link_target(link_path: &str, target: &str) -> Result<()> {
+    let target = Path::new(target);
+    let mut seen_name = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => seen_name = true,
+            Component::ParentDir if seen_name => {
+                anyhow::bail!("'..' after a directory name is not allowed")
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("absolute link target is not allowed")
+            }
+            Component::ParentDir | Component::CurDir => {}
+        }
+    }
+
+    let raw_path = Path::new(link_path);
+    let rel_path = raw_path.strip_prefix("/").unwrap_or(raw_path);
+    let parent = rel_path.parent().unwrap_or(Path::new(""));
+    validate_relative_restore_path(&parent.join(target))
+}
+
+/// Creates a symbolic link at `path`, replacing an existing file or link.
+///
+/// Returns false if links are not supported on this platform.
+#[cfg(unix)]
+async fn create_symlink(target: &str, path: &Path) -> Result<bool> {
+    if let Ok(metadata) = tokio_fs::symlink_metadata(path).await {
+        if metadata.is_dir() {
+            anyhow::bail!(
+                "Cannot create symbolic link {}: a directory exists at that path",
+                path.display()
+            );
+        }
+        tokio_fs::remove_file(path)
+            .await
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+    }
+    if let Some(parent) = path.parent() {
+        tokio_fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    tokio_fs::symlink(target, path)
+        .await
+        .with_context(|| format!("Failed to create symbolic link: {}", path.display()))?;
+    Ok(true)
+}
+
+/// Creates a symbolic link at `path`, replacing an existing file or link.
+///
+/// Returns false if links are not supported on this platform.
+#[cfg(not(unix))]
+async fn create_symlink(target: &str, path: &Path) -> Result<bool> {
+    warn!(
+        "Skipping symbolic link {} -> {target}: not supported on this platform",
+        path.display()
+    );
+    Ok(false)
+}
+
+/// Logs the manifest of a Markdown bundle, if it has one, and rejects diff
+/// bundles.
+fn check_manifest(content: &str) -> Result<()> {
+    let Some(line) = content.lines().nth(1) else {
+        return Ok(());
+    };
+    if let Some(manifest) = Manifest::from_comment(line) {
+        let manifest = manifest?;
+        if let Some(range) = &manifest.diff {
+            anyhow::bail!("Diff bundles cannot be restored (changes {range})");
+        }
+        info!(
+            "Bundle of {} file(s) from {} generated by src2md {} at {}{}",
+            manifest.files,
+            manifest.root,
+            manifest.version,
+            manifest.generated,
+            manifest
+                .commit
+                .map(|commit| format!(" (commit {commit})"))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Applies recorded permission bits to a restored file.
+#[cfg(unix)]
+async fn apply_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let permissions = std::fs::Permissions::from_mode(mode & 0o777);
+    tokio_fs::set_permissions(path, permissions)
+        .await
+        .with_context(|| format!("Failed to set permissions of {}", path.display()))
+}
+
+/// Applies recorded permission bits to a restored file.
+#[cfg(not(unix))]
+async fn apply_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Gives the owner write permission on an existing file about to be
+/// overwritten, such as a read-only file from an earlier restore.
+#[cfg(unix)]
+async fn make_writable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Ok(metadata) = tokio_fs::metadata(path).await else {
+        return Ok(());
+    };
+    let mode = metadata.permissions().mode();
+    if metadata.is_file() && mode & 0o200 == 0 {
+        let permissions = std::fs::Permissions::from_mode(mode | 0o200);
+        tokio_fs::set_permissions(path, permissions)
+            .await
+            .with_context(|| format!("Failed to make {} writable", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Gives the owner write permission on an existing file about to be
+/// overwritten.
+#[cfg(not(unix))]
+async fn make_writable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Extracts files from every part of a split bundle, in order.
+///
+/// Parts are independent bundles, so this is equivalent to calling
+/// [`extract_from_markdown`] for each of them.
+pub async fn extract_bundle_parts(parts: &[PathBuf], extract_root: Option<&PathBuf>) -> Result<()> {
+    extract_bundle_parts_with_options(parts, extract_root, &RestoreOptions::default()).await
+}
+
+/// Like [`extract_bundle_parts`], with explicit restore options.
+pub async fn extract_bundle_parts_with_options(
+    parts: &[PathBuf],
+    extract_root: Option<&PathBuf>,
+    options: &RestoreOptions,
+) -> Result<()> {
+    for part in parts {
+        debug!("Restoring part: {}", part.display());
+        extract_from_markdown_with_options(part, extract_root, options).await?;
+    }
+    Ok(())
+}
+
+/// Checks whether the content is an XML-tagged bundle.
+fn is_xml_bundle(content: &str) -> bool {
+    let Some((header, rest)) = content.split_once('\n') else {
+        return false;
+    };
+    let header = header.as_bytes();
+    (header == OUTPUT_MAGIC_BYTES || header == OUTPUT_MAGIC_BYTES_V1)
+        && rest.starts_with("<documents")
+}
+
+/// Parses the `## path` sections of a Markdown bundle.
+///
+/// A section holds a fenced code block, a `base64` block with an embedded
+/// binary file, or the placeholder for an omitted binary file. In format v2
+/// it starts with a [`FileMeta`] comment.
+fn parse_markdown_bundle(content: &str) -> Result<Vec<RestoredEntry>> {
+    let headers = find_top_level_headers(content);
+    let mut entries = Vec::with_capacity(headers.len());
+
+    for (idx, header) in headers.iter().enumerate() {
         let start = header.block_start;
         let end = headers
             .get(idx + 1)
@@ -54,42 +485,240 @@ pub async fn extract_from_markdown(
             .unwrap_or(content.len());
 
         let block = &content[start..end];
-        if block.contains("(binary file omitted)") {
-            debug!("Skipping binary file: {}", out_path.display());
+        let mut lines = block
+            .lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .filter(|line| !line.trim().is_empty());
+        let mut first_line = lines.next().unwrap_or("");
+
+        let meta = match FileMeta::from_comment(first_line) {
+            Some(meta) => {
+                first_line = lines.next().unwrap_or("");
+                meta.with_context(|| format!("Invalid metadata for {}", header.path))?
+            }
+            None => FileMeta::default(),
+        };
+        if let Some(target) = meta.symlink {
+            entries.push(RestoredEntry {
+                path: header.path.clone(),
+                content: None,
+                sha256: None,
+                mode: None,
+                symlink: Some(target),
+                incomplete: false,
+                redacted: false,
+            });
+            continue;
+        }
+        if meta.truncated || meta.omitted || first_line.starts_with(OVERSIZED_OMITTED_PREFIX) {
+            entries.push(RestoredEntry {
+                path: header.path.clone(),
+                content: None,
+                sha256: None,
+                mode: None,
+                symlink: None,
+                incomplete: true,
+                redacted: false,
+            });
             continue;
         }
 
-        // Find and extract code block with proper fence matching
-        if let Some(code) = extract_fenced_code(block) {
-            debug!("Extracting: {} ({} bytes)", out_path.display(), code.len());
+        let layout = meta.layout();
+        let mode = meta.mode;
+        let redacted = meta.redacted;
+        let mut sha256 = meta.sha256;
+
+        let code = if first_line == BINARY_OMITTED_MARKER {
+            None
+        } else if let Some(fence_len) = parse_base64_fence(first_line) {
+            let (data, info_sha256) = decode_base64_block(block, fence_len)
+                .and_then(|(data, info_sha256)| {
+                    verify_embedded(&data, info_sha256.as_deref().or(sha256.as_deref()))?;
+                    Ok((data, info_sha256))
+                })
+                .with_context(|| format!("Invalid embedded file: {}", header.path))?;
+            sha256 = sha256.or(info_sha256);
+            Some(data)
+        } else {
+            // Find and extract code block with proper fence matching
+            let Some(code) = extract_fenced_code(block) else {
+                continue;
+            };
+            let code = match layout {
+                Some(layout) => layout.restore(&code),
+                None => code,
+            };
+            Some(
+                encode_restored(code, meta.charset.as_deref())
+                    .with_context(|| format!("Invalid text content: {}", header.path))?,
+            )
+        };
+
+        entries.push(RestoredEntry {
+            path: header.path.clone(),
+            content: code,
+            sha256,
+            mode,
+            symlink: None,
+            incomplete: false,
+            redacted,
+        });
+    }
 
-            if let Some(parent) = out_path.parent() {
-                tokio_fs::create_dir_all(parent)
-                    .await
-                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
-            }
+    Ok(entries)
+}
 
-            let mut file = tokio_fs::File::create(&out_path)
-                .await
-                .with_context(|| format!("Failed to create file: {}", out_path.display()))?;
+/// Returns the fence length if `line` opens a `base64` block.
+fn parse_base64_fence(line: &str) -> Option<usize> {
+    let fence_len = parse_fence_len(line)?;
+    let mut words = line[fence_len..].split_whitespace();
+    (words.next() == Some(BASE64_INFO_STRING)).then_some(fence_len)
+}
 
-            file.write_all(code.as_bytes())
-                .await
-                .with_context(|| format!("Failed to write content to: {}", out_path.display()))?;
+/// Decodes a `base64` block and checks it against the `size` attribute of
+/// its info string.
+///
+/// Returns the data and the `sha256` attribute, which the caller checks with
+/// [`verify_embedded`].
+fn decode_base64_block(block: &str, fence_len: usize) -> Result<(Vec<u8>, Option<String>)> {
+    let mut lines = block
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .skip_while(|line| !line.starts_with('`'));
+
+    let info = lines.next().unwrap_or("");
+    let mut encoded = String::new();
+    let mut closed = false;
+    for line in lines {
+        if is_closing_fence(line, fence_len) {
+            closed = true;
+            break;
+        }
+        encoded.push_str(line);
+    }
+    if !closed {
+        anyhow::bail!("unterminated base64 block");
+    }
 
-            // Ensure data is flushed to disk before returning
-            file.sync_all()
-                .await
-                .with_context(|| format!("Failed to sync file: {}", out_path.display()))?;
+    let data = decode_base64(&encoded).context("invalid base64 data")?;
 
-            extracted_count += 1;
+    let mut sha256 = None;
+    for attr in info[fence_len..].split_whitespace().skip(1) {
+        match attr.split_once('=') {
+            Some(("size", size)) => {
+                let size: usize = size.parse().context("invalid size attribute")?;
+                if data.len() != size {
+                    anyhow::bail!("size mismatch: expected {size} bytes, got {}", data.len());
+                }
+            }
+            Some(("sha256", expected)) => sha256 = Some(expected.to_string()),
+            _ => {}
         }
     }
 
-    debug!("Extracted {} files", extracted_count);
+    Ok((data, sha256))
+}
+
+/// Checks embedded binary data against its recorded checksum.
+///
+/// Unlike text, which may have been edited by hand, base64 data that does
+/// not match its checksum is corrupt, so the bundle is rejected.
+fn verify_embedded(data: &[u8], expected: Option<&str>) -> Result<()> {
+    if let Some(expected) = expected {
+        let actual = sha256_hex(data);
+        if !actual.eq_ignore_ascii_case(expected) {
+            anyhow::bail!("checksum mismatch: expected sha256 {expected}, got {actual}");
+        }
+    }
     Ok(())
 }
 
+/// Parses a JSON or JSON Lines bundle.
+///
+/// A JSON Lines bundle starts with a complete `{"src2md":"v1"}` header line;
+/// otherwise the content is read as a single JSON document.
+fn parse_json_bundle(content: &str) -> Result<Vec<RestoredEntry>> {
+    #[derive(Deserialize)]
+    struct JsonBundle<'a> {
+        #[serde(borrow)]
+        files: Vec<JsonEntry<'a>>,
+    }
+
+    let first_line = content.lines().next().unwrap_or("");
+    let is_json_lines = serde_json::from_str::<serde_json::Value>(first_line).is_ok();
+
+    let files: Vec<JsonEntry> = if is_json_lines {
+        content
+            .lines()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid entry on line {}", idx + 1))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        serde_json::from_str::<JsonBundle>(content)?.files
+    };
+
+    files
+        .into_iter()
+        .map(|file| {
+            if file.truncated || file.omitted {
+                return Ok(RestoredEntry {
+                    path: file.path.into_owned(),
+                    content: None,
+                    sha256: None,
+                    mode: None,
+                    symlink: None,
+                    incomplete: true,
+                    redacted: false,
+                });
+            }
+            let content = match (file.binary, file.encoding.as_deref(), file.content) {
+                (true, Some(BASE64_INFO_STRING), Some(encoded)) => Some(
+                    decode_base64(&encoded)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|data| {
+                            verify_embedded(&data, file.sha256.as_deref())?;
+                            Ok(data)
+                        })
+                        .with_context(|| format!("Invalid base64 content: {}", file.path))?,
+                ),
+                (true, _, _) => None,
+                (false, _, content) => Some(
+                    encode_restored(
+                        content.map(|c| c.into_owned()).unwrap_or_default(),
+                        file.charset.as_deref(),
+                    )
+                    .with_context(|| format!("Invalid text content: {}", file.path))?,
+                ),
+            };
+            Ok(RestoredEntry {
+                path: file.path.into_owned(),
+                content,
+                sha256: file.sha256.map(Cow::into_owned),
+                mode: file.mode,
+                symlink: file.symlink.map(Cow::into_owned),
+                incomplete: false,
+                redacted: file.redacted,
+            })
+        })
+        .collect()
+}
+
+/// Encodes restored text in its original encoding, if it was not UTF-8.
+fn encode_restored(text: String, charset: Option<&str>) -> Result<Vec<u8>> {
+    let Some(charset) = charset else {
+        return Ok(text.into_bytes());
+    };
+    let encoding = Encoding::for_label(charset.as_bytes())
+        .with_context(|| format!("unknown charset: {charset}"))?;
+    encode_text(&text, encoding)
+        .with_context(|| format!("content cannot be encoded as {}", encoding.name()))
+}
+
 fn build_output_path(file_path_str: &str, extract_root: Option<&PathBuf>) -> Result<PathBuf> {
     let raw_path = Path::new(file_path_str);
     let rel_path = raw_path.strip_prefix("/").unwrap_or(raw_path);
@@ -222,6 +851,8 @@ fn extract_fenced_code(block: &str) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::format::OutputFormat;
+    use crate::writer::OUTPUT_MAGIC_HEADER;
     use std::fs;
     use tempfile::tempdir;
 
@@ -335,6 +966,439 @@ fn main() {}
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_extract_text_mentioning_binary_marker() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        fs::write(
+            &md_path,
+            "## src/writer.rs\n\n```rust\nconst MARKER: &str = \"(binary file omitted)\";\n```\n",
+        )?;
+
+        extract_from_markdown(&md_path, Some(&extract_dir)).await?;
+
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("src/writer.rs"))?,
+            "const MARKER: &str = \"(binary file omitted)\";"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_embedded_binary() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let data: Vec<u8> = (0..=255).rev().collect();
+        let mut bundle = String::new();
+        crate::writer::MarkdownFormat.binary(
+            &crate::format::BinaryFile {
+                content: Some(&data),
+                ..crate::format::BinaryFile::new(Path::new("assets/data.bin"), data.len() as u64)
+            },
+            &mut bundle,
+        )?;
+        fs::write(&md_path, bundle)?;
+
+        extract_from_markdown(&md_path, Some(&extract_dir)).await?;
+
+        assert_eq!(fs::read(extract_dir.join("assets/data.bin"))?, data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_corrupted_binary() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        // "YWJk" decodes to "abd", but the checksum is that of "abc"
+        fs::write(
+            &md_path,
+            "## data.bin\n\n```base64 size=3 sha256=ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\nYWJk\n```\n",
+        )?;
+
+        let err = extract_from_markdown(&md_path, Some(&extract_dir))
+            .await
+            .expect_err("checksum mismatch must fail");
+
+        assert!(format!("{err:#}").contains("checksum mismatch"));
+        assert!(!extract_dir.join("data.bin").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_truncated_binary() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        fs::write(&md_path, "## data.bin\n\n```base64 size=4\nYWJj\n```\n")?;
+
+        let err = extract_from_markdown(&md_path, Some(&extract_dir))
+            .await
+            .expect_err("size mismatch must fail");
+
+        assert!(format!("{err:#}").contains("size mismatch"));
+        assert!(!extract_dir.join("data.bin").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_verifies_file_metadata() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let manifest = crate::manifest::Manifest::new(temp_dir.path(), 2);
+        let mut bundle = String::new();
+        let mut format = crate::writer::MarkdownFormat;
+        format.begin(Some(&manifest), &mut bundle)?;
+        for (path, content) in [("ok.txt", "intact\n"), ("edited.txt", "original\n")] {
+            let sha256 = sha256_hex(content.as_bytes());
+            format.file(
+                &crate::format::TextFile {
+                    sha256: Some(&sha256),
+                    ..crate::format::TextFile::new(Path::new(path), "text", content)
+                },
+                &mut bundle,
+            )?;
+        }
+        fs::write(&md_path, bundle.replace("original", "modified"))?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert_eq!(summary.extracted, 2);
+        assert_eq!(summary.mismatched, ["edited.txt"]);
+        // Metadata comments are not part of the restored content
+        assert_eq!(fs::read_to_string(extract_dir.join("ok.txt"))?, "intact\n");
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("edited.txt"))?,
+            "modified\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_skips_incomplete_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let sha256 = sha256_hex(b"1\n2\n3\n");
+        let mut bundle = String::new();
+        let mut format = crate::writer::MarkdownFormat;
+        format.file(
+            &crate::format::TextFile {
+                sha256: Some(&sha256),
+                truncated: true,
+                ..crate::format::TextFile::new(
+                    Path::new("big.log"),
+                    "",
+                    "1\n[... truncated by src2md: 1 lines (2 bytes) omitted ...]\n3\n",
+                )
+            },
+            &mut bundle,
+        )?;
+        format.oversized(
+            &crate::format::OversizedFile {
+                path: Path::new("dump.sql"),
+                size: 4096,
+                lines: None,
+            },
+            &mut bundle,
+        )?;
+        format.file(
+            &crate::format::TextFile::new(Path::new("ok.txt"), "", "ok\n"),
+            &mut bundle,
+        )?;
+        fs::write(&md_path, bundle)?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert_eq!(summary.extracted, 1);
+        assert_eq!(summary.incomplete, ["big.log", "dump.sql"]);
+        assert!(!extract_dir.join("big.log").exists());
+        assert!(!extract_dir.join("dump.sql").exists());
+        assert!(extract_dir.join("ok.txt").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_json_skips_incomplete_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.jsonl");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        fs::write(
+            &md_path,
+            concat!(
+                "{\"src2md\":\"v1\"}\n",
+                "{\"path\":\"big.log\",\"size\":9,\"lines\":3,\"binary\":false,\"content\":\"1\\n\",\"truncated\":true}\n",
+                "{\"path\":\"dump.sql\",\"size\":4096,\"lines\":0,\"binary\":false,\"omitted\":true}\n",
+            ),
+        )?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert_eq!(summary.extracted, 0);
+        assert_eq!(summary.incomplete, ["big.log", "dump.sql"]);
+        assert!(!extract_dir.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_restores_redacted_files_without_checksum() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        // The checksum is that of the original file, with the secret
+        let sha256 = sha256_hex(b"password = \"hunter22\"\n");
+        let mut bundle = String::new();
+        let mut format = crate::writer::MarkdownFormat;
+        format.file(
+            &crate::format::TextFile {
+                sha256: Some(&sha256),
+                redacted: true,
+                ..crate::format::TextFile::new(
+                    Path::new("config.py"),
+                    "python",
+                    "password = \"[REDACTED:secret]\"\n",
+                )
+            },
+            &mut bundle,
+        )?;
+        fs::write(&md_path, bundle)?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert_eq!(summary.extracted, 1);
+        assert!(summary.mismatched.is_empty());
+        assert_eq!(summary.redacted, ["config.py"]);
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("config.py"))?,
+            "password = \"[REDACTED:secret]\"\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_reproduces_text_layout() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let files = [
+            ("no_newline.txt", "last line"),
+            ("crlf.txt", "one\r\ntwo\r\n"),
+            ("bom.txt", "\u{feff}bom\n"),
+            ("mixed.txt", "lf\ncrlf\r\n"),
+            ("blank.txt", "trailing\n\n"),
+            ("empty.txt", ""),
+        ];
+        let mut bundle = String::new();
+        for (path, content) in files {
+            let sha256 = sha256_hex(content.as_bytes());
+            crate::writer::MarkdownFormat.file(
+                &crate::format::TextFile {
+                    sha256: Some(&sha256),
+                    ..crate::format::TextFile::new(Path::new(path), "text", content)
+                },
+                &mut bundle,
+            )?;
+        }
+        assert!(bundle.contains("```text\none\ntwo\n```"));
+        assert!(!bundle.contains('\u{feff}'));
+        fs::write(&md_path, bundle)?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert!(summary.mismatched.is_empty());
+        for (path, content) in files {
+            assert_eq!(
+                fs::read(extract_dir.join(path))?,
+                content.as_bytes(),
+                "{path}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_restores_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        fs::write(
+            &md_path,
+            "## run.sh\n<!-- src2md:file {\"size\":10,\"mode\":\"4755\"} -->\n\n```sh\necho hi\n```\n\n",
+        )?;
+
+        let restored = temp_dir.path().join("restored");
+        extract_from_markdown(&md_path, Some(&restored)).await?;
+        let mode = fs::metadata(restored.join("run.sh"))?.permissions().mode();
+        // The setuid bit is dropped
+        assert_eq!(mode & 0o7777, 0o755);
+
+        let plain = temp_dir.path().join("plain");
+        let options = RestoreOptions {
+            restore_permissions: false,
+        };
+        extract_from_markdown_with_options(&md_path, Some(&plain), &options).await?;
+        let mode = fs::metadata(plain.join("run.sh"))?.permissions().mode();
+        assert_eq!(mode & 0o111, 0);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_overwrites_read_only_files() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        fs::write(
+            &md_path,
+            "## VERSION\n<!-- src2md:file {\"size\":3,\"mode\":\"0444\"} -->\n\n```text\n1.0\n```\n\n",
+        )?;
+
+        let restored = temp_dir.path().join("restored");
+        extract_from_markdown(&md_path, Some(&restored)).await?;
+        extract_from_markdown(&md_path, Some(&restored)).await?;
+
+        let path = restored.join("VERSION");
+        assert_eq!(fs::read_to_string(&path)?, "1.0");
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o444);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_restores_symlinks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        fs::write(
+            &md_path,
+            "## docs/latest.md\n<!-- src2md:file {\"size\":12,\"symlink\":\"../NOTES.md\"} -->\n\n\
+             (symbolic link to ../NOTES.md)\n\n\
+             ## NOTES.md\n\n```markdown\nnotes\n```\n\n",
+        )?;
+
+        let restored = temp_dir.path().join("restored");
+        extract_from_markdown(&md_path, Some(&restored)).await?;
+        let link = restored.join("docs/latest.md");
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(fs::read_link(&link)?, PathBuf::from("../NOTES.md"));
+        assert_eq!(fs::read_to_string(&link)?, "notes");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_escaping_symlinks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        for target in ["../../outside", "/etc/passwd", "a/../../outside"] {
+            let md_path = temp_dir.path().join("test.md");
+            fs::write(
+                &md_path,
+                format!(
+                    "## dir/link\n<!-- src2md:file {{\"size\":1,\"symlink\":{target:?}}} -->\n\n"
+                ),
+            )?;
+
+            let restored = temp_dir.path().join("restored");
+            let err = extract_from_markdown(&md_path, Some(&restored))
+                .await
+                .expect_err("escaping link target must fail");
+            assert!(err.to_string().contains("Invalid link target"), "{target}");
+            assert!(fs::symlink_metadata(restored.join("dir/link")).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_refuses_to_write_through_symlinks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let restored = temp_dir.path().join("restored");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&restored)?;
+        fs::create_dir_all(&outside)?;
+
+        // A link restored by an earlier part points back at the root
+        let part1 = temp_dir.path().join("part1.md");
+        fs::write(
+            &part1,
+            "## dir/up\n<!-- src2md:file {\"size\":2,\"symlink\":\"..\"} -->\n\n",
+        )?;
+        let part2 = temp_dir.path().join("part2.md");
+        fs::write(
+            &part2,
+            "## dir/up/../../outside/a.txt\n\n```text\nx\n```\n\n",
+        )?;
+        let err = extract_bundle_parts(&[part1, part2], Some(&restored))
+            .await
+            .expect_err("writing through a restored link must fail");
+        assert!(format!("{err:#}").contains("is a symbolic link"));
+        assert!(!outside.join("a.txt").exists());
+
+        // A link that already exists on disk
+        std::os::unix::fs::symlink(&outside, restored.join("evil"))?;
+        let md_path = temp_dir.path().join("test.md");
+        fs::write(&md_path, "## evil/b.txt\n\n```text\nx\n```\n\n")?;
+        let err = extract_from_markdown(&md_path, Some(&restored))
+            .await
+            .expect_err("writing through an existing link must fail");
+        assert!(format!("{err:#}").contains("is a symbolic link"));
+        assert!(!outside.join("b.txt").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_v1_bundle_without_metadata() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        fs::write(
+            &md_path,
+            "<!-- src2md:v1 -->\n\n## a.txt\n\n```text\nhello\n```\n\n",
+        )?;
+
+        let summary =
+            extract_bundle(&md_path, Some(&extract_dir), &RestoreOptions::default()).await?;
+
+        assert_eq!(summary.extracted, 1);
+        assert!(summary.mismatched.is_empty());
+        assert_eq!(fs::read_to_string(extract_dir.join("a.txt"))?, "hello");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_extract_with_extended_fences() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -440,9 +1504,93 @@ content
     }
 
     #[tokio::test]
-    async fn test_extract_with_magic_header() -> Result<()> {
-        use crate::writer::OUTPUT_MAGIC_HEADER;
+    async fn test_extract_ignores_preamble() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("test.md");
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let preamble = crate::preamble::Preamble {
+            paths: vec![PathBuf::from("src/main.rs")],
+            tree: true,
+            toc: true,
+            deleted: vec![PathBuf::from("src/old.rs")],
+        };
+        fs::write(
+            &md_path,
+            format!(
+                "{OUTPUT_MAGIC_HEADER}\n{}## src/main.rs\n\n```rust\nfn main() {{}}\n```\n\n",
+                preamble.to_markdown()
+            ),
+        )?;
+
+        extract_from_markdown(&md_path, Some(&extract_dir)).await?;
+
+        let entries: Vec<_> = fs::read_dir(&extract_dir)?.collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("src/main.rs"))?,
+            "fn main() {}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_xml_bundle() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("bundle.xml");
+
+        fs::write(
+            &md_path,
+            format!("{OUTPUT_MAGIC_HEADER}<documents>\n</documents>\n"),
+        )?;
+
+        let err = extract_from_markdown(&md_path, Some(&temp_dir.path().join("out")))
+            .await
+            .expect_err("XML bundles are not restorable");
+        assert!(err.to_string().contains("XML bundles cannot be restored"));
+
+        fs::write(
+            &md_path,
+            format!("{OUTPUT_MAGIC_HEADER}<documents version=\"1.0\" files=\"0\">\n</documents>\n"),
+        )?;
+        assert!(
+            extract_from_markdown(&md_path, Some(&temp_dir.path().join("out")))
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_diff_bundle() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let md_path = temp_dir.path().join("changes.md");
+        let manifest = Manifest {
+            diff: Some("main...HEAD".to_string()),
+            ..Manifest::new(temp_dir.path(), 1)
+        };
+        fs::write(
+            &md_path,
+            format!(
+                "{OUTPUT_MAGIC_HEADER}{}\n\n## src/main.rs\n\n```diff\n+fn main() {{}}\n```\n\n",
+                manifest.to_comment()?
+            ),
+        )?;
 
+        let extract_dir = temp_dir.path().join("out");
+        let err = extract_from_markdown(&md_path, Some(&extract_dir))
+            .await
+            .expect_err("diff bundles are not restorable");
+        assert!(err.to_string().contains("Diff bundles cannot be restored"));
+        assert!(!extract_dir.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_magic_header() -> Result<()> {
         let temp_dir = tempdir()?;
         let md_path = temp_dir.path().join("test.md");
         let extract_dir = temp_dir.path().join("extracted");
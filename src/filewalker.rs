@@ -1,16 +1,26 @@
-use crate::writer::OUTPUT_MAGIC_BYTES;
-use anyhow::Result;
+use crate::explain::Exclusion;
+use crate::json::JSON_MAGIC_BYTES;
+use crate::writer::{OUTPUT_MAGIC_BYTES, OUTPUT_MAGIC_BYTES_V1};
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+#[cfg(feature = "git")]
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::OverrideBuilder;
 use ignore::{DirEntry, WalkBuilder};
-use log::{debug, trace, warn};
+use log::{debug, info, trace, warn};
 use memmap2::MmapOptions;
+use std::cmp::{Ordering, Reverse};
 use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 /// Lock file patterns that are excluded by default.
 /// These files are typically auto-generated and not useful to include in documentation.
-const LOCK_FILE_NAMES: &[&str] = &[
+/// Set [`CollectOptions::include_lockfiles`] to collect them.
+pub const LOCK_FILE_NAMES: &[&str] = &[
     "package-lock.json",
     "yarn.lock",
     "pnpm-lock.yaml",
@@ -28,7 +38,934 @@ const LOCK_FILE_NAMES: &[&str] = &[
 ];
 
 /// File extensions that indicate lock files.
-const LOCK_FILE_EXTENSIONS: &[&str] = &["lock", "lockb"];
+pub const LOCK_FILE_EXTENSIONS: &[&str] = &["lock", "lockb"];
+
+/// Names of files that commonly hold private keys or credentials, which are
+/// excluded by default so they never end up in a bundle by accident.
+/// Set [`CollectOptions::allow_sensitive`] to collect them.
+pub const SENSITIVE_FILE_NAMES: &[&str] = &[
+    "id_rsa",
+    "id_dsa",
+    "id_ecdsa",
+    "id_ed25519",
+    "credentials.json",
+    "client_secret.json",
+    "service-account.json",
+    "secrets.json",
+    "secrets.yaml",
+    "secrets.yml",
+    "secrets.toml",
+];
+
+/// File extensions of private keys, certificate stores and password
+/// databases, treated like [`SENSITIVE_FILE_NAMES`].
+pub const SENSITIVE_FILE_EXTENSIONS: &[&str] =
+    &["pem", "key", "p12", "pfx", "keystore", "jks", "kdbx"];
+
+/// How symbolic links are handled during collection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Leave symbolic links out.
+    Skip,
+    /// Follow links to files and directories inside the project root and
+    /// bundle their content; links pointing outside the root are skipped.
+    #[default]
+    FollowWithinRoot,
+    /// Collect links without following them, so that bundles record the link
+    /// target and restore recreates the link.
+    Record,
+}
+
+impl SymlinkPolicy {
+    /// Names accepted by [`from_name`](Self::from_name).
+    pub const NAMES: &'static [&'static str] = &["skip", "follow", "record"];
+
+    /// Parses a policy name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "skip" => Some(Self::Skip),
+            "follow" => Some(Self::FollowWithinRoot),
+            "record" => Some(Self::Record),
+            _ => None,
+        }
+    }
+}
+
+/// Order of the collected files, and so of the files in a bundle.
+///
+/// Every order is deterministic: ties are broken by path, so the same tree
+/// always yields the same bundle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileOrder {
+    /// By path relative to the project root.
+    #[default]
+    Path,
+    /// Largest files first.
+    Size,
+    /// Most recently modified files first. Modification times usually
+    /// differ between clones, so bundles are only reproducible within one
+    /// checkout.
+    Mtime,
+    /// READMEs first, then package manifests such as `Cargo.toml` or
+    /// `package.json` (see [`MANIFEST_FILE_NAMES`]), then entry points such
+    /// as `main.rs` or `index.ts` (see [`ENTRY_POINT_NAMES`]), each from the
+    /// top of the tree down, followed by all other files by path.
+    Priority,
+}
+
+impl FileOrder {
+    /// Names accepted by [`from_name`](Self::from_name).
+    pub const NAMES: &'static [&'static str] = &["path", "size", "mtime", "priority"];
+
+    /// Parses an order name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "path" => Some(Self::Path),
+            "size" => Some(Self::Size),
+            "mtime" => Some(Self::Mtime),
+            "priority" => Some(Self::Priority),
+            _ => None,
+        }
+    }
+
+    /// Returns the sort key of a file under this order, ahead of its path.
+    fn key(self, entry: &impl CollectedFile, rel_path: &Path) -> SortKey {
+        match self {
+            Self::Path => SortKey::None,
+            Self::Size => SortKey::Largest(Reverse(entry.size())),
+            Self::Mtime => SortKey::Newest(Reverse(entry.modified())),
+            Self::Priority => {
+                let rank = priority_rank(rel_path);
+                // Only ranked files move up, so the rest keep their tree order
+                let depth = if rank < PRIORITY_RANK_OTHER {
+                    rel_path.components().count()
+                } else {
+                    0
+                };
+                SortKey::Priority(rank, depth)
+            }
+        }
+    }
+}
+
+/// Sort key of a file under a [`FileOrder`]; all files of one sort share
+/// the same variant.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    None,
+    Largest(Reverse<u64>),
+    Newest(Reverse<SystemTime>),
+    Priority(u8, usize),
+}
+
+/// Package manifests and build files moved up by [`FileOrder::Priority`].
+pub const MANIFEST_FILE_NAMES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "requirements.txt",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "mix.exs",
+    "pubspec.yaml",
+    "Package.swift",
+    "CMakeLists.txt",
+    "Makefile",
+    "Dockerfile",
+];
+
+/// Entry points moved up by [`FileOrder::Priority`], after the manifests.
+pub const ENTRY_POINT_NAMES: &[&str] = &[
+    "main.rs",
+    "lib.rs",
+    "main.go",
+    "main.py",
+    "__main__.py",
+    "app.py",
+    "index.js",
+    "index.ts",
+    "main.js",
+    "main.ts",
+    "main.c",
+    "main.cpp",
+    "Main.java",
+    "Program.cs",
+];
+
+/// Rank of files that [`FileOrder::Priority`] does not move up.
+const PRIORITY_RANK_OTHER: u8 = 3;
+
+/// Returns the [`FileOrder::Priority`] rank of a file: 0 for READMEs, 1 for
+/// manifests, 2 for entry points and [`PRIORITY_RANK_OTHER`] for the rest.
+fn priority_rank(path: &Path) -> u8 {
+    let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or("");
+    let is_readme = path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("readme"));
+    if is_readme {
+        0
+    } else if MANIFEST_FILE_NAMES.contains(&file_name) {
+        1
+    } else if ENTRY_POINT_NAMES.contains(&file_name) {
+        2
+    } else {
+        PRIORITY_RANK_OTHER
+    }
+}
+
+/// Compares two relative paths component by component. With
+/// `directories_first`, the contents of a directory come before the files
+/// next to it, like in a file manager.
+pub(crate) fn compare_paths(a: &Path, b: &Path, directories_first: bool) -> Ordering {
+    if !directories_first {
+        return a.cmp(b);
+    }
+    let (a_parts, b_parts): (Vec<_>, Vec<_>) = (a.components().collect(), b.components().collect());
+    for (idx, (a_part, b_part)) in a_parts.iter().zip(&b_parts).enumerate() {
+        if a_part == b_part {
+            continue;
+        }
+        let a_is_dir = idx + 1 < a_parts.len();
+        let b_is_dir = idx + 1 < b_parts.len();
+        return b_is_dir.cmp(&a_is_dir).then_with(|| a_part.cmp(b_part));
+    }
+    a_parts.len().cmp(&b_parts.len())
+}
+
+/// Sorts collected entries in the given order.
+pub(crate) fn sort_entries<E: CollectedFile>(
+    entries: Vec<E>,
+    project_root: &Path,
+    order: FileOrder,
+    directories_first: bool,
+) -> Vec<E> {
+    let mut keyed: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let rel_path = entry
+                .path()
+                .strip_prefix(project_root)
+                .unwrap_or(entry.path())
+                .to_path_buf();
+            (order.key(&entry, &rel_path), rel_path, entry)
+        })
+        .collect();
+    keyed.sort_by(|(a_key, a_path, _), (b_key, b_path, _)| {
+        a_key
+            .cmp(b_key)
+            .then_with(|| compare_paths(a_path, b_path, directories_first))
+    });
+    keyed.into_iter().map(|(_, _, entry)| entry).collect()
+}
+
+/// Hidden directories of version control systems, which are never collected.
+const VCS_DIRECTORIES: &[&str] = &[".git", ".hg", ".svn", ".jj"];
+
+/// Hidden files and directories that commonly hold credentials. They are
+/// left out by [`CollectOptions::hidden`] unless listed in
+/// [`CollectOptions::include_hidden`]; `.env.*` files are treated the same.
+const HIDDEN_SECRET_NAMES: &[&str] = &[
+    ".env",
+    ".envrc",
+    ".netrc",
+    ".npmrc",
+    ".pypirc",
+    ".pgpass",
+    ".git-credentials",
+    ".aws",
+    ".azure",
+    ".docker",
+    ".gnupg",
+    ".kube",
+    ".ssh",
+];
+
+/// Credential files inside hidden directories, treated like
+/// [`HIDDEN_SECRET_NAMES`].
+const HIDDEN_SECRET_PATHS: &[&str] = &[
+    ".cargo/credentials",
+    ".cargo/credentials.toml",
+    ".config/gh/hosts.yml",
+];
+
+/// Name of the per-directory ignore files read by src2md.
+pub const SRC2MD_IGNORE_FILENAME: &str = ".src2mdignore";
+
+/// Which ignore files are honored during collection.
+///
+/// Ignore files use `.gitignore` syntax and apply to the directory they are
+/// in and everything below it; ignore files in parent directories of the
+/// project root apply as well. A rule in a deeper directory wins over one
+/// higher up, and `.src2mdignore` rules win over `.gitignore` rules.
+/// Explicit ignore files ([`CollectOptions::ignore_files`]) are always
+/// honored, with the lowest precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IgnorePolicy {
+    /// Honor `.gitignore` files, `.git/info/exclude` and the global git
+    /// excludes file, also outside of git repositories.
+    pub gitignore: bool,
+    /// Honor [`SRC2MD_IGNORE_FILENAME`] files.
+    pub src2mdignore: bool,
+}
+
+impl IgnorePolicy {
+    /// Honors no ignore files except explicit ones.
+    pub const NONE: Self = Self {
+        gitignore: false,
+        src2mdignore: false,
+    };
+}
+
+impl Default for IgnorePolicy {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            src2mdignore: true,
+        }
+    }
+}
+
+/// A collected file: a [`DirEntry`] found on disk, or a [`VirtualEntry`]
+/// that is not read from the working tree, such as a file of a git revision.
+///
+/// [`BundleWriter`](crate::writer::BundleWriter) writes either kind.
+pub trait CollectedFile: Clone + Send + 'static {
+    /// Path of the file below the project root.
+    fn path(&self) -> &Path;
+    /// Size in bytes, or 0 if unknown.
+    fn size(&self) -> u64;
+    /// Last modification time, or the Unix epoch if unknown.
+    fn modified(&self) -> SystemTime;
+    /// Returns true for a symbolic link collected with
+    /// [`SymlinkPolicy::Record`], rather than a file.
+    fn is_recorded_symlink(&self) -> bool;
+    /// Returns the entry if its content is not read from the working tree.
+    fn as_virtual(&self) -> Option<&VirtualEntry> {
+        None
+    }
+}
+
+impl CollectedFile for DirEntry {
+    fn path(&self) -> &Path {
+        DirEntry::path(self)
+    }
+
+    fn size(&self) -> u64 {
+        self.metadata().map(|meta| meta.len()).unwrap_or(0)
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.metadata()
+            .and_then(|meta| Ok(meta.modified()?))
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    fn is_recorded_symlink(&self) -> bool {
+        is_recorded_symlink(self)
+    }
+}
+
+/// Content of a [`VirtualEntry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VirtualContent {
+    /// The bytes of a file.
+    File(Arc<[u8]>),
+    /// A file stored in a git repository, read when it is written
+    /// (requires `git` feature).
+    #[cfg(feature = "git")]
+    Blob(crate::git::GitBlob),
+    /// The target of a symbolic link.
+    Symlink(String),
+}
+
+/// A file held in memory or a git repository rather than on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualEntry {
+    /// Path of the file below the project root, as if it were on disk.
+    pub path: PathBuf,
+    /// Content of the file.
+    pub content: VirtualContent,
+    /// Unix permission bits, if known.
+    pub mode: Option<u32>,
+    /// Last modification time.
+    pub modified: SystemTime,
+}
+
+impl CollectedFile for VirtualEntry {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn size(&self) -> u64 {
+        match &self.content {
+            VirtualContent::File(data) => data.len() as u64,
+            #[cfg(feature = "git")]
+            VirtualContent::Blob(blob) => blob.size(),
+            VirtualContent::Symlink(_) => 0,
+        }
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    fn is_recorded_symlink(&self) -> bool {
+        matches!(self.content, VirtualContent::Symlink(_))
+    }
+
+    fn as_virtual(&self) -> Option<&VirtualEntry> {
+        Some(self)
+    }
+}
+
+/// Options for [`collect_files_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct CollectOptions {
+    /// Additional ignore files (e.g., `.gitignore`, `.src2md.ignore`), whose
+    /// rules apply relative to the directory they are in.
+    pub ignore_files: Vec<PathBuf>,
+    /// Which ignore files found in the project are honored.
+    pub ignore: IgnorePolicy,
+    /// If non-empty, only files within these paths are included.
+    pub specific_paths: HashSet<PathBuf>,
+    /// If set, only these files, relative to the project root, are included
+    /// (e.g. the files changed on a branch).
+    pub only_files: Option<HashSet<PathBuf>>,
+    /// Path of the output file being written, which is excluded.
+    pub output_path: Option<PathBuf>,
+    /// If non-empty, only files with these extensions are included.
+    pub extensions: HashSet<String>,
+    /// Globs matched against files relative to the project root; if
+    /// non-empty, only matching files are included. A glob without a `/`
+    /// matches the file name, a glob ending with `/` the files below a
+    /// directory. Files left out by ignore files or other filters stay
+    /// excluded.
+    pub include: Vec<String>,
+    /// Gitignore-style globs matched against paths relative to the project
+    /// root; matching files and directories are excluded, even if they
+    /// also match an `include` glob.
+    pub exclude: Vec<String>,
+    /// Collect hidden files and directories, except version control
+    /// directories such as `.git` and files that commonly hold credentials
+    /// such as `.env` or `.npmrc`.
+    pub hidden: bool,
+    /// Hidden files and directories to collect, relative to the project root
+    /// (e.g. `.github` or `.cargo/config.toml`). Listed paths are collected
+    /// even if they hold credentials; version control directories never are.
+    pub include_hidden: Vec<PathBuf>,
+    /// Collect lock files (see [`LOCK_FILE_NAMES`]), e.g. for dependency
+    /// audits.
+    pub include_lockfiles: bool,
+    /// Collect private keys and credential files (see
+    /// [`SENSITIVE_FILE_NAMES`]), which are skipped by default.
+    pub allow_sensitive: bool,
+    /// Globs of generated files to skip like lock files, e.g. `*.pb.go`,
+    /// `*_generated.rs` or `*.min.js`. They are matched like
+    /// [`include`](Self::include): against the file name, or against the
+    /// path relative to the project root if they contain a `/`, e.g.
+    /// `docs/*.md`.
+    pub skip_patterns: Vec<String>,
+    /// How symbolic links are handled.
+    pub symlinks: SymlinkPolicy,
+    /// Order of the returned files.
+    pub order: FileOrder,
+    /// List the contents of a directory before the files next to it, within
+    /// the same [`order`](Self::order) key.
+    pub directories_first: bool,
+}
+
+/// Sensitive files, lock files and file name patterns skipped during
+/// collection, as configured by [`CollectOptions::allow_sensitive`],
+/// [`CollectOptions::include_lockfiles`] and [`CollectOptions::skip_patterns`].
+#[derive(Debug, Clone)]
+pub(crate) struct SkipList {
+    sensitive: bool,
+    lockfiles: bool,
+    patterns: PathGlobs,
+}
+
+impl SkipList {
+    pub(crate) fn new(options: &CollectOptions) -> Result<Self> {
+        Ok(Self {
+            sensitive: !options.allow_sensitive,
+            lockfiles: !options.include_lockfiles,
+            patterns: PathGlobs::new(&options.skip_patterns, "skip")?,
+        })
+    }
+
+    /// Returns true if `path` is a sensitive file that is skipped.
+    pub(crate) fn is_skipped_sensitive_file(&self, path: &Path) -> bool {
+        self.sensitive && is_sensitive_file(path)
+    }
+
+    /// Returns true if `path` is a lock file that is skipped.
+    pub(crate) fn is_skipped_lock_file(&self, path: &Path) -> bool {
+        self.lockfiles && is_lock_file(path)
+    }
+
+    /// Returns the first skip pattern matching the file at `rel_path`,
+    /// relative to the project root.
+    pub(crate) fn matching_pattern(&self, rel_path: &Path) -> Option<&str> {
+        self.patterns.matching(rel_path)
+    }
+}
+
+/// Globs matched against files relative to the project root, as used by
+/// [`CollectOptions::include`] and [`CollectOptions::skip_patterns`].
+///
+/// Like in `.gitignore` files, a glob without a `/` matches the file name
+/// at any depth, while other globs match the whole relative path and may
+/// start with `/`. A glob ending with `/` matches the files below a
+/// directory.
+#[derive(Debug, Clone)]
+pub(crate) struct PathGlobs {
+    patterns: Vec<String>,
+    names: GlobSet,
+    name_patterns: Vec<usize>,
+    paths: GlobSet,
+    path_patterns: Vec<usize>,
+}
+
+impl PathGlobs {
+    /// Builds the globs; invalid ones are reported as `Invalid {kind} pattern`.
+    pub(crate) fn new(patterns: &[String], kind: &str) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut name_patterns = Vec::new();
+        let mut paths = GlobSetBuilder::new();
+        let mut path_patterns = Vec::new();
+        for (index, pattern) in patterns.iter().enumerate() {
+            let (glob, by_name) = match pattern.strip_suffix('/') {
+                Some(dir) if dir.contains('/') => {
+                    (format!("{}/**", dir.trim_start_matches('/')), false)
+                }
+                Some(dir) => (format!("**/{dir}/**"), false),
+                None if pattern.contains('/') => {
+                    (pattern.trim_start_matches('/').to_string(), false)
+                }
+                None => (pattern.clone(), true),
+            };
+            let glob = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid {kind} pattern: {pattern:?}"))?;
+            if by_name {
+                names.add(glob);
+                name_patterns.push(index);
+            } else {
+                paths.add(glob);
+                path_patterns.push(index);
+            }
+        }
+        Ok(Self {
+            patterns: patterns.to_vec(),
+            names: names
+                .build()
+                .with_context(|| format!("Failed to build {kind} patterns"))?,
+            name_patterns,
+            paths: paths
+                .build()
+                .with_context(|| format!("Failed to build {kind} patterns"))?,
+            path_patterns,
+        })
+    }
+
+    /// Returns true if there are no globs.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Returns the first glob matching the file at `rel_path`, relative to
+    /// the project root.
+    pub(crate) fn matching(&self, rel_path: &Path) -> Option<&str> {
+        let by_name = rel_path.file_name().into_iter().flat_map(|name| {
+            self.names
+                .matches(name)
+                .into_iter()
+                .map(|index| self.name_patterns[index])
+        });
+        let by_path = self
+            .paths
+            .matches(rel_path)
+            .into_iter()
+            .map(|index| self.path_patterns[index]);
+        let index = by_name.chain(by_path).min()?;
+        Some(&self.patterns[index])
+    }
+}
+
+/// Decides which hidden files and directories are collected, as configured
+/// by [`CollectOptions::hidden`] and [`CollectOptions::include_hidden`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HiddenFilter {
+    all: bool,
+    allow: Vec<PathBuf>,
+}
+
+impl HiddenFilter {
+    pub(crate) fn new(options: &CollectOptions) -> Self {
+        let allow = options
+            .include_hidden
+            .iter()
+            // Normalize `./.github/` to `.github`
+            .map(|path| {
+                path.components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect()
+            })
+            .collect();
+        Self {
+            all: options.hidden,
+            allow,
+        }
+    }
+
+    /// Returns true if any hidden files are collected.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.all || !self.allow.is_empty()
+    }
+
+    /// Returns true if the entry at `rel_path`, relative to the project root,
+    /// is collected as far as hidden files are concerned. Directories on the
+    /// way to a listed path are allowed, so the walker can reach it.
+    pub(crate) fn allows(&self, rel_path: &Path, is_dir: bool) -> bool {
+        let mut hidden = false;
+        let mut secret = false;
+        for component in rel_path.components() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            let name = name.to_string_lossy();
+            if VCS_DIRECTORIES.contains(&name.as_ref()) {
+                return false;
+            }
+            if name.starts_with('.') {
+                hidden = true;
+                secret |= HIDDEN_SECRET_NAMES.contains(&name.as_ref()) || name.starts_with(".env.");
+            }
+        }
+        if !hidden {
+            return true;
+        }
+        secret |= HIDDEN_SECRET_PATHS
+            .iter()
+            .any(|secret_path| rel_path.ends_with(secret_path));
+
+        let listed = self.allow.iter().any(|allowed| {
+            rel_path.starts_with(allowed) || (is_dir && allowed.starts_with(rel_path))
+        });
+        listed || (self.all && !secret)
+    }
+}
+
+/// Decides which directories and files the walker enters, as configured by
+/// [`CollectOptions::hidden`], [`CollectOptions::include_hidden`] and
+/// [`CollectOptions::symlinks`]. Hidden entries are only checked here when
+/// some are collected; otherwise the walker skips all of them itself.
+#[derive(Debug, Clone)]
+pub(crate) struct EntryFilter {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    pub(crate) hidden: HiddenFilter,
+    pub(crate) symlinks: SymlinkPolicy,
+}
+
+impl EntryFilter {
+    pub(crate) fn new(root: &Path, options: &CollectOptions) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            hidden: HiddenFilter::new(options),
+            symlinks: options.symlinks,
+        }
+    }
+
+    /// Returns why the walker skips the entry at `path`, if it does.
+    pub(crate) fn exclusion(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        is_symlink: bool,
+    ) -> Option<Exclusion> {
+        if self.hidden.is_enabled()
+            && !self
+                .hidden
+                .allows(path.strip_prefix(&self.root).unwrap_or(path), is_dir)
+        {
+            return Some(Exclusion::Hidden);
+        }
+        if !is_symlink {
+            return None;
+        }
+        let keep = match self.symlinks {
+            SymlinkPolicy::Skip => false,
+            SymlinkPolicy::FollowWithinRoot => path
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(&self.canonical_root)),
+            SymlinkPolicy::Record => true,
+        };
+        (!keep).then_some(Exclusion::Symlink(self.symlinks))
+    }
+}
+
+/// The checks applied to every file the walker yields, in order.
+pub(crate) struct FileFilter<'a> {
+    root: &'a Path,
+    options: &'a CollectOptions,
+    hidden: HiddenFilter,
+    includes: PathGlobs,
+    skip_list: SkipList,
+    canonical_output: Option<PathBuf>,
+}
+
+impl<'a> FileFilter<'a> {
+    pub(crate) fn new(root: &'a Path, options: &'a CollectOptions) -> Result<Self> {
+        Ok(Self {
+            root,
+            options,
+            hidden: HiddenFilter::new(options),
+            includes: PathGlobs::new(&options.include, "include")?,
+            skip_list: SkipList::new(options)?,
+            // Canonicalize output path for reliable comparison
+            canonical_output: options
+                .output_path
+                .as_ref()
+                .and_then(|path| path.canonicalize().ok()),
+        })
+    }
+
+    /// Returns why the file at `path` is not collected, if it is not.
+    pub(crate) fn exclusion(&self, path: &Path) -> Option<Exclusion> {
+        let options = self.options;
+        let rel_path = path.strip_prefix(self.root).unwrap_or(path);
+
+        // The walker skips hidden directories; hidden files are checked here
+        if is_hidden(path) && !self.hidden.allows(rel_path, false) {
+            Some(Exclusion::Hidden)
+        } else if !self.includes.is_empty() && self.includes.matching(rel_path).is_none() {
+            // Includes are checked after the walker, so they never override
+            // ignore files or the hidden filter
+            Some(Exclusion::NotIncluded)
+        } else if self.skip_list.is_skipped_sensitive_file(path) {
+            Some(Exclusion::Sensitive)
+        } else if self.skip_list.is_skipped_lock_file(path) {
+            Some(Exclusion::LockFile)
+        } else if let Some(pattern) = self.skip_list.matching_pattern(rel_path) {
+            Some(Exclusion::SkipPattern(pattern.to_string()))
+        } else if !options.extensions.is_empty()
+            && !has_matching_extension(path, &options.extensions)
+        {
+            Some(Exclusion::Extension)
+        } else if !options.specific_paths.is_empty()
+            && !is_in_specific_paths(path, &options.specific_paths)
+        {
+            Some(Exclusion::NotInPaths)
+        } else if options
+            .only_files
+            .as_ref()
+            .is_some_and(|files| !files.contains(rel_path))
+        {
+            Some(Exclusion::Unchanged)
+        } else if self
+            .canonical_output
+            .as_ref()
+            .is_some_and(|out| path.canonicalize().is_ok_and(|path| path == *out))
+        {
+            // Explicitly skip the output file by path (prevents race condition)
+            Some(Exclusion::OutputFile)
+        } else if is_src2md_output(path) {
+            // A previous src2md output, recognized by its header
+            Some(Exclusion::Src2mdOutput)
+        } else {
+            None
+        }
+    }
+}
+
+/// The collection filters that depend on the path of a file alone, for
+/// files that are not on disk, such as the files of a git revision.
+///
+/// Applies `--include` and `--exclude` globs, hidden files, sensitive files,
+/// lock files, skip patterns, extensions and specific paths like the walker,
+/// and ignore files if given with
+/// [`with_ignore_rules`](Self::with_ignore_rules).
+#[cfg(feature = "git")]
+pub(crate) struct PathFilter<'a> {
+    root: &'a Path,
+    options: &'a CollectOptions,
+    overrides: Option<ignore::overrides::Override>,
+    includes: PathGlobs,
+    skip_list: SkipList,
+    hidden_filter: HiddenFilter,
+    ignore_rules: Option<IgnoreRules>,
+}
+
+#[cfg(feature = "git")]
+impl<'a> PathFilter<'a> {
+    pub(crate) fn new(root: &'a Path, options: &'a CollectOptions) -> Result<Self> {
+        let overrides = if options.exclude.is_empty() {
+            None
+        } else {
+            Some(build_overrides(root, &options.exclude)?)
+        };
+        Ok(Self {
+            root,
+            options,
+            overrides,
+            includes: PathGlobs::new(&options.include, "include")?,
+            skip_list: SkipList::new(options)?,
+            hidden_filter: HiddenFilter::new(options),
+            ignore_rules: None,
+        })
+    }
+
+    /// Also leaves out the files matched by `rules`.
+    pub(crate) fn with_ignore_rules(self, rules: IgnoreRules) -> Self {
+        Self {
+            ignore_rules: Some(rules),
+            ..self
+        }
+    }
+
+    /// Returns true if the file at `rel_path`, relative to the project root,
+    /// passes the filters.
+    pub(crate) fn allows(&self, rel_path: &Path) -> bool {
+        let full = self.root.join(rel_path);
+        if let Some(overrides) = &self.overrides {
+            // An excluded directory excludes everything below it
+            let excluded_dir = rel_path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .any(|dir| overrides.matched(self.root.join(dir), true).is_ignore());
+            if excluded_dir || overrides.matched(&full, false).is_ignore() {
+                return false;
+            }
+        }
+        self.hidden_filter.allows(rel_path, false)
+            && (self.includes.is_empty() || self.includes.matching(rel_path).is_some())
+            && !self.skip_list.is_skipped_sensitive_file(rel_path)
+            && !self.skip_list.is_skipped_lock_file(rel_path)
+            && self.skip_list.matching_pattern(rel_path).is_none()
+            && (self.options.extensions.is_empty()
+                || has_matching_extension(rel_path, &self.options.extensions))
+            && (self.options.specific_paths.is_empty()
+                || is_in_specific_paths(&full, &self.options.specific_paths))
+            && !self
+                .ignore_rules
+                .as_ref()
+                .is_some_and(|rules| rules.is_ignored(rel_path))
+    }
+}
+
+/// The rules of the ignore files honored by the [`IgnorePolicy`], for files
+/// that are not found by walking the disk, such as staged files or the files
+/// of a git revision.
+///
+/// Only ignore files in the project root and below it apply, along with the
+/// explicit [`CollectOptions::ignore_files`]. Their precedence is the same
+/// as for the walker: `.src2mdignore` files, then `.gitignore` files, the
+/// deepest first, then the explicit ignore files.
+#[cfg(feature = "git")]
+pub(crate) struct IgnoreRules {
+    root: PathBuf,
+    /// Rules of the ignore files in the project, in order of precedence.
+    rules: Vec<Gitignore>,
+    /// Rules of the explicit ignore files, which may be anywhere.
+    explicit: Vec<Gitignore>,
+}
+
+#[cfg(feature = "git")]
+impl IgnoreRules {
+    /// Builds the rules for the files at `paths`, relative to `root`, from
+    /// the ignore files in their directories. `read` returns the content of
+    /// an ignore file by its path relative to `root`, or `None` if there is
+    /// none.
+    pub(crate) fn new<'p>(
+        root: &Path,
+        options: &CollectOptions,
+        paths: impl IntoIterator<Item = &'p Path>,
+        read: impl Fn(&Path) -> Option<Vec<u8>>,
+    ) -> Self {
+        let mut dirs: Vec<&Path> = paths
+            .into_iter()
+            .flat_map(|path| path.ancestors().skip(1))
+            .collect();
+        dirs.sort_by_key(|dir| (Reverse(dir.components().count()), *dir));
+        dirs.dedup();
+
+        let policy = options.ignore;
+        let names = [
+            (policy.src2mdignore, SRC2MD_IGNORE_FILENAME),
+            (policy.gitignore, ".gitignore"),
+        ];
+        let mut rules = Vec::new();
+        for (_, name) in names.into_iter().filter(|(honored, _)| *honored) {
+            for dir in &dirs {
+                let file = dir.join(name);
+                let Some(content) = read(&file) else {
+                    continue;
+                };
+                let mut builder = GitignoreBuilder::new(root.join(dir));
+                let from = root.join(&file);
+                for line in String::from_utf8_lossy(&content).lines() {
+                    if let Err(err) = builder.add_line(Some(from.clone()), line) {
+                        warn!("Problem reading ignore file {}: {err}", from.display());
+                    }
+                }
+                match builder.build() {
+                    Ok(gitignore) => rules.push(gitignore),
+                    Err(err) => warn!("Problem reading ignore file {}: {err}", from.display()),
+                }
+            }
+        }
+
+        let explicit = options
+            .ignore_files
+            .iter()
+            .map(|file| {
+                // Rules are relative to the directory of the file
+                let (gitignore, err) = Gitignore::new(file);
+                if let Some(err) = err {
+                    warn!("Problem reading ignore file {}: {err}", file.display());
+                }
+                gitignore
+            })
+            .collect();
+
+        Self {
+            root: root.to_path_buf(),
+            rules,
+            explicit,
+        }
+    }
+
+    /// Returns true if the file at `rel_path`, relative to the project root,
+    /// or one of its parent directories is ignored.
+    pub(crate) fn is_ignored(&self, rel_path: &Path) -> bool {
+        rel_path
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| self.matched(&self.root.join(path), path != rel_path))
+    }
+
+    fn matched(&self, path: &Path, is_dir: bool) -> bool {
+        let project = self
+            .rules
+            .iter()
+            .filter(|rules| path.starts_with(rules.path()));
+        project
+            .chain(&self.explicit)
+            .map(|rules| rules.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
 
 /// Collects all files from the project root, applying ignore filters and specific path constraints.
 ///
@@ -48,9 +985,16 @@ const LOCK_FILE_EXTENSIONS: &[&str] = &["lock", "lockb"];
 ///
 /// The following are always excluded:
 /// - Hidden files and directories (starting with `.`)
+/// - Private keys and credential files (id_rsa, *.pem, secrets.yaml, etc.)
 /// - Lock files (package-lock.json, yarn.lock, Cargo.lock, etc.)
+/// - Files matched by `.gitignore` and `.src2mdignore` rules (see [`IgnorePolicy`])
 /// - The explicit `output_path` if provided
 /// - Any file that starts with the src2md magic header
+///
+/// Files are returned sorted by their path relative to the project root.
+///
+/// Symbolic links are followed as long as they point inside the project
+/// root; see [`collect_files_with_options`] for other policies.
 pub fn collect_files(
     project_root: &Path,
     ignore_file: Option<&PathBuf>,
@@ -58,31 +1002,40 @@ pub fn collect_files(
     output_path: Option<&PathBuf>,
     extensions: &HashSet<String>,
 ) -> Result<Vec<DirEntry>> {
-    let mut builder = WalkBuilder::new(project_root);
-
-    // Configure walker to skip hidden files/directories
-    builder.hidden(true).ignore(false);
-
-    // If a user-provided ignore file exists, use it
-    if let Some(ignore_path) = ignore_file {
-        debug!("Using ignore file: {}", ignore_path.display());
-        builder.add_ignore(ignore_path);
-    }
+    let options = CollectOptions {
+        ignore_files: ignore_file.into_iter().cloned().collect(),
+        specific_paths: specific_paths.clone(),
+        output_path: output_path.cloned(),
+        extensions: extensions.clone(),
+        ..CollectOptions::default()
+    };
+    collect_files_with_options(project_root, &options)
+}
 
-    // Canonicalize output path for reliable comparison
-    let canonical_output = output_path.and_then(|p| p.canonicalize().ok());
-    if let Some(ref out) = canonical_output {
-        debug!("Excluding output file: {}", out.display());
+/// Collects all files from the project root like [`collect_files`], with
+/// the filters and policies given in `options`.
+///
+/// With [`SymlinkPolicy::Record`], symbolic links are returned as entries of
+/// their own (their [`file_type`](DirEntry::file_type) is a symlink); see
+/// [`is_recorded_symlink`].
+pub fn collect_files_with_options(
+    project_root: &Path,
+    options: &CollectOptions,
+) -> Result<Vec<DirEntry>> {
+    if let Some(output_path) = &options.output_path {
+        debug!("Excluding output file: {}", output_path.display());
     }
-
-    if !extensions.is_empty() {
-        debug!("Filtering by extensions: {:?}", extensions);
+    if !options.extensions.is_empty() {
+        debug!("Filtering by extensions: {:?}", options.extensions);
     }
 
-    let walker = builder.build();
+    let walker = walk_builder(project_root, options, |_| true)?.build();
+    let file_filter = FileFilter::new(project_root, options)?;
     let mut entries = Vec::new();
     let mut skipped_hidden = 0;
+    let mut skipped_sensitive = 0;
     let mut skipped_lock = 0;
+    let mut skipped_patterns = 0;
     let mut skipped_outputs = 0;
     let mut skipped_extensions = 0;
 
@@ -90,55 +1043,25 @@ pub fn collect_files(
         match result {
             Ok(entry) => {
                 let path = entry.path();
-
-                if !path.is_file() {
-                    continue;
-                }
-
-                // Skip hidden files (the walker with hidden(true) should skip hidden dirs)
-                if is_hidden(&entry) {
-                    trace!("Skipping hidden file: {}", path.display());
-                    skipped_hidden += 1;
-                    continue;
-                }
-
-                // Skip lock files
-                if is_lock_file(path) {
-                    trace!("Skipping lock file: {}", path.display());
-                    skipped_lock += 1;
+                let recorded_link = is_recorded_symlink(&entry);
+                if !path.is_file() && !recorded_link {
                     continue;
                 }
 
-                // Check extension filter
-                if !extensions.is_empty() && !has_matching_extension(path, extensions) {
-                    trace!("Skipping file (extension filter): {}", path.display());
-                    skipped_extensions += 1;
+                let Some(reason) = file_filter.exclusion(path) else {
+                    entries.push(entry);
                     continue;
+                };
+                trace!("Skipping file ({reason}): {}", path.display());
+                match reason {
+                    Exclusion::Hidden => skipped_hidden += 1,
+                    Exclusion::Sensitive => skipped_sensitive += 1,
+                    Exclusion::LockFile => skipped_lock += 1,
+                    Exclusion::SkipPattern(_) => skipped_patterns += 1,
+                    Exclusion::Extension => skipped_extensions += 1,
+                    Exclusion::OutputFile | Exclusion::Src2mdOutput => skipped_outputs += 1,
+                    _ => {}
                 }
-
-                // Check specific paths filter
-                if !specific_paths.is_empty() && !is_in_specific_paths(path, specific_paths) {
-                    continue;
-                }
-
-                // Explicitly skip the output file by path (prevents race condition)
-                if let Some(ref canonical_out) = canonical_output
-                    && let Ok(canonical_path) = path.canonicalize()
-                    && canonical_path == *canonical_out
-                {
-                    trace!("Skipping output file by path: {}", path.display());
-                    skipped_outputs += 1;
-                    continue;
-                }
-
-                // Check if file is a previous src2md output by reading its header
-                if is_src2md_output(path) {
-                    debug!("Skipping src2md output file: {}", path.display());
-                    skipped_outputs += 1;
-                    continue;
-                }
-
-                entries.push(entry);
             }
             Err(err) => {
                 warn!("Error walking path: {err}");
@@ -150,9 +1073,21 @@ pub fn collect_files(
     if skipped_hidden > 0 {
         debug!("Skipped {} hidden file(s)", skipped_hidden);
     }
+    if skipped_sensitive > 0 {
+        info!(
+            "Skipped {} sensitive file(s) such as private keys and credentials",
+            skipped_sensitive
+        );
+    }
     if skipped_lock > 0 {
         debug!("Skipped {} lock file(s)", skipped_lock);
     }
+    if skipped_patterns > 0 {
+        debug!(
+            "Skipped {} file(s) matching skip patterns",
+            skipped_patterns
+        );
+    }
     if skipped_outputs > 0 {
         debug!(
             "Skipped {} src2md output file(s) to prevent self-inclusion",
@@ -167,14 +1102,112 @@ pub fn collect_files(
     }
 
     debug!("Collected {} files", entries.len());
-    Ok(entries)
+    Ok(sort_entries(
+        entries,
+        project_root,
+        options.order,
+        options.directories_first,
+    ))
 }
 
+/// Configures a walker over `project_root` with the ignore files, `--exclude`
+/// globs, hidden file filter and symbolic link policy of `options`.
+///
+/// Entries for which `keep` returns false are skipped with everything below
+/// them, which lets [`explain_path`](crate::explain::explain_path) walk only
+/// the way to one path.
+pub(crate) fn walk_builder(
+    project_root: &Path,
+    options: &CollectOptions,
+    keep: impl Fn(&DirEntry) -> bool + Send + Sync + 'static,
+) -> Result<WalkBuilder> {
+    let mut builder = WalkBuilder::new(project_root);
+
+    // Configure walker to skip hidden files/directories, unless some are
+    // collected. `.ignore` files are not read; see `IgnorePolicy` for the
+    // files that are.
+    let entry_filter = EntryFilter::new(project_root, options);
+    let ignore = options.ignore;
+    builder
+        .hidden(!entry_filter.hidden.is_enabled())
+        .ignore(false)
+        .require_git(false)
+        .git_ignore(ignore.gitignore)
+        .git_exclude(ignore.gitignore)
+        .git_global(ignore.gitignore);
+    if ignore.src2mdignore {
+        builder.add_custom_ignore_filename(SRC2MD_IGNORE_FILENAME);
+    }
+
+    builder.follow_links(options.symlinks == SymlinkPolicy::FollowWithinRoot);
+    builder.filter_entry(move |entry| {
+        // The root itself may be hidden or a link
+        if entry.depth() == 0 {
+            return true;
+        }
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        if let Some(reason) = entry_filter.exclusion(entry.path(), is_dir, entry.path_is_symlink())
+        {
+            trace!("Skipping {} ({reason})", entry.path().display());
+            return false;
+        }
+        keep(entry)
+    });
+
+    if !options.exclude.is_empty() {
+        builder.overrides(build_overrides(project_root, &options.exclude)?);
+    }
+
+    for ignore_path in &options.ignore_files {
+        debug!("Using ignore file: {}", ignore_path.display());
+        if let Some(err) = builder.add_ignore(ignore_path) {
+            warn!(
+                "Problem reading ignore file {}: {err}",
+                ignore_path.display()
+            );
+        }
+    }
+
+    Ok(builder)
+}
+
+/// Builds the walker overrides for `--exclude` globs.
+///
+/// `--include` globs are not added: the walker lets whitelisted paths
+/// bypass ignore files and the hidden filter, so they are checked with
+/// [`PathGlobs`] instead.
+pub(crate) fn build_overrides(
+    project_root: &Path,
+    exclude: &[String],
+) -> Result<ignore::overrides::Override> {
+    let mut builder = OverrideBuilder::new(project_root);
+    for glob in exclude {
+        debug!("Excluding files matching: {glob}");
+        builder
+            .add(&format!("!{glob}"))
+            .with_context(|| format!("Invalid exclude pattern: {glob:?}"))?;
+    }
+    builder.build().context("Failed to build exclude patterns")
+}
+
+/// Returns true if the entry is a symbolic link collected with
+/// [`SymlinkPolicy::Record`], rather than a file.
+pub fn is_recorded_symlink(entry: &DirEntry) -> bool {
+    entry
+        .file_type()
+        .is_some_and(|file_type| file_type.is_symlink())
+}
+
+/// Magic byte sequences of all bundle formats src2md writes.
+const MAGIC_SIGNATURES: &[&[u8]] = &[OUTPUT_MAGIC_BYTES, OUTPUT_MAGIC_BYTES_V1, JSON_MAGIC_BYTES];
+
 /// Checks if a file is a src2md output by reading its magic header.
 ///
 /// This uses memory-mapped I/O to efficiently read just the first few bytes
 /// without loading the entire file into memory.
-fn is_src2md_output(path: &Path) -> bool {
+pub(crate) fn is_src2md_output(path: &Path) -> bool {
     let file = match File::open(path) {
         Ok(f) => f,
         Err(_) => return false,
@@ -186,35 +1219,46 @@ fn is_src2md_output(path: &Path) -> bool {
         Err(_) => return false,
     };
 
-    // File must be at least as long as the magic header
-    if metadata.len() < OUTPUT_MAGIC_BYTES.len() as u64 {
+    // File must be at least as long as the shortest magic header
+    let shortest = MAGIC_SIGNATURES.iter().map(|m| m.len()).min().unwrap_or(0);
+    if metadata.len() < shortest as u64 {
         return false;
     }
 
+    let longest = MAGIC_SIGNATURES.iter().map(|m| m.len()).max().unwrap_or(0);
+    let len = std::cmp::min(metadata.len(), longest as u64) as usize;
+
     // Memory-map just enough to check the header
     // SAFETY: We only read from the memory-mapped region and the file
     // remains open for the duration of the check.
-    let mmap = match unsafe { MmapOptions::new().len(OUTPUT_MAGIC_BYTES.len()).map(&file) } {
+    let mmap = match unsafe { MmapOptions::new().len(len).map(&file) } {
         Ok(m) => m,
         Err(_) => return false,
     };
 
-    mmap[..] == OUTPUT_MAGIC_BYTES[..]
+    is_src2md_content(&mmap)
+}
+
+/// Checks if file content starts with the magic header of a src2md output.
+pub(crate) fn is_src2md_content(content: &[u8]) -> bool {
+    MAGIC_SIGNATURES
+        .iter()
+        .any(|magic| content.starts_with(magic))
 }
 
 /// Checks if a file itself is hidden (filename starts with a dot).
 /// This only checks the filename, not the full path, since we filter
 /// directories during the walk and shouldn't check parent directories
 /// outside the project root.
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
+pub(crate) fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
         .is_some_and(|s| s.starts_with('.'))
 }
 
-/// Checks if a file is a lock file based on its name or extension.
-fn is_lock_file(path: &Path) -> bool {
+/// Checks if a file is a lock file based on its name or extension, using
+/// [`LOCK_FILE_NAMES`] and [`LOCK_FILE_EXTENSIONS`].
+pub fn is_lock_file(path: &Path) -> bool {
     let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or("");
 
     // Check exact filename matches
@@ -234,8 +1278,21 @@ fn is_lock_file(path: &Path) -> bool {
     false
 }
 
+/// Checks if a file commonly holds private keys or credentials based on its
+/// name or extension, using [`SENSITIVE_FILE_NAMES`] and
+/// [`SENSITIVE_FILE_EXTENSIONS`].
+pub fn is_sensitive_file(path: &Path) -> bool {
+    let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or("");
+    if SENSITIVE_FILE_NAMES.contains(&file_name.to_lowercase().as_str()) {
+        return true;
+    }
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| SENSITIVE_FILE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
 /// Checks if a file has an extension matching the provided set.
-fn has_matching_extension(path: &Path, extensions: &HashSet<String>) -> bool {
+pub(crate) fn has_matching_extension(path: &Path, extensions: &HashSet<String>) -> bool {
     path.extension()
         .and_then(OsStr::to_str)
         .map(|ext| extensions.contains(&ext.to_lowercase()))
@@ -246,7 +1303,7 @@ fn has_matching_extension(path: &Path, extensions: &HashSet<String>) -> bool {
 ///
 /// If the specific path is a file, it must match exactly.
 /// If it's a directory, the path must be a descendant of that directory.
-fn is_in_specific_paths(path: &Path, specific_paths: &HashSet<PathBuf>) -> bool {
+pub(crate) fn is_in_specific_paths(path: &Path, specific_paths: &HashSet<PathBuf>) -> bool {
     specific_paths.iter().any(|p| {
         if p.is_file() {
             path == p
@@ -264,6 +1321,15 @@ mod tests {
     use std::io::Write;
     use tempfile::tempdir;
 
+    /// Collects the files under `root` and returns their paths relative to
+    /// it, in collection order.
+    fn collect_rel(root: &Path, options: &CollectOptions) -> Result<Vec<PathBuf>> {
+        Ok(collect_files_with_options(root, options)?
+            .iter()
+            .map(|entry| entry.path().strip_prefix(root).unwrap().to_path_buf())
+            .collect())
+    }
+
     #[test]
     fn test_collect_files_basic() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -312,6 +1378,64 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_collect_files_hidden_allowlist() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join(".github/workflows"))?;
+        fs::create_dir_all(root.join(".cargo"))?;
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join(".aws"))?;
+        fs::write(root.join(".github/workflows/ci.yml"), "on: push")?;
+        fs::write(root.join(".cargo/config.toml"), "[build]")?;
+        fs::write(root.join(".cargo/credentials.toml"), "token = \"x\"")?;
+        fs::write(root.join(".git/config"), "[core]")?;
+        fs::write(root.join(".aws/credentials"), "key")?;
+        fs::write(root.join(".env"), "SECRET=1")?;
+        fs::write(root.join(".env.local"), "SECRET=2")?;
+        fs::write(root.join(".eslintrc"), "{}")?;
+        fs::write(root.join("main.rs"), "fn main() {}")?;
+
+        assert_eq!(
+            collect_rel(root, &CollectOptions::default())?,
+            [PathBuf::from("main.rs")]
+        );
+        let options = CollectOptions {
+            include_hidden: ["./.github/", ".cargo/config.toml", ".git"]
+                .map(PathBuf::from)
+                .into(),
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            [".cargo/config.toml", ".github/workflows/ci.yml", "main.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            hidden: true,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            [
+                ".cargo/config.toml",
+                ".eslintrc",
+                ".github/workflows/ci.yml",
+                "main.rs"
+            ]
+            .map(PathBuf::from)
+        );
+        // Credential files are only collected when listed
+        let options = CollectOptions {
+            hidden: true,
+            include_hidden: vec![PathBuf::from(".env")],
+            ..CollectOptions::default()
+        };
+        assert!(collect_rel(root, &options)?.contains(&PathBuf::from(".env")));
+
+        Ok(())
+    }
+
     #[test]
     fn test_collect_files_ignores_lock_files() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -330,6 +1454,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_collect_files_skips_sensitive_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join("deploy/certs"))?;
+        fs::write(root.join("source.rs"), "// source")?;
+        fs::write(root.join("id_rsa"), "key")?;
+        fs::write(root.join("credentials.json"), "{}")?;
+        fs::write(root.join("deploy/secrets.yaml"), "token: x")?;
+        fs::write(root.join("deploy/certs/server.pem"), "cert")?;
+        fs::write(root.join("deploy/certs/client.P12"), "store")?;
+        fs::write(root.join("deploy/release.keystore"), "store")?;
+
+        assert_eq!(collect_rel(root, &CollectOptions::default())?.len(), 1);
+        let options = CollectOptions {
+            allow_sensitive: true,
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?.len(), 7);
+        Ok(())
+    }
+
     #[test]
     fn test_collect_files_ignores_nested_lock_files() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -549,6 +1696,9 @@ mod tests {
         output_file.write_all(b"content")?;
         drop(output_file);
 
+        // Output of older versions
+        fs::write(root.join("output_v1.md"), "<!-- src2md:v1 -->\n\n## a.rs\n")?;
+
         // Regular file
         fs::write(root.join("regular.md"), "# Regular markdown")?;
 
@@ -558,7 +1708,16 @@ mod tests {
         // File with partial header
         fs::write(root.join("partial.md"), "<!-- src")?;
 
+        // JSON bundles
+        fs::write(root.join("bundle.json"), "{\"src2md\":\"v1\",\"files\":[]}")?;
+        fs::write(root.join("bundle.jsonl"), "{\"src2md\":\"v1\"}\n")?;
+        fs::write(root.join("data.json"), "{\"name\": \"src2md\"}")?;
+
         assert!(is_src2md_output(&root.join("output.md")));
+        assert!(is_src2md_output(&root.join("output_v1.md")));
+        assert!(is_src2md_output(&root.join("bundle.json")));
+        assert!(is_src2md_output(&root.join("bundle.jsonl")));
+        assert!(!is_src2md_output(&root.join("data.json")));
         assert!(!is_src2md_output(&root.join("regular.md")));
         assert!(!is_src2md_output(&root.join("empty.md")));
         assert!(!is_src2md_output(&root.join("partial.md")));
@@ -566,6 +1725,246 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_collect_files_ignore_policy() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        // Not a git repository
+        fs::create_dir_all(root.join("target"))?;
+        fs::create_dir_all(root.join("docs/drafts"))?;
+        fs::write(root.join(".gitignore"), "target/\n")?;
+        fs::write(root.join("docs/.src2mdignore"), "drafts/\n")?;
+        fs::write(root.join("target/app.rs"), "// build output")?;
+        fs::write(root.join("docs/drafts/idea.md"), "idea")?;
+        fs::write(root.join("docs/guide.md"), "guide")?;
+        fs::write(root.join("extra.ignore"), "guide.md\n")?;
+
+        assert_eq!(
+            collect_rel(root, &CollectOptions::default())?,
+            ["docs/guide.md", "extra.ignore"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            ignore: IgnorePolicy {
+                gitignore: false,
+                ..IgnorePolicy::default()
+            },
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["docs/guide.md", "extra.ignore", "target/app.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            ignore: IgnorePolicy::NONE,
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?.len(), 4);
+        // Explicit ignore files apply even without other ignore files
+        let options = CollectOptions {
+            ignore: IgnorePolicy::NONE,
+            ignore_files: vec![root.join("extra.ignore")],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["docs/drafts/idea.md", "extra.ignore", "target/app.rs"].map(PathBuf::from)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_files_lockfiles_and_skip_patterns() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join("api"))?;
+        fs::create_dir_all(root.join("dist"))?;
+        fs::write(root.join("Cargo.lock"), "# lock")?;
+        fs::write(root.join("main.rs"), "fn main() {}")?;
+        fs::write(root.join("api/service.pb.go"), "package api")?;
+        fs::write(root.join("api/service.go"), "package api")?;
+        fs::write(root.join("schema_generated.rs"), "// generated")?;
+        fs::write(root.join("dist/app.min.js"), "x")?;
+
+        assert_eq!(collect_rel(root, &CollectOptions::default())?.len(), 5);
+        let options = CollectOptions {
+            include_lockfiles: true,
+            ..CollectOptions::default()
+        };
+        assert!(collect_rel(root, &options)?.contains(&PathBuf::from("Cargo.lock")));
+        let options = CollectOptions {
+            skip_patterns: vec![
+                "*.pb.go".to_string(),
+                "*_generated.rs".to_string(),
+                "*.min.js".to_string(),
+            ],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["api/service.go", "main.rs"].map(PathBuf::from)
+        );
+        // Patterns with a slash match relative paths
+        let options = CollectOptions {
+            skip_patterns: vec!["api/*.go".to_string()],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["dist/app.min.js", "main.rs", "schema_generated.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            skip_patterns: vec!["/*.rs".to_string(), "dist/".to_string()],
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["api/service.go", "api/service.pb.go"].map(PathBuf::from)
+        );
+
+        let options = CollectOptions {
+            skip_patterns: vec!["[a-".to_string()],
+            ..CollectOptions::default()
+        };
+        let err = collect_rel(root, &options).expect_err("invalid glob must fail");
+        assert!(err.to_string().contains("Invalid skip pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_files_include_exclude_globs() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join("src/tests"))?;
+        fs::create_dir_all(root.join("src/generated"))?;
+        fs::create_dir_all(root.join("docs"))?;
+        fs::write(root.join("src/lib.rs"), "// lib")?;
+        fs::write(root.join("src/tests/lib_test.rs"), "// test")?;
+        fs::write(root.join("src/generated/api.rs"), "// generated")?;
+        fs::write(root.join("src/schema_generated.rs"), "// generated")?;
+        fs::write(root.join("docs/guide.md"), "# Guide")?;
+        fs::write(root.join("lib.rs"), "// top-level")?;
+
+        let globs = |globs: &[&str]| globs.iter().map(|s| s.to_string()).collect();
+        let options = CollectOptions {
+            include: globs(&["src/**"]),
+            exclude: globs(&["tests/", "generated/", "*_generated.rs"]),
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?, [PathBuf::from("src/lib.rs")]);
+        // Patterns are anchored to the project root with a leading slash
+        let options = CollectOptions {
+            include: globs(&["/lib.rs"]),
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?, [PathBuf::from("lib.rs")]);
+        let options = CollectOptions {
+            include: globs(&["*.md", "lib.rs"]),
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["docs/guide.md", "lib.rs", "src/lib.rs"].map(PathBuf::from)
+        );
+        // A trailing slash includes the files below a directory
+        let options = CollectOptions {
+            include: globs(&["tests/"]),
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            [PathBuf::from("src/tests/lib_test.rs")]
+        );
+        let options = CollectOptions {
+            exclude: globs(&["src"]),
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?.len(), 2);
+
+        let options = CollectOptions {
+            include: globs(&["src/{lib"]),
+            ..CollectOptions::default()
+        };
+        let err = collect_rel(root, &options).expect_err("invalid glob must fail");
+        assert!(err.to_string().contains("Invalid include pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_files_include_keeps_ignored_files_out() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::write(root.join(".gitignore"), "secret.rs\n")?;
+        fs::write(root.join("main.rs"), "fn main() {}")?;
+        fs::write(root.join("secret.rs"), "// ignored")?;
+        fs::write(root.join(".hidden.rs"), "// hidden")?;
+
+        let options = CollectOptions {
+            include: vec!["*.rs".to_string(), "secret.rs".to_string()],
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?, [PathBuf::from("main.rs")]);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_symlink_policies() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempdir()?;
+        fs::write(outside.path().join("secret.txt"), "outside")?;
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir(root.join("src"))?;
+        fs::write(root.join("src/lib.rs"), "// lib")?;
+        symlink("src/lib.rs", root.join("alias.rs"))?;
+        symlink("src", root.join("src_link"))?;
+        symlink(outside.path().join("secret.txt"), root.join("secret.txt"))?;
+
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::Skip,
+            ..CollectOptions::default()
+        };
+        assert_eq!(collect_rel(root, &options)?, [PathBuf::from("src/lib.rs")]);
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::FollowWithinRoot,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["alias.rs", "src/lib.rs", "src_link/lib.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::Record,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            ["alias.rs", "secret.txt", "src/lib.rs", "src_link"].map(PathBuf::from)
+        );
+
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::Record,
+            ..CollectOptions::default()
+        };
+        let recorded = collect_files_with_options(root, &options)?
+            .into_iter()
+            .filter(is_recorded_symlink)
+            .count();
+        assert_eq!(recorded, 3);
+
+        Ok(())
+    }
+
     #[test]
     fn test_is_lock_file() {
         assert!(is_lock_file(Path::new("package-lock.json")));
@@ -580,6 +1979,149 @@ mod tests {
         assert!(!is_lock_file(Path::new("lockfile.txt")));
     }
 
+    #[test]
+    fn test_collect_files_order() -> Result<()> {
+        use std::time::{Duration, SystemTime};
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join("src/bin"))?;
+        fs::create_dir_all(root.join("docs"))?;
+        let files = [
+            ("Cargo.toml", 30),
+            ("README.md", 20),
+            ("build.rs", 50),
+            ("docs/README.md", 10),
+            ("docs/guide.md", 40),
+            ("src/bin/tool.rs", 60),
+            ("src/lib.rs", 70),
+            ("src/main.rs", 5),
+            ("src/util.rs", 80),
+        ];
+        for (idx, (path, size)) in files.iter().enumerate() {
+            let file = fs::File::create(root.join(path))?;
+            file.set_len(*size)?;
+            file.set_modified(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + idx as u64),
+            )?;
+        }
+
+        assert_eq!(
+            collect_rel(root, &CollectOptions::default())?,
+            [
+                "Cargo.toml",
+                "README.md",
+                "build.rs",
+                "docs/README.md",
+                "docs/guide.md",
+                "src/bin/tool.rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "src/util.rs",
+            ]
+            .map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            directories_first: true,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            [
+                "docs/README.md",
+                "docs/guide.md",
+                "src/bin/tool.rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "src/util.rs",
+                "Cargo.toml",
+                "README.md",
+                "build.rs",
+            ]
+            .map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            order: FileOrder::Size,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?[..3],
+            ["src/util.rs", "src/lib.rs", "src/bin/tool.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            order: FileOrder::Mtime,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?[..3],
+            ["src/util.rs", "src/main.rs", "src/lib.rs"].map(PathBuf::from)
+        );
+        let options = CollectOptions {
+            order: FileOrder::Priority,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            collect_rel(root, &options)?,
+            [
+                "README.md",
+                "docs/README.md",
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/main.rs",
+                "build.rs",
+                "docs/guide.md",
+                "src/bin/tool.rs",
+                "src/util.rs",
+            ]
+            .map(PathBuf::from)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "git")]
+    fn test_path_filter() -> Result<()> {
+        let root = Path::new("/project");
+        let options = CollectOptions {
+            exclude: vec!["generated/".to_string(), "*.snap".to_string()],
+            extensions: ["rs".to_string(), "yml".to_string()].into(),
+            include_hidden: vec![PathBuf::from(".github")],
+            ..CollectOptions::default()
+        };
+        let filter = PathFilter::new(root, &options)?;
+
+        assert!(filter.allows(Path::new("src/main.rs")));
+        let included = CollectOptions {
+            include: vec!["*.rs".to_string()],
+            ..CollectOptions::default()
+        };
+        let filter_included = PathFilter::new(root, &included)?;
+        assert!(filter_included.allows(Path::new("src/main.rs")));
+        assert!(!filter_included.allows(Path::new(".cargo/build.rs")));
+        assert!(!filter_included.allows(Path::new("README.md")));
+        assert!(filter.allows(Path::new(".github/workflows/ci.yml")));
+        assert!(!filter.allows(Path::new("src/generated/api.rs")));
+        assert!(!filter.allows(Path::new("tests/out.snap")));
+        assert!(!filter.allows(Path::new("README.md")));
+        assert!(!filter.allows(Path::new(".cargo/config.yml")));
+        assert!(!filter.allows(Path::new("config/secrets.yml")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_sensitive_file() {
+        assert!(is_sensitive_file(Path::new("id_rsa")));
+        assert!(is_sensitive_file(Path::new("/home/user/keys/id_ed25519")));
+        assert!(is_sensitive_file(Path::new("config/Secrets.yml")));
+        assert!(is_sensitive_file(Path::new("tls/server.key")));
+        assert!(is_sensitive_file(Path::new("android/app/upload.jks")));
+
+        assert!(!is_sensitive_file(Path::new("id_rsa.pub")));
+        assert!(!is_sensitive_file(Path::new("src/secrets.rs")));
+        assert!(!is_sensitive_file(Path::new("credentials.md")));
+    }
+
     #[test]
     fn test_is_hidden() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -598,10 +2140,10 @@ mod tests {
             if entry.path().is_file() {
                 let file_name = entry.file_name().to_string_lossy();
                 if file_name == "visible.rs" {
-                    assert!(!is_hidden(&entry));
+                    assert!(!is_hidden(entry.path()));
                     visible_found = true;
                 } else if file_name == ".hidden" {
-                    assert!(is_hidden(&entry));
+                    assert!(is_hidden(entry.path()));
                     hidden_found = true;
                 }
             }
@@ -0,0 +1,284 @@
+//! Pluggable output formats.
+//!
+//! A bundle is produced by driving an [`OutputFormat`] with a sequence of
+//! events: [`begin`](OutputFormat::begin) once, optionally
+//! [`preamble`](OutputFormat::preamble), then one
+//! [`file`](OutputFormat::file), [`binary`](OutputFormat::binary),
+//! [`symlink`](OutputFormat::symlink) or [`oversized`](OutputFormat::oversized)
+//! call per collected entry, an optional [`contents`](OutputFormat::contents)
+//! and finally [`end`](OutputFormat::end). Each event appends
+//! its rendered text to the buffer it is given; the
+//! [`BundleWriter`](crate::writer::BundleWriter) takes care of reading the
+//! source files and writing the rendered text to the output.
+//!
+//! The built-in formats are listed in [`BundleFormat`]; the default Markdown
+//! layout is implemented by [`MarkdownFormat`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use anyhow::Result;
+//! use src2md::format::{BinaryFile, OutputFormat, TextFile};
+//!
+//! /// Renders a plain list of the bundled paths.
+//! struct PathList;
+//!
+//! impl OutputFormat for PathList {
+//!     fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()> {
+//!         out.push_str(&format!("{}\n", file.path.display()));
+//!         Ok(())
+//!     }
+//!
+//!     fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()> {
+//!         out.push_str(&format!("{} (binary)\n", file.path.display()));
+//!         Ok(())
+//!     }
+//! }
+//! ```
+
+use crate::json::{JsonFormat, JsonLinesFormat};
+use crate::manifest::Manifest;
+use crate::preamble::Preamble;
+use crate::writer::{MarkdownFormat, OVERSIZED_OMITTED_PREFIX};
+use crate::xml::XmlFormat;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// The built-in bundle formats, selectable with `--format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BundleFormat {
+    /// `## path` headings with fenced code blocks.
+    #[default]
+    Markdown,
+    /// A single JSON document with a `files` array.
+    Json,
+    /// A header line followed by one JSON object per file.
+    JsonLines,
+    /// `<document>` elements for pasting into LLM prompts.
+    Xml,
+}
+
+impl BundleFormat {
+    /// Names accepted by [`from_name`](Self::from_name).
+    pub const NAMES: &'static [&'static str] = &["markdown", "md", "json", "jsonl", "xml"];
+
+    /// Parses a format name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            "jsonl" => Some(Self::JsonLines),
+            "xml" => Some(Self::Xml),
+            _ => None,
+        }
+    }
+
+    /// File extension used for default output file names.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::JsonLines => "jsonl",
+            Self::Xml => "xml",
+        }
+    }
+
+    /// Creates a fresh renderer for this format.
+    pub fn output_format(self) -> Box<dyn OutputFormat> {
+        match self {
+            Self::Markdown => Box::new(MarkdownFormat),
+            Self::Json => Box::new(JsonFormat::default()),
+            Self::JsonLines => Box::new(JsonLinesFormat),
+            Self::Xml => Box::new(XmlFormat),
+        }
+    }
+}
+
+/// A text file passed to [`OutputFormat::file`].
+#[derive(Debug, Clone, Copy)]
+pub struct TextFile<'a> {
+    /// Path of the file relative to the project root.
+    pub path: &'a Path,
+    /// Language tag from [`get_language_tag`](crate::utils::get_language_tag),
+    /// empty when unknown.
+    pub language: &'static str,
+    /// The file content, transcoded to UTF-8 if necessary.
+    pub content: &'a str,
+    /// Size of the file on disk in bytes, or of `content` if it was
+    /// [`redacted`](Self::redacted).
+    pub size: u64,
+    /// Name of the original encoding if the file is not UTF-8, e.g.
+    /// `"windows-1252"` or `"UTF-16LE"`.
+    pub charset: Option<&'static str>,
+    /// Hex SHA-256 of the file on disk, if known. Never set for
+    /// [`redacted`](Self::redacted) files.
+    pub sha256: Option<&'a str>,
+    /// Unix permission bits of the file, if known.
+    pub mode: Option<u32>,
+    /// Whether `content` was cut down to its first and last lines because
+    /// the file exceeds the [`FileLimits`](crate::writer::FileLimits). The
+    /// omitted part is replaced by an elision marker line, so `size` and
+    /// `sha256` no longer describe `content`.
+    pub truncated: bool,
+    /// Whether secrets in `content` were replaced with `[REDACTED:kind]`
+    /// placeholders (see [`Redactor`](crate::redact::Redactor)). The
+    /// size and checksum of the original are left out, as they would help
+    /// to guess the secrets.
+    pub redacted: bool,
+}
+
+impl<'a> TextFile<'a> {
+    /// Creates a UTF-8 text file without checksum or mode.
+    pub fn new(path: &'a Path, language: &'static str, content: &'a str) -> Self {
+        Self {
+            path,
+            language,
+            content,
+            size: content.len() as u64,
+            charset: None,
+            sha256: None,
+            mode: None,
+            truncated: false,
+            redacted: false,
+        }
+    }
+}
+
+/// A binary file passed to [`OutputFormat::binary`].
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryFile<'a> {
+    /// Path of the file relative to the project root.
+    pub path: &'a Path,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// The file content, if binary embedding is enabled and the file is
+    /// within the size limit (see [`BinaryMode`](crate::writer::BinaryMode)).
+    pub content: Option<&'a [u8]>,
+    /// Hex SHA-256 of the file on disk, if known.
+    pub sha256: Option<&'a str>,
+    /// Unix permission bits of the file, if known.
+    pub mode: Option<u32>,
+}
+
+impl<'a> BinaryFile<'a> {
+    /// Creates a binary file without content, checksum or mode.
+    pub fn new(path: &'a Path, size: u64) -> Self {
+        Self {
+            path,
+            size,
+            content: None,
+            sha256: None,
+            mode: None,
+        }
+    }
+}
+
+/// A symbolic link passed to [`OutputFormat::symlink`].
+#[derive(Debug, Clone, Copy)]
+pub struct SymlinkFile<'a> {
+    /// Path of the link relative to the project root.
+    pub path: &'a Path,
+    /// The link target exactly as stored in the link, usually relative to
+    /// the directory containing the link.
+    pub target: &'a str,
+}
+
+/// A file left out of the bundle because it exceeds the
+/// [`FileLimits`](crate::writer::FileLimits), passed to
+/// [`OutputFormat::oversized`].
+#[derive(Debug, Clone, Copy)]
+pub struct OversizedFile<'a> {
+    /// Path of the file relative to the project root.
+    pub path: &'a Path,
+    /// Size of the file on disk in bytes.
+    pub size: u64,
+    /// Number of lines, if the file was read to count them.
+    pub lines: Option<usize>,
+}
+
+impl OversizedFile<'_> {
+    /// Returns the placeholder describing the omitted file, e.g.
+    /// `(omitted: file too large, 41943040 bytes)`.
+    pub fn marker(&self) -> String {
+        match self.lines {
+            Some(lines) => format!(
+                "{OVERSIZED_OMITTED_PREFIX}, {} bytes, {lines} lines)",
+                self.size
+            ),
+            None => format!("{OVERSIZED_OMITTED_PREFIX}, {} bytes)", self.size),
+        }
+    }
+}
+
+/// The table of contents of one bundle part, passed to
+/// [`OutputFormat::contents`].
+#[derive(Debug, Clone, Copy)]
+pub struct Contents<'a> {
+    /// Paths of the entries written to the part relative to the project
+    /// root, in order.
+    pub paths: &'a [PathBuf],
+    /// Level-1 headings written to the part before its entries, i.e. the
+    /// [`Preamble::headings`] of the first part.
+    pub headings: &'a [&'static str],
+}
+
+/// A renderer for src2md bundles.
+///
+/// Implementations append their output for each event to `out`. Events are
+/// always delivered in order: `begin`, an optional `preamble`, any number of
+/// `file`/`binary`/`symlink`/`oversized`, an optional `contents`, `end`.
+///
+/// When a bundle is split into parts, the same renderer is used for every
+/// part and the sequence starts over with `begin`, so implementations must
+/// reset any per-document state there. An entry may also be rendered twice
+/// when it turns out not to fit into the current part; only the second
+/// rendering is written. Likewise, `end` is called after each entry of a
+/// split bundle to measure the footer against the part budget, and that
+/// output is discarded, so `contents` and `end` must not change the
+/// renderer's state.
+pub trait OutputFormat: Send {
+    /// Called once before the first entry, with the manifest describing the
+    /// bundle if the writer was given one.
+    fn begin(&mut self, _manifest: Option<&Manifest>, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after `begin` of the first part when the writer was given a
+    /// [`Preamble`] describing the whole bundle. Formats without an overview
+    /// section can ignore it.
+    fn preamble(&mut self, _preamble: &Preamble, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for every text file.
+    fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()>;
+
+    /// Called for every file detected as binary.
+    fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()>;
+
+    /// Called for every symbolic link collected with
+    /// [`SymlinkPolicy::Record`](crate::filewalker::SymlinkPolicy::Record).
+    /// Formats that cannot represent links can ignore them.
+    fn symlink(&mut self, _link: &SymlinkFile<'_>, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for every file that exceeds the
+    /// [`FileLimits`](crate::writer::FileLimits) when they are set to omit
+    /// such files. Formats without a placeholder for them can ignore it.
+    fn oversized(&mut self, _file: &OversizedFile<'_>, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the last entry of every part when the writer was given
+    /// a [`Preamble`] asking for a table of contents, with the entries
+    /// actually written to that part. Formats without one can ignore it.
+    fn contents(&mut self, _contents: &Contents<'_>, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last entry.
+    fn end(&mut self, _out: &mut String) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+//! Detection of minified and machine-generated files.
+//!
+//! Build output that is checked in next to the sources (bundled and
+//! minified JavaScript, source maps, code from protobuf or GraphQL
+//! generators) can easily outweigh the hand-written code of a project.
+//! [`detect_generated`] recognizes such files by their content:
+//!
+//! - Minified files have very long lines and almost no whitespace
+//! - Source maps are JSON objects with `version` and `mappings` members
+//! - Generated files say so in a comment near the top, following the
+//!   conventions of Go, Meta's tooling and .NET. Only the comment syntax of
+//!   the file's language counts, so a Markdown heading is not a comment.
+//!
+//! Only the first 64 KiB of a file are inspected. Binary files are never
+//! reported.
+
+use crate::utils::get_language_tag;
+use content_inspector::inspect;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of bytes inspected at the start of a file.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Files smaller than this are never considered minified.
+const MINIFIED_MIN_SIZE: usize = 1024;
+
+/// Smallest average line length of a minified file, in bytes.
+const MINIFIED_LINE_LENGTH: usize = 250;
+
+/// Largest share of whitespace in a minified file, in percent.
+const MINIFIED_MAX_WHITESPACE_PERCENT: usize = 10;
+
+/// Number of lines at the start of a file searched for [`GENERATED_MARKERS`].
+const MARKER_LINES: usize = 10;
+
+/// Markers of generated files, searched in comments near the top.
+const GENERATED_MARKERS: &[&str] = &["@generated", "DO NOT EDIT", "<auto-generated"];
+
+/// Prefixes of comment lines in languages whose comment syntax is unknown.
+/// A `*` alone is left out, as it starts list items as often as the lines
+/// of block comments.
+const COMMENT_PREFIXES: &[&str] = &["//", "/*", "#", "<!--", "--", ";"];
+
+/// Why a file is considered generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratedKind {
+    /// Very long lines with little whitespace, e.g. `dist/app.min.js`.
+    Minified,
+    /// A JavaScript or CSS source map.
+    SourceMap,
+    /// A comment near the top marks the file as generated.
+    Marked,
+}
+
+impl fmt::Display for GeneratedKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Minified => "minified file",
+            Self::SourceMap => "source map",
+            Self::Marked => "marked as generated",
+        })
+    }
+}
+
+/// Reads the start of the file at `path` and reports whether it looks
+/// minified or generated. Unreadable files are not reported.
+pub fn detect_generated(path: &Path) -> Option<GeneratedKind> {
+    let mut sample = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(SAMPLE_SIZE as u64).read_to_end(&mut sample))
+        .ok()?;
+    detect_generated_content(path, &sample)
+}
+
+/// Like [`detect_generated`], for the content of a file that is already in
+/// memory. Only the first 64 KiB of `content` are inspected; `path` selects
+/// the comment syntax searched for markers.
+pub fn detect_generated_content(path: &Path, content: &[u8]) -> Option<GeneratedKind> {
+    let sample = &content[..content.len().min(SAMPLE_SIZE)];
+    if inspect(sample).is_binary() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(sample);
+
+    if is_marked(&text, comment_prefixes(path)) {
+        Some(GeneratedKind::Marked)
+    } else if is_source_map(&text) {
+        Some(GeneratedKind::SourceMap)
+    } else if is_minified(sample) {
+        Some(GeneratedKind::Minified)
+    } else {
+        None
+    }
+}
+
+/// Prefixes of comment lines in the language of `path`.
+fn comment_prefixes(path: &Path) -> &'static [&'static str] {
+    match get_language_tag(path) {
+        "rust" | "go" | "gomod" | "c" | "cpp" | "csharp" | "objectivec" | "java" | "kotlin"
+        | "scala" | "groovy" | "gradle" | "swift" | "javascript" | "jsx" | "typescript" | "tsx"
+        | "protobuf" | "thrift" | "solidity" | "zig" | "odin" | "v" | "d" | "cuda" | "glsl"
+        | "hlsl" | "prisma" | "move" | "cairo" | "jsonc" | "json5" | "fsharp" | "scss" | "less"
+        | "sass" => &["//", "/*", "*"],
+        "css" => &["/*", "*"],
+        "php" => &["//", "/*", "*", "#"],
+        "python" | "cython" | "ruby" | "bash" | "zsh" | "fish" | "perl" | "r" | "julia"
+        | "elixir" | "nim" | "nix" | "yaml" | "toml" | "hcl" | "graphql" | "makefile"
+        | "dockerfile" | "cmake" | "just" | "powershell" | "conf" | "properties" | "dotenv"
+        | "gitignore" | "editorconfig" | "procfile" => &["#"],
+        "sql" | "plsql" => &["--", "/*", "*"],
+        "lua" | "luau" | "haskell" | "elm" | "purescript" | "dhall" => &["--"],
+        "lisp" | "clojure" | "scheme" | "racket" | "asm" | "nasm" => &[";"],
+        "ini" => &[";", "#"],
+        "erlang" | "latex" | "matlab" => &["%"],
+        "ocaml" => &["(*"],
+        "html" | "xml" | "svg" | "markdown" | "mdx" | "vue" | "svelte" | "astro" => &["<!--"],
+        _ => COMMENT_PREFIXES,
+    }
+}
+
+/// Returns true if a comment among the first lines carries a generated marker.
+fn is_marked(text: &str, prefixes: &[&str]) -> bool {
+    text.lines().take(MARKER_LINES).any(|line| {
+        let line = line.trim_start();
+        prefixes.iter().any(|prefix| line.starts_with(prefix))
+            && GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+    })
+}
+
+/// Returns true for a JSON object that starts like a source map.
+fn is_source_map(text: &str) -> bool {
+    let text = text.trim_start_matches('\u{feff}');
+    // Source maps served to browsers may carry an anti-XSSI prefix
+    let text = text.strip_prefix(")]}'").unwrap_or(text).trim_start();
+    text.starts_with('{') && text.contains("\"version\"") && text.contains("\"mappings\"")
+}
+
+/// Returns true if the sample has very long lines and little whitespace.
+fn is_minified(sample: &[u8]) -> bool {
+    if sample.len() < MINIFIED_MIN_SIZE {
+        return false;
+    }
+    let lines = sample.split(|&b| b == b'\n').count();
+    let whitespace = sample.iter().filter(|b| b.is_ascii_whitespace()).count();
+    sample.len() / lines >= MINIFIED_LINE_LENGTH
+        && whitespace * 100 < sample.len() * MINIFIED_MAX_WHITESPACE_PERCENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_minified_javascript() {
+        let minified = "var a=function(b){return b*2};".repeat(100);
+        assert_eq!(
+            detect_generated_content(Path::new("app.min.js"), minified.as_bytes()),
+            Some(GeneratedKind::Minified)
+        );
+
+        let readable = "function double(value) {\n    return value * 2;\n}\n".repeat(100);
+        assert_eq!(
+            detect_generated_content(Path::new("app.js"), readable.as_bytes()),
+            None
+        );
+
+        // Long prose lines have plenty of whitespace
+        let prose = format!(
+            "{}\n",
+            "The quick brown fox jumps over the lazy dog. ".repeat(40)
+        );
+        assert_eq!(
+            detect_generated_content(Path::new("notes.txt"), prose.repeat(5).as_bytes()),
+            None
+        );
+
+        // Small files are never minified
+        assert_eq!(
+            detect_generated_content(Path::new("a.css"), b"a{b:c}"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detects_source_maps() {
+        let map = r#"{"version":3,"file":"app.js","sources":["app.ts"],"mappings":"AAAA"}"#;
+        assert_eq!(
+            detect_generated_content(Path::new("app.js.map"), map.as_bytes()),
+            Some(GeneratedKind::SourceMap)
+        );
+        assert_eq!(
+            detect_generated_content(Path::new("app.js.map"), format!(")]}}'\n{map}").as_bytes()),
+            Some(GeneratedKind::SourceMap)
+        );
+        assert_eq!(
+            detect_generated_content(
+                Path::new("package.json"),
+                br#"{"name":"app","version":"1.0.0"}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detects_generated_markers() {
+        for (path, header) in [
+            (
+                "api.pb.go",
+                "// Code generated by protoc-gen-go. DO NOT EDIT.\n",
+            ),
+            ("Schema.js", "/**\n * @generated SignedSource<<abc>>\n */\n"),
+            ("requirements.txt", "# @generated by pip-compile\n"),
+            ("Model.cs", "// <auto-generated>\n"),
+            ("schema.graphql", "# DO NOT EDIT\n"),
+            ("api.html", "<!-- @generated -->\n"),
+        ] {
+            let content = format!("{header}package main\n");
+            assert_eq!(
+                detect_generated_content(Path::new(path), content.as_bytes()),
+                Some(GeneratedKind::Marked),
+                "{header:?}"
+            );
+        }
+
+        // Markers outside of comments or further down are not reported
+        assert_eq!(
+            detect_generated_content(Path::new("lib.rs"), b"let marker = \"DO NOT EDIT\";\n"),
+            None
+        );
+        let late = format!("{}// DO NOT EDIT\n", "fn f() {}\n".repeat(MARKER_LINES));
+        assert_eq!(
+            detect_generated_content(Path::new("lib.rs"), late.as_bytes()),
+            None
+        );
+
+        // Markdown headings and list items are not comments
+        for content in [
+            "# DO NOT EDIT this section by hand\n",
+            "* @generated files are skipped by default\n",
+        ] {
+            assert_eq!(
+                detect_generated_content(Path::new("README.md"), content.as_bytes()),
+                None,
+                "{content:?}"
+            );
+        }
+        // Nor is a Rust attribute
+        assert_eq!(
+            detect_generated_content(Path::new("lib.rs"), b"#[doc = \"DO NOT EDIT\"]\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ignores_binary_content() {
+        let data: Vec<u8> = (0..4096).map(|i| (i % 256) as u8).collect();
+        assert_eq!(detect_generated_content(Path::new("data.bin"), &data), None);
+    }
+}
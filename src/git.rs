@@ -1,8 +1,11 @@
-//! Git repository cloning support.
+//! Git repository support.
 //!
 //! This module provides functionality to clone git repositories into temporary
-//! directories for processing by src2md. It is only available when the `git`
-//! feature is enabled.
+//! directories for processing by src2md, to find the files changed in a
+//! repository for review bundles ([`changed_files`]), and to collect the
+//! files of any revision without checking it out ([`collect_revision`]) or
+//! as they are staged in the index ([`collect_staged`]). It is only
+//! available when the `git` feature is enabled.
 //!
 //! # Example
 //!
@@ -14,10 +17,21 @@
 //! // temp_dir is dropped when it goes out of scope, cleaning up the clone
 //! ```
 
+use crate::filewalker::{
+    CollectOptions, IgnoreRules, PathFilter, SymlinkPolicy, VirtualContent, VirtualEntry,
+    sort_entries,
+};
 use anyhow::{Context, Result};
-use git2::{FetchOptions, RemoteCallbacks, build::RepoBuilder};
-use log::{debug, info};
-use std::path::PathBuf;
+use git2::{
+    Commit, Delta, DiffOptions, FetchOptions, FileMode, ObjectType, Odb, Oid, RemoteCallbacks,
+    Repository, TreeWalkMode, TreeWalkResult, build::RepoBuilder,
+};
+use log::{debug, info, trace};
+use std::cell::RefCell;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tempfile::TempDir;
 
 /// Result of cloning a repository.
@@ -143,9 +157,790 @@ pub fn repo_name_from_url(url: &str) -> Option<String> {
     }
 }
 
+/// Returns the id of the commit checked out in the repository containing `path`.
+///
+/// Returns `None` if `path` is not inside a git repository or the repository
+/// has no commits yet.
+pub fn head_commit(path: &Path) -> Option<String> {
+    let repo = Repository::discover(path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// Opens the git repository containing `project_root`.
+///
+/// Also returns the path of `project_root` relative to the working
+/// directory, since git paths are relative to the working directory, which
+/// may be above the project root.
+pub(crate) fn open_repository(project_root: &Path) -> Result<(Repository, PathBuf)> {
+    let repo = Repository::discover(project_root)
+        .with_context(|| format!("Not inside a git repository: {}", project_root.display()))?;
+    let workdir = repo
+        .workdir()
+        .context("Bare repositories are not supported")?;
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let prefix = canonical(project_root)
+        .strip_prefix(canonical(workdir))
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Ok((repo, prefix))
+}
+
+/// Resolves a revision such as a branch, tag or commit hash to a commit.
+pub(crate) fn resolve_commit<'r>(repo: &'r Repository, rev: &str) -> Result<Commit<'r>> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Unknown revision: {rev}"))
+}
+
+/// What the working tree is compared against by [`changed_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeBase {
+    /// Everything changed since the merge base of this revision and `HEAD`,
+    /// like `git diff <rev>...`, plus uncommitted changes and untracked
+    /// files. This is what a branch touches when `<rev>` is the branch it
+    /// was started from.
+    Since(String),
+    /// The changes staged in the index, like `git diff --cached`.
+    Staged,
+}
+
+/// Files that differ from a [`ChangeBase`], relative to the project root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedFiles {
+    /// Added and modified files. Renamed files are listed as added under
+    /// their new path and deleted under their old path.
+    pub changed: Vec<PathBuf>,
+    /// Deleted files.
+    pub deleted: Vec<PathBuf>,
+}
+
+/// Finds the files that differ from `base` in the git repository containing
+/// `project_root`.
+///
+/// Only files inside `project_root` are returned. Files ignored by git are
+/// never reported.
+///
+/// # Errors
+///
+/// Returns an error if `project_root` is not inside a non-bare git
+/// repository or the revision cannot be resolved to a commit.
+pub fn changed_files(project_root: &Path, base: &ChangeBase) -> Result<ChangedFiles> {
+    let (repo, prefix) = open_repository(project_root)?;
+    let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+
+    let diff = match base {
+        ChangeBase::Since(rev) => {
+            let commit = resolve_commit(&repo, rev)?;
+            // Compare against the fork point, so changes made on `rev` since
+            // then do not show up as reverted
+            let base_commit = head
+                .as_ref()
+                .and_then(|head| repo.merge_base(commit.id(), head.id()).ok())
+                .and_then(|id| repo.find_commit(id).ok())
+                .unwrap_or(commit);
+            debug!("Listing files changed since {}", base_commit.id());
+
+            let mut options = DiffOptions::new();
+            options.include_untracked(true).recurse_untracked_dirs(true);
+            repo.diff_tree_to_workdir_with_index(Some(&base_commit.tree()?), Some(&mut options))?
+        }
+        ChangeBase::Staged => {
+            let head_tree = head.map(|commit| commit.tree()).transpose()?;
+            repo.diff_tree_to_index(head_tree.as_ref(), None, None)?
+        }
+    };
+
+    let relative = |path: Option<&Path>| -> Option<PathBuf> {
+        Some(path?.strip_prefix(&prefix).ok()?.to_path_buf())
+    };
+
+    let mut changes = ChangedFiles::default();
+    for delta in diff.deltas() {
+        match delta.status() {
+            Delta::Deleted => changes.deleted.extend(relative(delta.old_file().path())),
+            Delta::Unmodified | Delta::Ignored | Delta::Unreadable => {}
+            _ => changes.changed.extend(relative(delta.new_file().path())),
+        }
+    }
+    changes.changed.sort();
+    changes.changed.dedup();
+    changes.deleted.sort();
+    changes.deleted.dedup();
+    Ok(changes)
+}
+
+/// A blob of a git repository, read from the object database when the file
+/// is written rather than when it is collected.
+///
+/// Blobs are equal if they have the same id, and thus the same content.
+#[derive(Clone)]
+pub struct GitBlob {
+    /// Path of the `.git` directory of the repository.
+    git_dir: Arc<Path>,
+    id: Oid,
+    size: u64,
+}
+
+thread_local! {
+    /// Repository opened by this thread to read blobs, so that the workers
+    /// reading files concurrently do not share one.
+    static BLOB_REPOSITORY: RefCell<Option<(Arc<Path>, Repository)>> = const { RefCell::new(None) };
+}
+
+impl GitBlob {
+    /// Id of the blob.
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    /// Size of the blob in bytes, known without reading it.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Reads the content of the blob.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the blob cannot be read from the repository.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        // A repository can only be used by one thread at a time, so each
+        // thread opens its own
+        BLOB_REPOSITORY.with_borrow_mut(|cached| {
+            if !matches!(cached, Some((git_dir, _)) if *git_dir == self.git_dir) {
+                let repo = Repository::open(&self.git_dir).with_context(|| {
+                    format!("Failed to open repository {}", self.git_dir.display())
+                })?;
+                *cached = Some((Arc::clone(&self.git_dir), repo));
+            }
+            let (_, repo) = cached.as_ref().expect("repository is open");
+            let blob = repo
+                .find_blob(self.id)
+                .with_context(|| format!("Failed to read blob {}", self.id))?;
+            Ok(blob.content().to_vec())
+        })
+    }
+}
+
+impl fmt::Debug for GitBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitBlob")
+            .field("id", &self.id)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl PartialEq for GitBlob {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for GitBlob {}
+
+/// Files of a commit collected by [`collect_revision`].
+#[derive(Debug, Clone)]
+pub struct RevisionFiles {
+    /// Id of the commit the revision resolved to.
+    pub commit: String,
+    /// The collected files, in the order given by the collect options.
+    pub entries: Vec<VirtualEntry>,
+}
+
+/// Collects the files of the project at revision `rev`, such as a branch,
+/// tag or commit hash, reading them from the git object database instead
+/// of the working tree.
+///
+/// The project root maps to the same directory of the commit's tree, and
+/// entries carry the paths the files would have on disk below it. The
+/// filters of `options` apply as they do to the working tree, with the
+/// `.gitignore` and `.src2mdignore` files read from the commit's tree
+/// rather than from disk, so files that were force-added or that the
+/// project excludes from bundles are left out. Symbolic links
+/// are followed to files inside the project root, and links to directories
+/// are skipped. Every file gets the commit time as its modification time.
+///
+/// Only the ids and sizes of the blobs are collected: their content is read
+/// by the writer (see [`VirtualContent::Blob`]), which also leaves out
+/// files over the size limit before reading them, and recognizes minified,
+/// generated and src2md output files like those of the working tree (see
+/// [`BundleWriter::set_skip_generated`](crate::writer::BundleWriter::set_skip_generated)).
+///
+/// # Errors
+///
+/// Returns an error if `project_root` is not inside a non-bare git
+/// repository, the revision cannot be resolved to a commit, or the project
+/// root does not exist at that commit.
+pub fn collect_revision(
+    project_root: &Path,
+    rev: &str,
+    options: &CollectOptions,
+) -> Result<RevisionFiles> {
+    let (repo, prefix) = open_repository(project_root)?;
+    let commit = resolve_commit(&repo, rev)?;
+    let root_tree = commit.tree()?;
+    let tree = if prefix.as_os_str().is_empty() {
+        root_tree.clone()
+    } else {
+        root_tree
+            .get_path(&prefix)
+            .and_then(|entry| repo.find_tree(entry.id()))
+            .with_context(|| format!("{} does not exist at {rev}", prefix.display()))?
+    };
+    let seconds = u64::try_from(commit.time().seconds()).unwrap_or(0);
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+
+    let mut blobs = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(ObjectType::Blob)
+            && let Some(name) = entry.name()
+        {
+            blobs.push((Path::new(dir).join(name), entry.id(), entry.filemode()));
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    // Ignore files are read from the same tree, as committed
+    let ignore_rules = IgnoreRules::new(
+        project_root,
+        options,
+        blobs.iter().map(|(rel_path, _, _)| rel_path.as_path()),
+        |path| {
+            let entry = tree.get_path(path).ok()?;
+            let blob = repo.find_blob(entry.id()).ok()?;
+            Some(blob.content().to_vec())
+        },
+    );
+    let filter = PathFilter::new(project_root, options)?.with_ignore_rules(ignore_rules);
+    let collector = BlobCollector::new(&repo, project_root, &prefix, options.symlinks)?;
+    let lookup = |path: &Path| {
+        let entry = root_tree.get_path(path).ok()?;
+        (entry.kind() == Some(ObjectType::Blob)).then(|| (entry.id(), entry.filemode()))
+    };
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for (rel_path, id, filemode) in blobs {
+        if !filter.allows(&rel_path) {
+            trace!("Skipping {}", rel_path.display());
+            skipped += 1;
+            continue;
+        }
+        match collector.entry(&rel_path, id, filemode, modified, lookup)? {
+            Some(entry) => entries.push(entry),
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        debug!("Skipped {skipped} file(s) of {rev}");
+    }
+    info!(
+        "Collected {} files from {rev} ({})",
+        entries.len(),
+        commit.id()
+    );
+    Ok(RevisionFiles {
+        commit: commit.id().to_string(),
+        entries: sort_entries(
+            entries,
+            project_root,
+            options.order,
+            options.directories_first,
+        ),
+    })
+}
+
+/// Files with changes staged for commit, collected by [`collect_staged`].
+#[derive(Debug, Clone)]
+pub struct StagedFiles {
+    /// The staged files with their content in the index, in the order given
+    /// by the collect options.
+    pub entries: Vec<VirtualEntry>,
+    /// Files deleted in the index, relative to the project root.
+    pub deleted: Vec<PathBuf>,
+}
+
+/// Collects the files with changes staged for commit (see
+/// [`ChangeBase::Staged`]), reading them from the index instead of the
+/// working tree, so that changes that are not staged are left out.
+///
+/// The filters of `options` apply like for [`collect_revision`], and so do
+/// the ignore files in the project, as they are in the working tree.
+/// Symbolic links are followed to staged files inside the project root.
+/// Every file gets the modification time recorded in the index, and its
+/// content is read from the index when it is written.
+///
+/// # Errors
+///
+/// Returns an error if `project_root` is not inside a non-bare git
+/// repository or the index cannot be read.
+pub fn collect_staged(project_root: &Path, options: &CollectOptions) -> Result<StagedFiles> {
+    let changes = changed_files(project_root, &ChangeBase::Staged)?;
+    let (repo, prefix) = open_repository(project_root)?;
+    let index = repo.index().context("Failed to read the git index")?;
+
+    // Ignore files apply like in the working tree, where they are read from
+    let ignore_rules = IgnoreRules::new(
+        project_root,
+        options,
+        changes.changed.iter().map(PathBuf::as_path),
+        |path| std::fs::read(project_root.join(path)).ok(),
+    );
+    let filter = PathFilter::new(project_root, options)?.with_ignore_rules(ignore_rules);
+    let collector = BlobCollector::new(&repo, project_root, &prefix, options.symlinks)?;
+    // Submodules are commits rather than blobs and are left out
+    let blob_entry = |path: &Path| {
+        index
+            .get_path(path, 0)
+            .filter(|entry| entry.mode != u32::from(FileMode::Commit))
+    };
+    let lookup = |path: &Path| blob_entry(path).map(|entry| (entry.id, entry.mode as i32));
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for rel_path in changes.changed {
+        let staged = blob_entry(&prefix.join(&rel_path));
+        let Some(staged) = staged.filter(|_| filter.allows(&rel_path)) else {
+            trace!("Skipping {}", rel_path.display());
+            skipped += 1;
+            continue;
+        };
+        let seconds = u64::try_from(staged.mtime.seconds()).unwrap_or(0);
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        match collector.entry(&rel_path, staged.id, staged.mode as i32, modified, lookup)? {
+            Some(entry) => entries.push(entry),
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        debug!("Skipped {skipped} staged file(s)");
+    }
+    info!("Collected {} staged files", entries.len());
+    Ok(StagedFiles {
+        entries: sort_entries(
+            entries,
+            project_root,
+            options.order,
+            options.directories_first,
+        ),
+        deleted: changes.deleted,
+    })
+}
+
+/// Turns the blobs of a tree or the index into collected entries.
+struct BlobCollector<'a> {
+    repo: &'a Repository,
+    odb: Odb<'a>,
+    /// Path of the `.git` directory, shared by the collected blobs.
+    git_dir: Arc<Path>,
+    project_root: &'a Path,
+    /// Path of the project root relative to the working directory.
+    prefix: &'a Path,
+    symlinks: SymlinkPolicy,
+}
+
+impl<'a> BlobCollector<'a> {
+    fn new(
+        repo: &'a Repository,
+        project_root: &'a Path,
+        prefix: &'a Path,
+        symlinks: SymlinkPolicy,
+    ) -> Result<Self> {
+        Ok(Self {
+            repo,
+            odb: repo.odb()?,
+            git_dir: Arc::from(repo.path()),
+            project_root,
+            prefix,
+            symlinks,
+        })
+    }
+
+    /// Returns the entry for the blob `id` at `rel_path`, or `None` if it is
+    /// skipped. Symbolic links are handled as the symlink policy says;
+    /// `lookup` finds the blob id and mode of a link target by its path
+    /// relative to the working directory.
+    fn entry(
+        &self,
+        rel_path: &Path,
+        id: Oid,
+        filemode: i32,
+        modified: SystemTime,
+        lookup: impl Fn(&Path) -> Option<(Oid, i32)>,
+    ) -> Result<Option<VirtualEntry>> {
+        let (id, filemode) = if filemode == i32::from(FileMode::Link) {
+            let link_blob = self.repo.find_blob(id)?;
+            let target = String::from_utf8_lossy(link_blob.content()).into_owned();
+            match self.symlinks {
+                SymlinkPolicy::Record => {
+                    return Ok(Some(VirtualEntry {
+                        path: self.project_root.join(rel_path),
+                        content: VirtualContent::Symlink(target),
+                        mode: None,
+                        modified,
+                    }));
+                }
+                SymlinkPolicy::FollowWithinRoot => {
+                    let link = self.prefix.join(rel_path);
+                    let Some((id, filemode)) = resolve_link(self.prefix, &link, &target)
+                        .and_then(|path| lookup(&path))
+                        .filter(|(_, filemode)| *filemode != i32::from(FileMode::Link))
+                    else {
+                        debug!(
+                            "Skipping symlink to a directory or outside the project: {}",
+                            rel_path.display()
+                        );
+                        return Ok(None);
+                    };
+                    (id, filemode)
+                }
+                SymlinkPolicy::Skip => {
+                    trace!("Skipping symlink: {}", rel_path.display());
+                    return Ok(None);
+                }
+            }
+        } else {
+            (id, filemode)
+        };
+
+        // Only the header is read, so that the content is not held in memory
+        let (size, _) = self.odb.read_header(id)?;
+        let blob = GitBlob {
+            git_dir: Arc::clone(&self.git_dir),
+            id,
+            size: size as u64,
+        };
+
+        let executable = filemode == i32::from(FileMode::BlobExecutable);
+        Ok(Some(VirtualEntry {
+            path: self.project_root.join(rel_path),
+            content: VirtualContent::Blob(blob),
+            mode: Some(if executable { 0o755 } else { 0o644 }),
+            modified,
+        }))
+    }
+}
+
+/// Resolves the `target` of the symbolic link at `link` to a path below
+/// `prefix`, both relative to the working directory.
+///
+/// Returns `None` if the target is outside the project root.
+fn resolve_link(prefix: &Path, link: &Path, target: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in link.parent()?.join(target).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::ParentDir => {
+                if !path.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    path.starts_with(prefix).then_some(path)
+}
+
+#[cfg(test)]
+#[path = "../tests/support/git.rs"]
+pub(crate) mod test_support;
+
 #[cfg(test)]
 mod tests {
+    use super::test_support::commit_all;
     use super::*;
+    use crate::filewalker::IgnorePolicy;
+
+    /// Returns the collected `entries` by path relative to `project_root`,
+    /// with the content of blobs read.
+    fn contents(
+        entries: Vec<VirtualEntry>,
+        project_root: &Path,
+    ) -> Result<Vec<(PathBuf, VirtualContent)>> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                let path = entry.path.strip_prefix(project_root)?.to_path_buf();
+                let content = match entry.content {
+                    VirtualContent::Blob(blob) => VirtualContent::File(Arc::from(blob.read()?)),
+                    content => content,
+                };
+                Ok((path, content))
+            })
+            .collect()
+    }
+
+    fn file(path: &str, content: &str) -> (PathBuf, VirtualContent) {
+        (
+            PathBuf::from(path),
+            VirtualContent::File(Arc::from(content.as_bytes())),
+        )
+    }
+
+    #[test]
+    fn test_head_commit() -> Result<()> {
+        let dir = TempDir::new()?;
+        assert_eq!(head_commit(dir.path()), None);
+
+        let repo = Repository::init(dir.path())?;
+        assert_eq!(head_commit(dir.path()), None);
+
+        let signature = git2::Signature::now("src2md", "src2md@example.com")?;
+        let tree_id = repo.index()?.write_tree()?;
+        let tree = repo.find_tree(tree_id)?;
+        let commit_id = repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])?;
+
+        assert_eq!(head_commit(dir.path()), Some(commit_id.to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_files() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let repo = Repository::init(root)?;
+        std::fs::create_dir(root.join("sub"))?;
+        for name in ["a.rs", "b.rs", "c.rs", "sub/d.rs", "sub/e.rs"] {
+            std::fs::write(root.join(name), name)?;
+        }
+        std::fs::write(root.join(".gitignore"), "*.log\n")?;
+        commit_all(&repo, "base")?;
+
+        std::fs::write(root.join("a.rs"), "changed")?;
+        std::fs::remove_file(root.join("c.rs"))?;
+        commit_all(&repo, "change")?;
+
+        // Uncommitted, untracked and ignored files
+        std::fs::write(root.join("sub/d.rs"), "changed")?;
+        std::fs::write(root.join("sub/new.rs"), "new")?;
+        std::fs::write(root.join("debug.log"), "ignored")?;
+
+        let paths = |paths: &[&str]| -> Vec<PathBuf> { paths.iter().map(PathBuf::from).collect() };
+
+        let changes = changed_files(root, &ChangeBase::Since("HEAD~1".to_string()))?;
+        assert_eq!(changes.changed, paths(&["a.rs", "sub/d.rs", "sub/new.rs"]));
+        assert_eq!(changes.deleted, paths(&["c.rs"]));
+
+        // Paths are relative to a project root below the working directory
+        let changes = changed_files(&root.join("sub"), &ChangeBase::Since("HEAD~1".to_string()))?;
+        assert_eq!(changes.changed, paths(&["d.rs", "new.rs"]));
+        assert!(changes.deleted.is_empty());
+
+        assert_eq!(
+            changed_files(root, &ChangeBase::Staged)?,
+            ChangedFiles::default()
+        );
+        let mut index = repo.index()?;
+        index.add_path(Path::new("sub/d.rs"))?;
+        index.remove_path(Path::new("b.rs"))?;
+        index.write()?;
+        let changes = changed_files(root, &ChangeBase::Staged)?;
+        assert_eq!(changes.changed, paths(&["sub/d.rs"]));
+        assert_eq!(changes.deleted, paths(&["b.rs"]));
+
+        assert!(changed_files(root, &ChangeBase::Since("no-such-rev".to_string())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_staged() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let repo = Repository::init(root)?;
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            std::fs::write(root.join(name), name)?;
+        }
+        commit_all(&repo, "base")?;
+
+        std::fs::write(root.join("a.rs"), "staged")?;
+        std::fs::write(root.join("Cargo.lock"), "lock")?;
+        let mut index = repo.index()?;
+        index.add_path(Path::new("a.rs"))?;
+        index.add_path(Path::new("Cargo.lock"))?;
+        index.remove_path(Path::new("c.rs"))?;
+        index.write()?;
+        // Changes that are not staged
+        std::fs::write(root.join("a.rs"), "not staged")?;
+        std::fs::write(root.join("b.rs"), "not staged")?;
+
+        let staged = collect_staged(root, &CollectOptions::default())?;
+        assert_eq!(contents(staged.entries, root)?, [file("a.rs", "staged")]);
+        assert_eq!(staged.deleted, [PathBuf::from("c.rs")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_staged_ignore_files() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let repo = Repository::init(root)?;
+        std::fs::create_dir(root.join("sub"))?;
+        std::fs::write(root.join(".src2mdignore"), "secret.rs\n")?;
+        std::fs::write(root.join("sub/.gitignore"), "*.tmp\n")?;
+        for name in ["a.rs", "secret.rs", "sub/secret.rs", "sub/b.tmp"] {
+            std::fs::write(root.join(name), name)?;
+        }
+        let mut index = repo.index()?;
+        for name in ["a.rs", "secret.rs", "sub/secret.rs", "sub/b.tmp"] {
+            index.add_path(Path::new(name))?;
+        }
+        index.write()?;
+
+        let staged = collect_staged(root, &CollectOptions::default())?;
+        assert_eq!(contents(staged.entries, root)?, [file("a.rs", "a.rs")]);
+
+        let options = CollectOptions {
+            ignore: IgnorePolicy {
+                src2mdignore: false,
+                ..IgnorePolicy::default()
+            },
+            ..CollectOptions::default()
+        };
+        let staged = collect_staged(root, &options)?;
+        assert_eq!(
+            contents(staged.entries, root)?,
+            [
+                file("a.rs", "a.rs"),
+                file("secret.rs", "secret.rs"),
+                file("sub/secret.rs", "sub/secret.rs"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_revision() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let repo = Repository::init(root)?;
+        std::fs::create_dir(root.join("sub"))?;
+        std::fs::write(root.join("a.rs"), "old")?;
+        std::fs::write(root.join("sub/b.rs"), "b")?;
+        std::fs::write(root.join("Cargo.lock"), "lock")?;
+        commit_all(&repo, "base")?;
+
+        std::fs::write(root.join("a.rs"), "new")?;
+        std::fs::write(root.join("c.rs"), "c")?;
+        commit_all(&repo, "change")?;
+        std::fs::write(root.join("a.rs"), "uncommitted")?;
+
+        let options = CollectOptions::default();
+        let revision = collect_revision(root, "HEAD~1", &options)?;
+        assert_eq!(
+            contents(revision.entries, root)?,
+            [file("a.rs", "old"), file("sub/b.rs", "b")]
+        );
+        let revision = collect_revision(root, "HEAD", &options)?;
+        assert_eq!(
+            revision.commit,
+            repo.head()?.peel_to_commit()?.id().to_string()
+        );
+        // Blobs are only read when they are written
+        assert!(matches!(
+            &revision.entries[0].content,
+            VirtualContent::Blob(blob) if blob.size() == 3
+        ));
+        assert_eq!(
+            contents(revision.entries, root)?,
+            [
+                file("a.rs", "new"),
+                file("c.rs", "c"),
+                file("sub/b.rs", "b")
+            ]
+        );
+        let sub = root.join("sub");
+        let revision = collect_revision(&sub, "HEAD~1", &options)?;
+        assert_eq!(contents(revision.entries, &sub)?, [file("b.rs", "b")]);
+
+        assert!(collect_revision(root, "no-such-rev", &CollectOptions::default()).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_revision_ignore_files() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let repo = Repository::init(root)?;
+        std::fs::write(root.join(".src2mdignore"), "fixtures/\n")?;
+        std::fs::create_dir(root.join("fixtures"))?;
+        std::fs::write(root.join("a.rs"), "a")?;
+        std::fs::write(root.join("fixtures/big.rs"), "big")?;
+        commit_all(&repo, "base")?;
+        // Only the committed ignore file applies
+        std::fs::write(root.join(".src2mdignore"), "a.rs\n")?;
+
+        let options = CollectOptions::default();
+        let revision = collect_revision(root, "HEAD", &options)?;
+        assert_eq!(contents(revision.entries, root)?, [file("a.rs", "a")]);
+
+        let options = CollectOptions {
+            ignore: IgnorePolicy::NONE,
+            ..CollectOptions::default()
+        };
+        let revision = collect_revision(root, "HEAD", &options)?;
+        assert_eq!(
+            contents(revision.entries, root)?,
+            [file("a.rs", "a"), file("fixtures/big.rs", "big")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_revision_symlinks() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path().join("repo");
+        std::fs::create_dir_all(root.join("sub"))?;
+        let repo = Repository::init(&root)?;
+        std::fs::write(root.join("a.rs"), "a")?;
+        std::fs::write(dir.path().join("outside.rs"), "outside")?;
+        std::os::unix::fs::symlink("../a.rs", root.join("sub/link.rs"))?;
+        std::os::unix::fs::symlink("../outside.rs", root.join("escape.rs"))?;
+        std::os::unix::fs::symlink("sub", root.join("dir"))?;
+        commit_all(&repo, "links")?;
+
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::FollowWithinRoot,
+            ..CollectOptions::default()
+        };
+        let revision = collect_revision(&root, "HEAD", &options)?;
+        assert_eq!(
+            contents(revision.entries, &root)?,
+            [file("a.rs", "a"), file("sub/link.rs", "a")]
+        );
+
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::Skip,
+            ..CollectOptions::default()
+        };
+        let revision = collect_revision(&root, "HEAD", &options)?;
+        assert_eq!(contents(revision.entries, &root)?, [file("a.rs", "a")]);
+
+        let options = CollectOptions {
+            symlinks: SymlinkPolicy::Record,
+            ..CollectOptions::default()
+        };
+        let revision = collect_revision(&root, "HEAD", &options)?;
+        let symlink = |path: &str, target: &str| {
+            (
+                PathBuf::from(path),
+                VirtualContent::Symlink(target.to_string()),
+            )
+        };
+        assert_eq!(
+            contents(revision.entries, &root)?,
+            [
+                file("a.rs", "a"),
+                symlink("dir", "sub"),
+                symlink("escape.rs", "../outside.rs"),
+                symlink("sub/link.rs", "../a.rs"),
+            ]
+        );
+        Ok(())
+    }
 
     #[test]
     fn test_repo_name_from_https_url() {
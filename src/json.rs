@@ -0,0 +1,372 @@
+//! JSON and JSON Lines bundle formats.
+//!
+//! Both formats emit one object per collected file with the fields of
+//! [`JsonEntry`]. They differ only in framing:
+//!
+//! - [`JsonFormat`] writes a single document:
+//!   `{"src2md":"v1","manifest":{ ... },"files":[ ... ]}`
+//! - [`JsonLinesFormat`] writes a header line
+//!   `{"src2md":"v1","manifest":{ ... }}` followed by one file object per line
+//!
+//! The `manifest` member holds the [`Manifest`] of the bundle, if any.
+//!
+//! The leading `{"src2md":"v1"` marker plays the role of
+//! [`OUTPUT_MAGIC_HEADER`](crate::writer::OUTPUT_MAGIC_HEADER), so JSON bundles
+//! are excluded from collection just like Markdown ones.
+
+use crate::format::{BinaryFile, OutputFormat, OversizedFile, SymlinkFile, TextFile};
+use crate::manifest::Manifest;
+use crate::utils::encode_base64;
+use crate::writer::BASE64_INFO_STRING;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// The byte sequence every JSON and JSON Lines bundle starts with.
+pub const JSON_MAGIC_BYTES: &[u8] = br#"{"src2md":"v1""#;
+
+/// A single file in a JSON or JSON Lines bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonEntry<'a> {
+    /// Path relative to the project root.
+    #[serde(borrow)]
+    pub path: Cow<'a, str>,
+    /// Language tag, empty when unknown.
+    #[serde(borrow, default)]
+    pub language: Cow<'a, str>,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Number of lines (0 for binary files).
+    pub lines: usize,
+    /// Whether the file was detected as binary.
+    pub binary: bool,
+    /// File content; absent for binary files unless they are embedded.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Cow<'a, str>>,
+    /// Encoding of `content`: `"base64"` for embedded binary files, absent
+    /// for text.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Cow<'a, str>>,
+    /// Original encoding of a text file that is not UTF-8; `content` is
+    /// re-encoded on restore.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<Cow<'a, str>>,
+    /// Hex SHA-256 of the file on disk, checked on restore.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<Cow<'a, str>>,
+    /// Unix permission bits, written as an octal string such as `"0755"`.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::manifest::octal_mode"
+    )]
+    pub mode: Option<u32>,
+    /// Target of a symbolic link; such entries have no content.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<Cow<'a, str>>,
+    /// Whether `content` holds only the first and last lines of the file;
+    /// such entries are not restored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Whether the content was left out because the file is too large;
+    /// such entries have no content and are not restored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub omitted: bool,
+    /// Whether secrets in `content` were replaced with placeholders; such
+    /// entries are restored without checksum verification.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
+impl<'a> JsonEntry<'a> {
+    fn from_text(file: &TextFile<'a>) -> Self {
+        Self {
+            path: Cow::Owned(file.path.display().to_string()),
+            language: Cow::Borrowed(file.language),
+            size: file.size,
+            lines: file.content.lines().count(),
+            binary: false,
+            content: Some(Cow::Borrowed(file.content)),
+            encoding: None,
+            charset: file.charset.map(Cow::Borrowed),
+            sha256: file.sha256.map(Cow::Borrowed),
+            mode: file.mode,
+            symlink: None,
+            truncated: file.truncated,
+            omitted: false,
+            redacted: file.redacted,
+        }
+    }
+
+    fn from_binary(file: &BinaryFile<'a>) -> Self {
+        Self {
+            path: Cow::Owned(file.path.display().to_string()),
+            language: Cow::Borrowed(""),
+            size: file.size,
+            lines: 0,
+            binary: true,
+            content: file.content.map(|data| Cow::Owned(encode_base64(data))),
+            encoding: file.content.map(|_| Cow::Borrowed(BASE64_INFO_STRING)),
+            charset: None,
+            sha256: file.sha256.map(Cow::Borrowed),
+            mode: file.mode,
+            symlink: None,
+            truncated: false,
+            omitted: false,
+            redacted: false,
+        }
+    }
+
+    fn from_symlink(link: &SymlinkFile<'a>) -> Self {
+        Self {
+            path: Cow::Owned(link.path.display().to_string()),
+            language: Cow::Borrowed(""),
+            size: link.target.len() as u64,
+            lines: 0,
+            binary: false,
+            content: None,
+            encoding: None,
+            charset: None,
+            sha256: None,
+            mode: None,
+            symlink: Some(Cow::Borrowed(link.target)),
+            truncated: false,
+            omitted: false,
+            redacted: false,
+        }
+    }
+
+    fn from_oversized(file: &OversizedFile<'a>) -> Self {
+        Self {
+            path: Cow::Owned(file.path.display().to_string()),
+            language: Cow::Borrowed(""),
+            size: file.size,
+            lines: file.lines.unwrap_or(0),
+            binary: false,
+            content: None,
+            encoding: None,
+            charset: None,
+            sha256: None,
+            mode: None,
+            symlink: None,
+            truncated: false,
+            omitted: true,
+            redacted: false,
+        }
+    }
+}
+
+/// Appends a `"manifest"` member to the header object.
+fn push_manifest(manifest: Option<&Manifest>, out: &mut String) -> Result<()> {
+    if let Some(manifest) = manifest {
+        out.push_str(r#","manifest":"#);
+        out.push_str(&serde_json::to_string(manifest).context("Failed to serialize manifest")?);
+    }
+    Ok(())
+}
+
+/// Renders a bundle as a single JSON document.
+#[derive(Debug, Default)]
+pub struct JsonFormat {
+    entries_written: usize,
+}
+
+impl JsonFormat {
+    fn push_entry(&mut self, entry: &JsonEntry<'_>, out: &mut String) -> Result<()> {
+        if self.entries_written > 0 {
+            out.push_str(",\n");
+        }
+        out.push_str(&serde_json::to_string(entry).context("Failed to serialize JSON entry")?);
+        self.entries_written += 1;
+        Ok(())
+    }
+}
+
+impl OutputFormat for JsonFormat {
+    fn begin(&mut self, manifest: Option<&Manifest>, out: &mut String) -> Result<()> {
+        self.entries_written = 0;
+        out.push_str(r#"{"src2md":"v1""#);
+        push_manifest(manifest, out)?;
+        out.push_str(r#","files":["#);
+        out.push('\n');
+        Ok(())
+    }
+
+    fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()> {
+        self.push_entry(&JsonEntry::from_text(file), out)
+    }
+
+    fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()> {
+        self.push_entry(&JsonEntry::from_binary(file), out)
+    }
+
+    fn symlink(&mut self, link: &SymlinkFile<'_>, out: &mut String) -> Result<()> {
+        self.push_entry(&JsonEntry::from_symlink(link), out)
+    }
+
+    fn oversized(&mut self, file: &OversizedFile<'_>, out: &mut String) -> Result<()> {
+        self.push_entry(&JsonEntry::from_oversized(file), out)
+    }
+
+    fn end(&mut self, out: &mut String) -> Result<()> {
+        if self.entries_written > 0 {
+            out.push('\n');
+        }
+        out.push_str("]}\n");
+        Ok(())
+    }
+}
+
+/// Renders a bundle as JSON Lines: a header line, then one object per file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonLinesFormat;
+
+impl JsonLinesFormat {
+    fn push_entry(entry: &JsonEntry<'_>, out: &mut String) -> Result<()> {
+        out.push_str(&serde_json::to_string(entry).context("Failed to serialize JSON entry")?);
+        out.push('\n');
+        Ok(())
+    }
+}
+
+impl OutputFormat for JsonLinesFormat {
+    fn begin(&mut self, manifest: Option<&Manifest>, out: &mut String) -> Result<()> {
+        out.push_str(r#"{"src2md":"v1""#);
+        push_manifest(manifest, out)?;
+        out.push_str("}\n");
+        Ok(())
+    }
+
+    fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()> {
+        Self::push_entry(&JsonEntry::from_text(file), out)
+    }
+
+    fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()> {
+        Self::push_entry(&JsonEntry::from_binary(file), out)
+    }
+
+    fn symlink(&mut self, link: &SymlinkFile<'_>, out: &mut String) -> Result<()> {
+        Self::push_entry(&JsonEntry::from_symlink(link), out)
+    }
+
+    fn oversized(&mut self, file: &OversizedFile<'_>, out: &mut String) -> Result<()> {
+        Self::push_entry(&JsonEntry::from_oversized(file), out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn render(format: &mut dyn OutputFormat) -> Result<String> {
+        let mut out = String::new();
+        format.begin(None, &mut out)?;
+        format.file(
+            &TextFile::new(
+                Path::new("src/main.rs"),
+                "rust",
+                "fn main() {\n    println!(\"hi\");\n}\n",
+            ),
+            &mut out,
+        )?;
+        format.binary(&BinaryFile::new(Path::new("logo.png"), 3), &mut out)?;
+        format.end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_json_format_is_valid_document() -> Result<()> {
+        let out = render(&mut JsonFormat::default())?;
+        assert!(out.as_bytes().starts_with(JSON_MAGIC_BYTES));
+
+        let value: serde_json::Value = serde_json::from_str(&out)?;
+        let files = value["files"].as_array().expect("files array");
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["path"], "src/main.rs");
+        assert_eq!(files[0]["language"], "rust");
+        assert_eq!(files[0]["lines"], 3);
+        assert_eq!(files[0]["binary"], false);
+        assert_eq!(files[1]["binary"], true);
+        assert!(files[1].get("content").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_format_embeds_binary() -> Result<()> {
+        let mut out = String::new();
+        JsonLinesFormat.binary(
+            &BinaryFile {
+                content: Some(b"abc"),
+                ..BinaryFile::new(Path::new("data.bin"), 3)
+            },
+            &mut out,
+        )?;
+
+        let entry: JsonEntry = serde_json::from_str(out.trim_end())?;
+        assert!(entry.binary);
+        assert_eq!(entry.content.as_deref(), Some("YWJj"));
+        assert_eq!(entry.encoding.as_deref(), Some("base64"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_format_marks_incomplete_files() -> Result<()> {
+        let mut out = String::new();
+        JsonLinesFormat.file(
+            &TextFile {
+                truncated: true,
+                ..TextFile::new(Path::new("big.log"), "", "a\n")
+            },
+            &mut out,
+        )?;
+        JsonLinesFormat.oversized(
+            &OversizedFile {
+                path: Path::new("dump.sql"),
+                size: 4096,
+                lines: None,
+            },
+            &mut out,
+        )?;
+
+        let lines: Vec<_> = out.lines().collect();
+        let truncated: JsonEntry = serde_json::from_str(lines[0])?;
+        assert!(truncated.truncated);
+        assert!(!truncated.omitted);
+        let omitted: JsonEntry = serde_json::from_str(lines[1])?;
+        assert!(omitted.omitted);
+        assert_eq!(omitted.size, 4096);
+        assert!(omitted.content.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_format_empty_bundle() -> Result<()> {
+        let mut format = JsonFormat::default();
+        let mut out = String::new();
+        format.begin(None, &mut out)?;
+        format.end(&mut out)?;
+
+        let value: serde_json::Value = serde_json::from_str(&out)?;
+        assert!(value["files"].as_array().expect("files array").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_format_one_object_per_line() -> Result<()> {
+        let out = render(&mut JsonLinesFormat)?;
+        assert!(out.as_bytes().starts_with(JSON_MAGIC_BYTES));
+
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        let entry: JsonEntry = serde_json::from_str(lines[1])?;
+        assert_eq!(entry.path, "src/main.rs");
+        assert_eq!(entry.size, 34);
+        assert_eq!(
+            entry.content.as_deref(),
+            Some("fn main() {\n    println!(\"hi\");\n}\n")
+        );
+        Ok(())
+    }
+}
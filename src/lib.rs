@@ -3,9 +3,15 @@
 //! This crate can be used to:
 //!
 //! - Collect all source/text files under given path and compile them into a Markdown file
+//!   (or a JSON, JSON Lines or XML-tagged bundle)
 //! - Restore original source files back from a generated Markdown file (requires `restore` feature)
 //! - Clone and process git repositories (requires `git` feature)
+//! - Render the changes between two git revisions as Markdown diffs (requires `git` feature)
+//! - Bundle any revision of a local git repository without checking it out (requires `git` feature)
 //! - Generate mdbook-compatible output (requires `mdbook` feature)
+//! - Render bundles with a custom [`OutputFormat`] via [`run_src2md_with_format`]
+//! - Report per-file sizes and estimated tokens via [`collect_stats`] or
+//!   [`collect_stats_with_config`]
 //!
 //! ## Features
 //!
@@ -21,39 +27,36 @@
 //! ## Default Exclusions
 //!
 //! The following are always excluded by default:
-//! - Hidden files and directories (starting with `.`)
+//! - Hidden files and directories (starting with `.`), unless opted in with
+//!   [`CollectOptions::hidden`] or [`CollectOptions::include_hidden`]
+//! - Private keys and credential files (id_rsa, *.pem, secrets.yaml, etc.),
+//!   unless opted in with [`CollectOptions::allow_sensitive`]
 //! - Lock files (package-lock.json, yarn.lock, Cargo.lock, etc.)
+//! - Minified files, source maps and files marked as generated, unless opted
+//!   in with [`Config::include_generated`]. They are recognized by their
+//!   content when they are written, by
+//!   [`BundleWriter::set_skip_generated`], and by
+//!   [`generate_mdbook`]
+//! - Files matched by `.gitignore` and `.src2mdignore` files (see [`IgnorePolicy`])
 //! - Previous src2md output files
 //!
+//! [`explain_path`] reports which rule excludes a given path.
+//!
 //! ## Usage
 //!
 //! ### To generate a Markdown file:
 //!
 //! ```rust,no_run
 //! use src2md::{Config, run_src2md};
-//! use std::collections::HashSet;
 //! use std::path::PathBuf;
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
 //!     let config = Config {
 //!         output_path: PathBuf::from("output.md"),
-//!         ignore_file: None,
-//!         specific_paths: HashSet::new(),
 //!         project_root: std::env::current_dir()?,
-//!         #[cfg(feature = "restore")]
-//!         restore_input: None,
-//!         #[cfg(feature = "restore")]
-//!         restore_path: None,
-//!         verbosity: 0,
 //!         fail_fast: true,
-//!         extensions: HashSet::new(),
-//!         #[cfg(feature = "git")]
-//!         git_url: None,
-//!         #[cfg(feature = "git")]
-//!         git_branch: None,
-//!         #[cfg(feature = "mdbook")]
-//!         mdbook_output: None,
+//!         ..Default::default()
 //!     };
 //!
 //!     run_src2md(config).await
@@ -61,12 +64,24 @@
 //! ```
 
 pub mod cli;
+pub mod explain;
 #[cfg(feature = "restore")]
 pub mod extractor;
 pub mod filewalker;
+pub mod format;
+pub mod generated;
+pub mod json;
+pub mod manifest;
+pub mod preamble;
+pub mod redact;
+pub mod stats;
+pub mod tokens;
 pub mod utils;
 pub mod writer;
+pub mod xml;
 
+#[cfg(feature = "git")]
+pub mod diff;
 #[cfg(feature = "git")]
 pub mod git;
 
@@ -74,19 +89,37 @@ pub mod git;
 pub mod mdbook;
 
 pub use cli::Config;
+pub use explain::{Explanation, explain_path};
 #[cfg(feature = "restore")]
-pub use extractor::extract_from_markdown;
-pub use filewalker::collect_files;
-pub use writer::{MarkdownWriter, OUTPUT_MAGIC_BYTES, OUTPUT_MAGIC_HEADER};
+pub use extractor::{
+    RestoreOptions, extract_bundle_parts, extract_bundle_parts_with_options, extract_from_markdown,
+    extract_from_markdown_with_options,
+};
+pub use filewalker::{
+    CollectOptions, CollectedFile, FileOrder, IgnorePolicy, SymlinkPolicy, VirtualContent,
+    VirtualEntry, collect_files, collect_files_with_options,
+};
+pub use format::{BundleFormat, OutputFormat};
+pub use generated::GeneratedKind;
+pub use manifest::{FileMeta, LineEnding, Manifest, TextLayout};
+pub use preamble::Preamble;
+pub use redact::{Redaction, Redactor};
+pub use stats::{FileStats, StatsReport, collect_stats};
+pub use writer::{
+    BinaryMode, BundleWriter, FileLimits, MarkdownFormat, MarkdownWriter, OUTPUT_MAGIC_BYTES,
+    OUTPUT_MAGIC_BYTES_V1, OUTPUT_MAGIC_HEADER, PartBudget, default_jobs,
+};
 
 #[cfg(feature = "git")]
 pub use git::{ClonedRepo, clone_repository, repo_name_from_url};
 
 #[cfg(feature = "mdbook")]
-pub use mdbook::generate_mdbook;
+pub use mdbook::{MdbookOptions, generate_mdbook, generate_mdbook_with_options};
 
 use anyhow::Result;
-use log::error;
+use log::{error, info};
+use redact::report_redactions;
+use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::BufWriter;
 
@@ -104,31 +137,158 @@ use tokio::io::BufWriter;
 ///
 /// # Default Exclusions
 ///
-/// Hidden files, lock files, and previous src2md outputs are always excluded.
+/// Hidden files, sensitive files, lock files, and previous src2md outputs are
+/// always excluded.
 /// Use the `extensions` field to filter by file type.
+///
+/// The bundle is rendered in the format selected by `config.format`. If
+/// `max_tokens` or `max_bytes` is set, the output is split into numbered
+/// parts (`name.part2.md`, ...), each starting with the magic header.
+/// With `embed_binary`, binary files are embedded as base64 and restored
+/// byte for byte. `tree` adds an overview of all files after the header of the
+/// first part (see [`Preamble`]), and `toc` ends every part with links to the
+/// files written to it.
+///
+/// With the `git` feature, `changed_since` or `staged` restrict the bundle to
+/// changed files and list the deleted ones (see [`Config::restrict_to_changes`]);
+/// staged files are read from the index (see [`git::collect_staged`]). `rev`
+/// bundles a revision read from the git object database instead of the
+/// working tree (see [`git::collect_revision`]).
+///
+/// Up to `config.jobs()` files are read concurrently; they are still written
+/// in collection order, so the bundle does not depend on the number of jobs.
+///
+/// # Errors
+///
+/// Returns an error if `stats` is set, since the report is not a bundle:
+/// use [`collect_stats_with_config`] to get it instead.
 pub async fn run_src2md(config: Config) -> Result<()> {
-    let file = File::create(&config.output_path).await?;
-    let buf_writer = BufWriter::new(file);
-    let mut md_writer = MarkdownWriter::new(buf_writer);
+    let format = config.format.output_format();
+    run_src2md_with_format(config, format).await
+}
 
-    let entries = collect_files(
+/// Generate a bundle rendered with a custom [`OutputFormat`].
+///
+/// Behaves exactly like [`run_src2md`], but every collected file is passed to
+/// `format` instead of the default Markdown layout.
+pub async fn run_src2md_with_format(config: Config, format: Box<dyn OutputFormat>) -> Result<()> {
+    anyhow::ensure!(
+        !config.stats,
+        "No bundle is written with stats; use collect_stats_with_config for the report"
+    );
+    #[cfg(feature = "git")]
+    if let Some(rev) = &config.rev {
+        let revision = git::collect_revision(&config.project_root, rev, &config.collect_options())?;
+        return write_bundle(
+            &config,
+            format,
+            &revision.entries,
+            &[],
+            Some(revision.commit),
+        )
+        .await;
+    }
+    #[cfg(feature = "git")]
+    if config.staged && config.changed_since.is_none() {
+        let staged = git::collect_staged(&config.project_root, &config.collect_options())?;
+        return write_bundle(&config, format, &staged.entries, &staged.deleted, None).await;
+    }
+
+    let mut options = config.collect_options();
+    let deleted = config.restrict_to_changes(&mut options, &config.project_root)?;
+    let entries = collect_files_with_options(&config.project_root, &options)?;
+    write_bundle(&config, format, &entries, &deleted, None).await
+}
+
+/// Measures the files [`run_src2md`] would bundle with `config`, like the
+/// `--stats` option of the CLI.
+///
+/// The files are collected as configured, including `changed_since`, and
+/// measured with [`collect_stats`]. `config.stats` need not be set.
+///
+/// # Errors
+///
+/// Returns an error if the files cannot be collected, if `fail_fast` is set
+/// and a file cannot be read, or with the `git` feature, if `rev` or
+/// `staged` is set: stats are only available for the working tree.
+pub fn collect_stats_with_config(config: &Config) -> Result<StatsReport> {
+    #[cfg(feature = "git")]
+    anyhow::ensure!(
+        config.rev.is_none() && !config.staged,
+        "Stats are only available for the working tree"
+    );
+    let mut options = config.collect_options();
+    config.restrict_to_changes(&mut options, &config.project_root)?;
+    let entries = collect_files_with_options(&config.project_root, &options)?;
+    collect_stats(
+        &entries,
         &config.project_root,
-        config.ignore_file.as_ref(),
-        &config.specific_paths,
-        Some(&config.output_path),
-        &config.extensions,
-    )?;
+        config.include_generated,
+        config.fail_fast,
+    )
+}
+
+/// Writes the collected `entries` as configured, listing the `deleted`
+/// files. `commit` overrides the commit recorded in the manifest.
+async fn write_bundle(
+    config: &Config,
+    format: Box<dyn OutputFormat>,
+    entries: &[impl CollectedFile],
+    deleted: &[PathBuf],
+    commit: Option<String>,
+) -> Result<()> {
+    info!("Output file: {}", config.output_path.display());
+    let mut bundle_writer =
+        BundleWriter::create(&config.output_path, format, config.part_budget()).await?;
+
+    bundle_writer.set_binary_mode(config.binary_mode());
+
+    bundle_writer.set_skip_generated(!config.include_generated);
+    bundle_writer.set_file_limits(config.file_limits());
+    if let Some(redactor) = config.redactor()? {
+        bundle_writer.set_redactor(redactor);
+    }
+    let mut manifest = Manifest::new(&config.project_root, entries.len());
+    if let Some(name) = &config.root_name {
+        manifest.root = name.clone();
+    }
+    manifest.deleted = deleted
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    if commit.is_some() {
+        manifest.commit = commit;
+    }
+    bundle_writer.set_manifest(manifest);
+    if let Some(preamble) = config.preamble(entries, &config.project_root, deleted) {
+        bundle_writer.set_preamble(preamble);
+    }
 
-    for entry in entries {
-        if let Err(e) = md_writer.write_entry(&entry, &config.project_root).await {
+    info!("Processing {} files", entries.len());
+    bundle_writer
+        .write_entries(entries, &config.project_root, config.jobs(), |entry, e| {
             if config.fail_fast {
                 return Err(e);
             }
             error!("Failed to write {}: {e}", entry.path().display());
+            Ok(())
+        })
+        .await?;
+
+    bundle_writer.finish().await?;
+    report_redactions(
+        bundle_writer.redactions(),
+        config.fail_on_secrets,
+        &bundle_writer.output_paths(),
+    )?;
+    let parts = bundle_writer.part_paths();
+    if parts.len() > 1 {
+        info!("Bundle split into {} parts:", parts.len());
+        for part in parts {
+            info!("  {}", part.display());
         }
     }
-
-    md_writer.flush().await?;
+    info!("Done: {}", config.output_path.display());
     Ok(())
 }
 
@@ -136,7 +296,9 @@ pub async fn run_src2md(config: Config) -> Result<()> {
 ///
 /// This is a convenience function that creates a Config and runs src2md.
 /// Useful when you have a path (e.g., from a cloned git repo) and want to
-/// process it without constructing a full Config.
+/// process it without constructing a full Config. Like with a default
+/// Config, generated files are skipped and secrets found by the built-in
+/// detectors are redacted.
 pub async fn run_src2md_on_path(
     project_root: std::path::PathBuf,
     output_path: std::path::PathBuf,
@@ -147,6 +309,8 @@ pub async fn run_src2md_on_path(
     let file = File::create(&output_path).await?;
     let buf_writer = BufWriter::new(file);
     let mut md_writer = MarkdownWriter::new(buf_writer);
+    md_writer.set_skip_generated(true);
+    md_writer.set_redactor(Redactor::new(true, &[])?);
 
     let entries = collect_files(
         &project_root,
@@ -156,15 +320,16 @@ pub async fn run_src2md_on_path(
         extensions,
     )?;
 
-    for entry in entries {
-        if let Err(e) = md_writer.write_entry(&entry, &project_root).await {
+    md_writer
+        .write_entries(&entries, &project_root, default_jobs(), |entry, e| {
             if fail_fast {
                 return Err(e);
             }
             error!("Failed to write {}: {e}", entry.path().display());
-        }
-    }
+            Ok(())
+        })
+        .await?;
 
-    md_writer.flush().await?;
-    Ok(())
+    md_writer.finish().await?;
+    report_redactions(md_writer.redactions(), false, &[])
 }
@@ -1,14 +1,19 @@
 use anyhow::Result;
-use log::{LevelFilter, error, info};
+#[cfg(feature = "restore")]
+use log::warn;
+use log::{LevelFilter, info};
 use src2md::cli::parse_args;
+use src2md::explain::explain_path;
 #[cfg(feature = "restore")]
-use src2md::extractor::extract_from_markdown;
-use src2md::filewalker::collect_files;
+use src2md::extractor::extract_bundle_parts_with_options;
+use src2md::filewalker::CollectOptions;
+#[cfg(feature = "mdbook")]
+use src2md::filewalker::collect_files_with_options;
 #[cfg(feature = "mdbook")]
-use src2md::mdbook::generate_mdbook;
-use src2md::writer::MarkdownWriter;
-use tokio::fs::File;
-use tokio::io::BufWriter;
+use src2md::mdbook::generate_mdbook_with_options;
+#[cfg(feature = "git")]
+use src2md::redact::report_redactions;
+use src2md::{collect_stats_with_config, run_src2md};
 
 fn init_logger(verbosity: u8) {
     let level = match verbosity {
@@ -32,17 +37,31 @@ async fn main() -> Result<()> {
 
     // Handle restore mode (requires feature)
     #[cfg(feature = "restore")]
-    if let Some(input) = &config.restore_input {
-        info!("Restoring files from: {}", input.display());
-        extract_from_markdown(input, config.restore_path.as_ref()).await?;
+    if !config.restore_inputs.is_empty() {
+        for input in &config.restore_inputs {
+            info!("Restoring files from: {}", input.display());
+        }
+        warn_about_unlisted_parts(&config.restore_inputs);
+        extract_bundle_parts_with_options(
+            &config.restore_inputs,
+            config.restore_path.as_ref(),
+            &config.restore_options(),
+        )
+        .await?;
         info!("Restore complete");
         return Ok(());
     }
 
     // Handle git mode (requires feature)
     #[cfg(feature = "git")]
-    if let Some(ref git_url) = config.git_url {
-        return run_git_mode(&config, git_url).await;
+    if let Some(git_url) = config.git_url.clone() {
+        return run_git_mode(config, &git_url).await;
+    }
+
+    // Handle diff mode (requires feature)
+    #[cfg(feature = "git")]
+    if let Some(ref range) = config.diff {
+        return run_diff_mode(&config, range).await;
     }
 
     // Handle mdbook mode (requires feature)
@@ -52,98 +71,101 @@ async fn main() -> Result<()> {
     }
 
     // Standard mode: process local directory
-    run_local_mode(&config).await
+    run_local_mode(config).await
 }
 
-/// Process a local directory and generate markdown output.
-async fn run_local_mode(config: &src2md::Config) -> Result<()> {
-    info!("Output file: {}", config.output_path.display());
-
+/// Process a local directory and generate bundle output.
+async fn run_local_mode(config: src2md::Config) -> Result<()> {
     if !config.extensions.is_empty() {
         info!("Filtering by extensions: {:?}", config.extensions);
     }
 
-    let file = File::create(&config.output_path).await?;
-    let buf_writer = BufWriter::new(file);
-    let mut md_writer = MarkdownWriter::new(buf_writer);
+    if !config.explain.is_empty() {
+        let mut options = config.collect_options();
+        config.restrict_to_changes(&mut options, &config.project_root)?;
+        return print_explanations(&config, &options);
+    }
 
-    let entries = collect_files(
-        &config.project_root,
-        config.ignore_file.as_ref(),
-        &config.specific_paths,
-        Some(&config.output_path),
-        &config.extensions,
-    )?;
-
-    info!("Processing {} files", entries.len());
-
-    for entry in entries {
-        if let Err(e) = md_writer.write_entry(&entry, &config.project_root).await {
-            error!("Failed to write {}: {e}", entry.path().display());
-            if config.fail_fast {
-                return Err(e);
-            }
+    if config.stats {
+        print!("{}", collect_stats_with_config(&config)?);
+        return Ok(());
+    }
+
+    run_src2md(config).await
+}
+
+/// Prints why each of the paths to explain is or is not bundled.
+fn print_explanations(config: &src2md::Config, options: &CollectOptions) -> Result<()> {
+    for path in &config.explain {
+        let explanation = explain_path(
+            &config.project_root,
+            options,
+            config.include_generated,
+            path,
+        )?;
+        println!("{explanation}");
+    }
+    Ok(())
+}
+
+/// Warns when a split bundle has parts next to the given inputs that were not listed.
+#[cfg(feature = "restore")]
+fn warn_about_unlisted_parts(inputs: &[std::path::PathBuf]) {
+    use src2md::writer::part_path;
+
+    let Some(first) = inputs.first() else {
+        return;
+    };
+
+    let mut index = 2;
+    loop {
+        let part = part_path(first, index);
+        if !part.exists() {
+            break;
+        }
+        if !inputs.contains(&part) {
+            warn!(
+                "{} looks like part {} of {}, but was not listed for restore",
+                part.display(),
+                index,
+                first.display()
+            );
         }
+        index += 1;
     }
+}
 
-    md_writer.flush().await?;
-    info!("Done: {}", config.output_path.display());
+/// Write the changes in a revision range of the local repository as a diff bundle.
+#[cfg(feature = "git")]
+async fn run_diff_mode(config: &src2md::Config, range: &str) -> Result<()> {
+    use src2md::diff::{RevRange, render_diff_bundle};
+
+    let range = RevRange::parse(range);
+    info!("Rendering changes {range}");
 
+    let bundle = render_diff_bundle(&config.project_root, &range, &config.diff_bundle_options()?)?;
+    // Nothing is written yet, so there is nothing to remove on failure
+    report_redactions(&bundle.redactions, config.fail_on_secrets, &[])?;
+    tokio::fs::write(&config.output_path, &bundle.markdown).await?;
+
+    info!("{} changed file(s)", bundle.files.len());
+    info!("Done: {}", config.output_path.display());
     Ok(())
 }
 
 /// Clone a git repository and generate markdown from it.
 #[cfg(feature = "git")]
-async fn run_git_mode(config: &src2md::Config, git_url: &str) -> Result<()> {
+async fn run_git_mode(mut config: src2md::Config, git_url: &str) -> Result<()> {
     use src2md::git::clone_repository;
 
     info!("Cloning repository: {}", git_url);
 
     // Clone the repository
     let cloned = clone_repository(git_url, config.git_branch.as_deref())?;
-    let project_root = cloned.path().clone();
-
-    info!("Output file: {}", config.output_path.display());
-
-    if !config.extensions.is_empty() {
-        info!("Filtering by extensions: {:?}", config.extensions);
-    }
-
-    let file = File::create(&config.output_path).await?;
-    let buf_writer = BufWriter::new(file);
-    let mut md_writer = MarkdownWriter::new(buf_writer);
-
-    // Look for .gitignore in the cloned repo to use as ignore file
-    let ignore_file = config.ignore_file.clone().or_else(|| {
-        let gitignore = project_root.join(".gitignore");
-        if gitignore.exists() {
-            Some(gitignore)
-        } else {
-            None
-        }
-    });
-
-    let entries = collect_files(
-        &project_root,
-        ignore_file.as_ref(),
-        &config.specific_paths,
-        Some(&config.output_path),
-        &config.extensions,
-    )?;
-
-    info!("Processing {} files from cloned repository", entries.len());
-
-    for entry in entries {
-        if let Err(e) = md_writer.write_entry(&entry, &project_root).await {
-            error!("Failed to write {}: {e}", entry.path().display());
-            if config.fail_fast {
-                return Err(e);
-            }
-        }
-    }
-
-    md_writer.flush().await?;
-    info!("Done: {}", config.output_path.display());
+    // Ignore files of the clone, including nested .gitignore files, are
+    // honored by the ignore policy
+    config.project_root = cloned.path().clone();
+    run_local_mode(config).await?;
 
     // The cloned repo is automatically cleaned up when `cloned` is dropped
     info!("Cleaned up temporary clone");
@@ -160,17 +182,21 @@ async fn run_mdbook_mode(config: &src2md::Config, output_dir: &std::path::Path)
         info!("Filtering by extensions: {:?}", config.extensions);
     }
 
-    let entries = collect_files(
-        &config.project_root,
-        config.ignore_file.as_ref(),
-        &config.specific_paths,
-        None, // No single output file to exclude
-        &config.extensions,
-    )?;
+    let options = CollectOptions {
+        output_path: None, // No single output file to exclude
+        ..config.collect_options()
+    };
+    let entries = collect_files_with_options(&config.project_root, &options)?;
 
     info!("Processing {} files into mdbook format", entries.len());
 
-    generate_mdbook(&entries, &config.project_root, output_dir).await?;
+    generate_mdbook_with_options(
+        &entries,
+        &config.project_root,
+        output_dir,
+        &config.mdbook_options()?,
+    )
+    .await?;
 
     info!("Done: {}", output_dir.display());
     Ok(())
@@ -0,0 +1,450 @@
+//! Bundle manifest and per-file metadata.
+//!
+//! Markdown bundles in format v2 start with the magic header followed by a
+//! manifest comment, and every file section carries a metadata comment right
+//! below its heading:
+//!
+//! ````markdown
+//! <!-- src2md:v2 -->
+//! <!-- src2md:manifest {"version":"0.1.8","generated":"2026-01-01T12:00:00Z","root":"app","commit":"4b825dc6…","files":2} -->
+//!
+//! ## src/main.rs
+//! <!-- src2md:file {"size":13,"sha256":"9f86d081…","mode":"0644","eol":"crlf","final_newline":true} -->
+//!
+//! ```rust
+//! fn main() {}
+//! ```
+//! ````
+//!
+//! Both comments hold a JSON object. The metadata lets the extractor detect
+//! files that were corrupted or edited after bundling; bundles without it
+//! (format v1) are still restored, just without verification.
+//!
+//! Text files are written with LF line endings and without a byte order
+//! mark; their [`TextLayout`] is recorded in the metadata so the extractor
+//! can reproduce the original bytes.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Start of the manifest comment.
+pub const MANIFEST_COMMENT_PREFIX: &str = "<!-- src2md:manifest ";
+
+/// Start of a per-file metadata comment.
+pub const FILE_META_COMMENT_PREFIX: &str = "<!-- src2md:file ";
+
+/// End of a src2md comment.
+const COMMENT_SUFFIX: &str = " -->";
+
+/// Environment variable holding a Unix timestamp that is recorded as the
+/// generation time instead of the current time, so that bundles of the same
+/// files are byte-identical (see <https://reproducible-builds.org/specs/source-date-epoch/>).
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Describes a whole bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of src2md that generated the bundle.
+    pub version: String,
+    /// Generation time as an RFC 3339 UTC timestamp, taken from
+    /// [`SOURCE_DATE_EPOCH`] if it is set.
+    pub generated: String,
+    /// Name of the bundled project's root directory.
+    pub root: String,
+    /// Commit checked out in the project's git repository, if any, or the
+    /// commit a bundle of another revision was read from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Number of files written to the bundle (across all parts of a split
+    /// bundle).
+    pub files: usize,
+    /// Files deleted by the bundled changes, relative to the project root
+    /// (see `--changed-since` and `--staged`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+    /// Revision range of a diff bundle (see `--diff`), which holds changes
+    /// rather than files and cannot be restored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+impl Manifest {
+    /// Creates a manifest for a bundle of `files` files from `project_root`,
+    /// generated now by this version of src2md. The time is read from
+    /// [`SOURCE_DATE_EPOCH`] instead if it is set.
+    ///
+    /// The commit is looked up with git when the `git` feature is enabled.
+    pub fn new(project_root: &Path, files: usize) -> Self {
+        let root = project_root
+            .canonicalize()
+            .unwrap_or_else(|_| project_root.to_path_buf())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        #[cfg(feature = "git")]
+        let commit = crate::git::head_commit(project_root);
+        #[cfg(not(feature = "git"))]
+        let commit = None;
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            generated: generation_time(std::env::var(SOURCE_DATE_EPOCH).ok().as_deref()),
+            root,
+            commit,
+            files,
+            deleted: Vec::new(),
+            diff: None,
+        }
+    }
+
+    /// Renders the manifest as a comment line.
+    pub fn to_comment(&self) -> Result<String> {
+        to_comment(MANIFEST_COMMENT_PREFIX, self)
+    }
+
+    /// Parses a manifest comment line.
+    pub fn from_comment(line: &str) -> Option<Result<Self>> {
+        from_comment(MANIFEST_COMMENT_PREFIX, line)
+    }
+}
+
+/// Metadata recorded for a single file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMeta {
+    /// Size of the file on disk in bytes, or of the bundled content if it
+    /// was [`redacted`](Self::redacted).
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the file on disk; left out for redacted
+    /// files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Unix permission bits, written as an octal string such as `"0755"`.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "octal_mode")]
+    pub mode: Option<u32>,
+    /// Line-ending style of a text file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eol: Option<LineEnding>,
+    /// Whether a text file starts with a UTF-8 byte order mark.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bom: bool,
+    /// Whether a text file ends with a line break.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_newline: Option<bool>,
+    /// Original encoding of a text file that is not UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    /// Target of a symbolic link; the entry has no content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<String>,
+    /// Whether the content was cut down to its first and last lines; the
+    /// entry cannot be restored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Whether the content was left out because the file is too large; the
+    /// entry cannot be restored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub omitted: bool,
+    /// Whether secrets in the content were replaced with placeholders; the
+    /// entry is restored without checksum verification.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
+impl FileMeta {
+    /// Records the layout of a text file.
+    pub fn with_layout(self, layout: TextLayout) -> Self {
+        Self {
+            eol: Some(layout.eol),
+            bom: layout.bom,
+            final_newline: Some(layout.final_newline),
+            ..self
+        }
+    }
+
+    /// Returns the recorded text layout, if any.
+    pub fn layout(&self) -> Option<TextLayout> {
+        Some(TextLayout {
+            eol: self.eol?,
+            bom: self.bom,
+            final_newline: self.final_newline?,
+        })
+    }
+
+    /// Renders the metadata as a comment line.
+    pub fn to_comment(&self) -> Result<String> {
+        to_comment(FILE_META_COMMENT_PREFIX, self)
+    }
+
+    /// Parses a metadata comment line.
+    pub fn from_comment(line: &str) -> Option<Result<Self>> {
+        from_comment(FILE_META_COMMENT_PREFIX, line)
+    }
+}
+
+/// Line-ending style of a text file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// `\n` line breaks, or no line breaks at all.
+    Lf,
+    /// `\r\n` line breaks.
+    Crlf,
+    /// Both kinds of line breaks; the content is bundled verbatim.
+    Mixed,
+}
+
+/// The byte-level details of a text file that Markdown bundles normalize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextLayout {
+    /// Line-ending style.
+    pub eol: LineEnding,
+    /// Whether the file starts with a UTF-8 byte order mark.
+    pub bom: bool,
+    /// Whether the file ends with a line break.
+    pub final_newline: bool,
+}
+
+impl TextLayout {
+    /// Detects the layout of `content`.
+    pub fn detect(content: &str) -> Self {
+        let (bom, body) = match content.strip_prefix('\u{feff}') {
+            Some(body) => (true, body),
+            None => (false, content),
+        };
+
+        let crlf = body.matches("\r\n").count();
+        let eol = if crlf == 0 {
+            LineEnding::Lf
+        } else if crlf == body.matches('\n').count() {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Mixed
+        };
+
+        Self {
+            eol,
+            bom,
+            final_newline: body.ends_with('\n'),
+        }
+    }
+
+    /// Strips the byte order mark and converts CRLF line endings to LF.
+    pub fn normalize<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        let body = content.strip_prefix('\u{feff}').unwrap_or(content);
+        match self.eol {
+            LineEnding::Crlf => Cow::Owned(body.replace("\r\n", "\n")),
+            LineEnding::Lf | LineEnding::Mixed => Cow::Borrowed(body),
+        }
+    }
+
+    /// Reverses [`normalize`](Self::normalize) for normalized content whose
+    /// final line break, if any, has been removed.
+    pub fn restore(&self, content: &str) -> String {
+        let mut out = String::with_capacity(content.len() + 4);
+        if self.bom {
+            out.push('\u{feff}');
+        }
+        match self.eol {
+            LineEnding::Crlf => out.push_str(&content.replace('\n', "\r\n")),
+            LineEnding::Lf | LineEnding::Mixed => out.push_str(content),
+        }
+        if self.final_newline {
+            // The `\r` of a mixed file's last line break is still part of `content`
+            out.push_str(if self.eol == LineEnding::Crlf {
+                "\r\n"
+            } else {
+                "\n"
+            });
+        }
+        out
+    }
+}
+
+/// Serializes `value` into a `prefix{json} -->` comment line.
+fn to_comment<T: Serialize>(prefix: &str, value: &T) -> Result<String> {
+    let json = serde_json::to_string(value).context("Failed to serialize metadata")?;
+    // `>` can only occur inside JSON strings, where `>` is equivalent,
+    // and escaping it keeps a `-->` in a value from ending the comment
+    Ok(format!(
+        "{prefix}{}{COMMENT_SUFFIX}",
+        json.replace('>', "\\u003e")
+    ))
+}
+
+/// Parses a comment line written by [`to_comment`].
+///
+/// Returns `None` if the line is not a comment with the given prefix.
+fn from_comment<T: DeserializeOwned>(prefix: &str, line: &str) -> Option<Result<T>> {
+    let json = line.strip_prefix(prefix)?.strip_suffix(COMMENT_SUFFIX)?;
+    Some(serde_json::from_str(json).context("Invalid src2md metadata comment"))
+}
+
+/// Serializes file modes as octal strings.
+pub(crate) mod octal_mode {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match mode {
+            Some(mode) => serializer.serialize_str(&format!("{mode:04o}")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|mode| u32::from_str_radix(&mode, 8).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Formats the generation time: the Unix timestamp `source_date_epoch` if
+/// it is valid, or the current time.
+fn generation_time(source_date_epoch: Option<&str>) -> String {
+    let time = source_date_epoch.and_then(|value| {
+        let time = value
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0));
+        if time.is_none() {
+            warn!("Ignoring invalid {SOURCE_DATE_EPOCH}: {value:?}");
+        }
+        time
+    });
+    time.unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_time_from_source_date_epoch() {
+        assert_eq!(generation_time(Some("1700000000")), "2023-11-14T22:13:20Z");
+        // Invalid values fall back to the current time
+        let now = generation_time(Some("yesterday"));
+        assert!(now.ends_with('Z') && now.len() == "2023-11-14T22:13:20Z".len());
+        assert_ne!(now, "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_manifest_comment_roundtrip() -> Result<()> {
+        let manifest = Manifest {
+            version: "1.2.3".to_string(),
+            generated: "2026-01-01T00:00:00Z".to_string(),
+            root: "odd --> name".to_string(),
+            commit: None,
+            files: 3,
+            deleted: Vec::new(),
+            diff: None,
+        };
+
+        let comment = manifest.to_comment()?;
+        assert!(comment.starts_with(MANIFEST_COMMENT_PREFIX));
+        assert_eq!(comment.matches("-->").count(), 1);
+        assert!(!comment.contains("commit"));
+
+        let parsed = Manifest::from_comment(&comment).expect("manifest comment")?;
+        assert_eq!(parsed, manifest);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_meta_comment() -> Result<()> {
+        let meta = FileMeta {
+            size: 12,
+            sha256: Some("abc".to_string()),
+            mode: Some(0o755),
+            ..FileMeta::default()
+        };
+
+        let comment = meta.to_comment()?;
+        assert_eq!(
+            comment,
+            r#"<!-- src2md:file {"size":12,"sha256":"abc","mode":"0755"} -->"#
+        );
+        assert_eq!(
+            FileMeta::from_comment(&comment).expect("meta comment")?,
+            meta
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_comment_ignores_other_lines() {
+        assert!(FileMeta::from_comment("<!-- src2md:v2 -->").is_none());
+        assert!(Manifest::from_comment("## src/main.rs").is_none());
+        assert!(
+            FileMeta::from_comment("<!-- src2md:file {not json} -->").is_some_and(|r| r.is_err())
+        );
+    }
+
+    #[test]
+    fn test_text_layout_roundtrip() {
+        for content in [
+            "",
+            "no newline",
+            "lf\nlines\n",
+            "crlf\r\nlines\r\n",
+            "crlf\r\nno newline",
+            "\u{feff}bom\r\n",
+            "mixed\r\nlf\n",
+            "mixed\nending in crlf\r\n",
+            "lone\rcarriage return\r",
+            "blank lines\n\n\n",
+        ] {
+            let layout = TextLayout::detect(content);
+            let normalized = layout.normalize(content);
+            assert!(!normalized.starts_with('\u{feff}'));
+            if layout.eol != LineEnding::Mixed {
+                assert!(!normalized.contains("\r\n"), "{content:?}");
+            }
+
+            let stripped = normalized.strip_suffix('\n').unwrap_or(&normalized);
+            assert_eq!(layout.restore(stripped), content, "{content:?}");
+        }
+    }
+
+    #[test]
+    fn test_file_meta_layout() -> Result<()> {
+        let layout = TextLayout::detect("\u{feff}a\r\nb");
+        assert_eq!(
+            layout,
+            TextLayout {
+                eol: LineEnding::Crlf,
+                bom: true,
+                final_newline: false,
+            }
+        );
+
+        let meta = FileMeta::default().with_layout(layout);
+        let comment = meta.to_comment()?;
+        assert!(comment.contains(r#""eol":"crlf","bom":true,"final_newline":false"#));
+        let parsed = FileMeta::from_comment(&comment).expect("meta comment")?;
+        assert_eq!(parsed.layout(), Some(layout));
+        assert_eq!(FileMeta::default().layout(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_new() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let manifest = Manifest::new(dir.path(), 7);
+        assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest.files, 7);
+        assert_eq!(
+            manifest.root,
+            dir.path().file_name().unwrap().to_string_lossy()
+        );
+        assert!(manifest.generated.ends_with('Z'));
+    }
+}
@@ -5,18 +5,19 @@
 //! - One .md file per folder containing all files as sections
 //! - Nested folders become nested chapters
 
+use crate::filewalker::is_recorded_symlink;
+use crate::format::{BinaryFile, OutputFormat, TextFile};
+use crate::generated::detect_generated;
+use crate::redact::{Redaction, Redactor, report_redactions};
 use crate::utils::get_language_tag;
+use crate::writer::{MarkdownFormat, SourceContent, read_source};
 use anyhow::{Context, Result};
-use content_inspector::{ContentType, inspect};
 use ignore::DirEntry;
-use log::{debug, info};
-use memmap2::MmapOptions;
+use log::{debug, info, warn};
 use std::collections::BTreeMap;
-use std::fs::File as StdFile;
 use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::str;
 use tokio::fs;
 
 /// Represents a chapter in the mdbook structure.
@@ -67,6 +68,20 @@ pub struct MdbookWriter {
     project_root: PathBuf,
     /// Output directory for mdbook src/
     output_dir: PathBuf,
+    /// Leave out minified files, source maps and files marked as generated
+    skip_generated: bool,
+    redactor: Option<Redactor>,
+    /// Secrets redacted from the written files
+    redactions: Vec<Redaction>,
+    /// Paths of the written files
+    written: Vec<PathBuf>,
+}
+
+/// Redactions and files of a book being written.
+#[derive(Default)]
+struct Progress {
+    redactions: Vec<Redaction>,
+    written: Vec<PathBuf>,
 }
 
 impl MdbookWriter {
@@ -76,21 +91,73 @@ impl MdbookWriter {
             root: Chapter::new(),
             project_root,
             output_dir,
+            skip_generated: false,
+            redactor: None,
+            redactions: Vec::new(),
+            written: Vec::new(),
         }
     }
 
+    /// Replaces secrets found by `redactor` in text files with
+    /// `[REDACTED:kind]` before they are written.
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.redactor = Some(redactor);
+    }
+
+    /// Secrets redacted from the files written so far.
+    pub fn redactions(&self) -> &[Redaction] {
+        &self.redactions
+    }
+
+    /// Paths of the files written so far.
+    pub fn output_paths(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    /// Leaves minified files, source maps and files marked as generated out
+    /// of the files added afterwards, like
+    /// [`BundleWriter::set_skip_generated`](crate::writer::BundleWriter::set_skip_generated)
+    /// does for bundles.
+    pub fn set_skip_generated(&mut self, skip: bool) {
+        self.skip_generated = skip;
+    }
+
     /// Adds files to the chapter structure.
+    ///
+    /// Recorded symbolic links have no content to show and are left out.
     pub fn add_files(&mut self, entries: &[DirEntry]) {
+        let mut skipped_generated = 0;
         for entry in entries {
+            if is_recorded_symlink(entry) {
+                continue;
+            }
             let path = entry.path();
+            if self.skip_generated
+                && let Some(kind) = detect_generated(path)
+            {
+                debug!("Skipping {}: {kind}", path.display());
+                skipped_generated += 1;
+                continue;
+            }
             if let Ok(rel_path) = path.strip_prefix(&self.project_root) {
                 self.root.insert(rel_path);
             }
         }
+        if skipped_generated > 0 {
+            info!("Skipped {skipped_generated} minified or generated file(s)");
+        }
     }
 
     /// Writes the complete mdbook structure to the output directory.
-    pub async fn write(&self) -> Result<()> {
+    pub async fn write(&mut self) -> Result<()> {
+        let mut progress = Progress::default();
+        let result = self.write_book(&mut progress).await;
+        self.redactions.append(&mut progress.redactions);
+        self.written.append(&mut progress.written);
+        result
+    }
+
+    async fn write_book(&self, progress: &mut Progress) -> Result<()> {
         // Create output directory
         fs::create_dir_all(&self.output_dir)
             .await
@@ -105,9 +172,10 @@ impl MdbookWriter {
             .await
             .with_context(|| "Failed to write SUMMARY.md")?;
         info!("Wrote: {}", summary_path.display());
+        progress.written.push(summary_path);
 
         // Write chapter files
-        self.write_chapters(&self.root, &PathBuf::new(), &PathBuf::new(), 0)
+        self.write_chapters(&self.root, &PathBuf::new(), &PathBuf::new(), 0, progress)
             .await?;
 
         Ok(())
@@ -161,24 +229,27 @@ impl MdbookWriter {
     /// * `rel_path` - The relative path to this chapter from the output dir (for .md file naming)
     /// * `src_path` - The source path prefix for finding files in project_root
     /// * `depth` - Current depth (0 = root)
+    /// * `progress` - Collects the redactions and written files
     fn write_chapters<'a>(
         &'a self,
         chapter: &'a Chapter,
         rel_path: &'a Path,
         src_path: &'a Path,
         depth: usize,
+        progress: &'a mut Progress,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move {
             // Write introduction.md for root files
             if depth == 0 && !chapter.files.is_empty() {
                 let intro_path = self.output_dir.join("introduction.md");
                 let content = self
-                    .generate_chapter_content(&chapter.files, src_path, "Introduction")
+                    .generate_chapter_content(&chapter.files, src_path, "Introduction", progress)
                     .await?;
                 fs::write(&intro_path, content)
                     .await
                     .with_context(|| "Failed to write introduction.md")?;
                 info!("Wrote: {}", intro_path.display());
+                progress.written.push(intro_path);
             }
 
             // Write chapter files for subdirectories
@@ -199,16 +270,17 @@ impl MdbookWriter {
                     }
 
                     let content = self
-                        .generate_chapter_content(&child.files, &child_src_path, name)
+                        .generate_chapter_content(&child.files, &child_src_path, name, progress)
                         .await?;
                     fs::write(&md_path, content)
                         .await
                         .with_context(|| format!("Failed to write {}", md_path.display()))?;
                     info!("Wrote: {}", md_path.display());
+                    progress.written.push(md_path);
                 }
 
                 // Recurse into children
-                self.write_chapters(child, &child_rel_path, &child_src_path, depth + 1)
+                self.write_chapters(child, &child_rel_path, &child_src_path, depth + 1, progress)
                     .await?;
             }
 
@@ -222,11 +294,13 @@ impl MdbookWriter {
     /// * `files` - Filenames in this chapter (just the filename, not full path)
     /// * `src_path` - The source directory path prefix to find files
     /// * `title` - The chapter title
+    /// * `progress` - Collects the redactions
     async fn generate_chapter_content(
         &self,
         files: &[PathBuf],
         src_path: &Path,
         title: &str,
+        progress: &mut Progress,
     ) -> Result<String> {
         let mut content = format!("# {}\n\n", title);
 
@@ -234,7 +308,9 @@ impl MdbookWriter {
             // Construct full relative path: src_path + filename
             let rel_path = src_path.join(filename);
             let full_path = self.project_root.join(&rel_path);
-            let file_content = self.read_file_content(&full_path, filename).await?;
+            let file_content = self
+                .read_file_content(&full_path, &rel_path, &mut progress.redactions)
+                .await?;
             content.push_str(&file_content);
         }
 
@@ -242,7 +318,16 @@ impl MdbookWriter {
     }
 
     /// Reads and formats a single file's content as a section.
-    async fn read_file_content(&self, full_path: &Path, rel_path: &Path) -> Result<String> {
+    ///
+    /// Sections are rendered with [`MarkdownFormat`], headed by the file name
+    /// rather than the full relative path. Secrets are redacted from text
+    /// files and added to `redactions`.
+    async fn read_file_content(
+        &self,
+        full_path: &Path,
+        rel_path: &Path,
+        redactions: &mut Vec<Redaction>,
+    ) -> Result<String> {
         let filename = rel_path
             .file_name()
             .and_then(|n| n.to_str())
@@ -250,69 +335,59 @@ impl MdbookWriter {
 
         debug!("Processing: {}", rel_path.display());
 
-        // Memory-map the file for efficient reading
-        let file = StdFile::open(full_path)
-            .with_context(|| format!("Failed to open file: {}", full_path.display()))?;
-
-        let mmap = unsafe {
-            MmapOptions::new()
-                .map(&file)
-                .with_context(|| format!("Failed to mmap file: {}", full_path.display()))?
-        };
-
-        // Check for binary content
-        let sample_size = std::cmp::min(8192, mmap.len());
-        let content_type = inspect(&mmap[..sample_size]);
-
-        if content_type == ContentType::BINARY {
-            return Ok(format!("## {}\n\n(binary file omitted)\n\n", filename));
+        let mut section = String::new();
+        match read_source(full_path)?.content {
+            SourceContent::Text(text) => {
+                let (text, findings) = match &self.redactor {
+                    Some(redactor) => redactor.redact(&text),
+                    None => (text.as_str().into(), Vec::new()),
+                };
+                for finding in &findings {
+                    warn!(
+                        "Redacted {} in {}:{}",
+                        finding.kind,
+                        rel_path.display(),
+                        finding.line
+                    );
+                    redactions.push(Redaction {
+                        path: rel_path.to_path_buf(),
+                        line: finding.line,
+                        kind: finding.kind,
+                    });
+                }
+                let file = TextFile {
+                    redacted: !findings.is_empty(),
+                    ..TextFile::new(Path::new(filename), get_language_tag(full_path), &text)
+                };
+                MarkdownFormat.file(&file, &mut section)?;
+            }
+            SourceContent::Binary { size } => {
+                let file = BinaryFile::new(Path::new(filename), size);
+                MarkdownFormat.binary(&file, &mut section)?;
+            }
         }
 
-        // Get content as string
-        let text = match str::from_utf8(&mmap) {
-            Ok(s) => s.to_string(),
-            Err(_) => std::fs::read_to_string(full_path)
-                .with_context(|| format!("Fallback read failed for {}", full_path.display()))?,
-        };
-
-        // Get language tag for syntax highlighting
-        let lang = get_language_tag(full_path);
-
-        // Calculate fence length
-        let fence = calculate_fence(&text);
-
-        Ok(format!(
-            "## {}\n\n{}{}\n{}\n{}\n\n",
-            filename, fence, lang, text, fence
-        ))
+        Ok(section)
     }
 }
 
-/// Calculates the appropriate fence string for wrapping content.
-///
-/// Returns a fence with at least 3 backticks, or more if the content
-/// contains backtick sequences that would interfere with parsing.
-fn calculate_fence(content: &str) -> String {
-    let max_backtick_run = content
-        .lines()
-        .filter_map(|line| {
-            let trimmed = line.trim_start();
-            if trimmed.starts_with('`') {
-                Some(trimmed.chars().take_while(|&c| c == '`').count())
-            } else {
-                None
-            }
-        })
-        .max()
-        .unwrap_or(0);
-
-    // Minimum fence length is 3, or one more than the max found in content
-    let fence_len = max_backtick_run.max(2) + 1;
-    "`".repeat(fence_len)
+/// Options for [`generate_mdbook_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct MdbookOptions {
+    /// Include minified files, source maps and files marked as generated,
+    /// which are left out by default.
+    pub include_generated: bool,
+    /// Redacts secrets from the text files.
+    pub redactor: Option<Redactor>,
+    /// Fail if any secret is found, and remove the files written by then.
+    pub fail_on_secrets: bool,
 }
 
 /// Generates mdbook output from collected files.
 ///
+/// Minified files, source maps and files marked as generated are left out,
+/// and secrets found by the built-in detectors of [`Redactor`] are redacted.
+///
 /// # Arguments
 /// * `entries` - Collected file entries
 /// * `project_root` - Root directory of the project being processed
@@ -321,10 +396,35 @@ pub async fn generate_mdbook(
     entries: &[DirEntry],
     project_root: &Path,
     output_dir: &Path,
+) -> Result<()> {
+    let options = MdbookOptions {
+        redactor: Some(Redactor::new(true, &[])?),
+        ..MdbookOptions::default()
+    };
+    generate_mdbook_with_options(entries, project_root, output_dir, &options).await
+}
+
+/// Generates mdbook output from collected files with the given options.
+///
+/// Redacted secrets are reported with [`report_redactions`].
+pub async fn generate_mdbook_with_options(
+    entries: &[DirEntry],
+    project_root: &Path,
+    output_dir: &Path,
+    options: &MdbookOptions,
 ) -> Result<()> {
     let mut writer = MdbookWriter::new(project_root.to_path_buf(), output_dir.to_path_buf());
+    writer.set_skip_generated(!options.include_generated);
+    if let Some(redactor) = &options.redactor {
+        writer.set_redactor(redactor.clone());
+    }
     writer.add_files(entries);
-    writer.write().await
+    writer.write().await?;
+    report_redactions(
+        writer.redactions(),
+        options.fail_on_secrets,
+        writer.output_paths(),
+    )
 }
 
 #[cfg(test)]
@@ -373,23 +473,4 @@ mod tests {
         assert!(chapter.has_content());
         assert!(chapter.children["src"].has_content());
     }
-
-    #[test]
-    fn test_calculate_fence_basic() {
-        assert_eq!(calculate_fence("no backticks"), "```");
-        assert_eq!(calculate_fence("```rust\ncode\n```"), "````");
-        assert_eq!(calculate_fence("````\ncode\n````"), "`````");
-    }
-
-    #[test]
-    fn test_calculate_fence_single_backticks() {
-        // Single backticks (inline code) should still result in minimum 3-backtick fence
-        assert_eq!(calculate_fence("`inline code`"), "```");
-    }
-
-    #[test]
-    fn test_calculate_fence_double_backticks() {
-        // Double backticks should still result in minimum 3-backtick fence
-        assert_eq!(calculate_fence("``double``"), "```");
-    }
 }
@@ -0,0 +1,334 @@
+//! Overview sections written at the top of a bundle.
+//!
+//! A [`Preamble`] describes every file in the bundle before the first entry.
+//! [`MarkdownFormat`](crate::writer::MarkdownFormat) renders it as a project
+//! tree in the style of the `tree` command:
+//!
+//! ````markdown
+//! # Project Tree
+//!
+//! ```text
+//! .
+//! ├── src
+//! │   └── main.rs
+//! └── README.md
+//! ```
+//! ````
+//!
+//! Bundles of changed files also list the files deleted by the changes under
+//! a `# Deleted Files` heading, since they have no section of their own.
+//!
+//! The table of contents is written at the end of every part instead, once
+//! it is known which entries made it into that part, so that each link
+//! points at a `## path` heading of the same document
+//! ([`render_contents`]):
+//!
+//! ```markdown
+//! # Contents
+//!
+//! - [src/main.rs](#srcmainrs)
+//! - [README.md](#readmemd)
+//! ```
+//!
+//! These headings are level-1 headings and the tree is fenced, so the
+//! extractor never mistakes them for file sections.
+
+use crate::writer::calculate_fence;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Heading of the project tree section.
+pub const TREE_HEADING: &str = "Project Tree";
+
+/// Heading of the table of contents section.
+pub const TOC_HEADING: &str = "Contents";
+
+/// Heading of the list of deleted files.
+pub const DELETED_HEADING: &str = "Deleted Files";
+
+/// The overview to write at the top of a bundle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preamble {
+    /// Paths of all bundled files relative to the project root, in bundle order.
+    pub paths: Vec<PathBuf>,
+    /// Include a project tree.
+    pub tree: bool,
+    /// End every part with a table of contents of the entries written to it
+    /// (see [`render_contents`]).
+    pub toc: bool,
+    /// Files deleted by the bundled changes, relative to the project root.
+    /// Listed if non-empty.
+    pub deleted: Vec<PathBuf>,
+}
+
+impl Preamble {
+    /// Renders the preamble as Markdown.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+
+        if self.tree {
+            let tree = render_tree(&self.paths);
+            let fence = calculate_fence(&tree);
+            out.push_str(&format!(
+                "# {TREE_HEADING}\n\n{fence}text\n{tree}{fence}\n\n"
+            ));
+        }
+
+        if !self.deleted.is_empty() {
+            out.push_str(&format!("# {DELETED_HEADING}\n\n"));
+            for path in &self.deleted {
+                out.push_str(&format!("- `{}`\n", path.display()));
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Level-1 headings rendered by [`to_markdown`](Self::to_markdown), in
+    /// order.
+    pub fn headings(&self) -> Vec<&'static str> {
+        let mut headings = Vec::new();
+        if self.tree {
+            headings.push(TREE_HEADING);
+        }
+        if !self.deleted.is_empty() {
+            headings.push(DELETED_HEADING);
+        }
+        headings
+    }
+}
+
+/// Renders a table of contents linking to the `## path` sections of `paths`,
+/// for a document in which they follow the level-1 `headings`.
+///
+/// The contents heading itself comes after the sections, so it does not
+/// change their anchors.
+pub fn render_contents(headings: &[&str], paths: &[PathBuf]) -> String {
+    let mut anchors = AnchorSet::new();
+    for heading in headings {
+        anchors.insert(heading);
+    }
+
+    let mut out = format!("# {TOC_HEADING}\n\n");
+    for path in paths {
+        let heading = path.display().to_string();
+        let anchor = anchors.insert(&heading);
+        out.push_str(&format!("- [{}](#{anchor})\n", escape_link_text(&heading)));
+    }
+    out.push('\n');
+    out
+}
+
+/// A directory level of the project tree.
+#[derive(Debug, Default)]
+struct TreeNode {
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    fn insert(&mut self, path: &std::path::Path) {
+        let mut node = self;
+        for component in path.components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name).or_default();
+        }
+    }
+
+    fn render(&self, prefix: &str, out: &mut String) {
+        let count = self.children.len();
+        for (idx, (name, child)) in self.children.iter().enumerate() {
+            let last = idx + 1 == count;
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            out.push_str(&format!("{prefix}{branch}{name}\n"));
+            child.render(&format!("{prefix}{indent}"), out);
+        }
+    }
+}
+
+/// Renders `paths` as a tree in the style of the `tree` command.
+///
+/// Entries are sorted by name at every level; the root is shown as `.`.
+pub fn render_tree(paths: &[PathBuf]) -> String {
+    let mut root = TreeNode::default();
+    for path in paths {
+        root.insert(path);
+    }
+
+    let mut out = String::from(".\n");
+    root.render("", &mut out);
+    out
+}
+
+/// Tracks heading anchors the way GitHub generates them, including the
+/// `-1`, `-2` suffixes for repeated headings.
+#[derive(Debug, Default)]
+struct AnchorSet {
+    seen: HashMap<String, usize>,
+}
+
+impl AnchorSet {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the anchor for the next heading with the given text.
+    fn insert(&mut self, heading: &str) -> String {
+        let slug = heading_slug(heading);
+        let count = self.seen.entry(slug.clone()).or_insert(0);
+        let anchor = if *count == 0 {
+            slug
+        } else {
+            format!("{slug}-{count}")
+        };
+        *count += 1;
+        anchor
+    }
+}
+
+/// Converts heading text to a GitHub-style anchor slug.
+///
+/// Letters are lowercased, spaces become `-`, and punctuation other than
+/// `-` and `_` is dropped.
+pub fn heading_slug(heading: &str) -> String {
+    heading
+        .trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Escapes characters that would end the text of a Markdown link.
+fn escape_link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '[' | ']' | '\\' | '`' | '*' | '_') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let tree = render_tree(&paths(&[
+            "src/main.rs",
+            "README.md",
+            "src/utils/mod.rs",
+            "src/lib.rs",
+        ]));
+        assert_eq!(
+            tree,
+            ".\n\
+             ├── README.md\n\
+             └── src\n\
+             \x20   ├── lib.rs\n\
+             \x20   ├── main.rs\n\
+             \x20   └── utils\n\
+             \x20       └── mod.rs\n"
+        );
+    }
+
+    #[test]
+    fn test_heading_slug() {
+        assert_eq!(heading_slug("src/main.rs"), "srcmainrs");
+        assert_eq!(heading_slug("My File_v2-final.txt"), "my-file_v2-finaltxt");
+        assert_eq!(heading_slug("docs/Ä.md"), "docsämd");
+    }
+
+    #[test]
+    fn test_duplicate_anchors_are_numbered() {
+        let mut anchors = AnchorSet::new();
+        assert_eq!(anchors.insert("a.rs"), "ars");
+        assert_eq!(anchors.insert("a/rs"), "ars-1");
+        assert_eq!(anchors.insert("ars"), "ars-2");
+    }
+
+    #[test]
+    fn test_preamble_markdown() {
+        let preamble = Preamble {
+            paths: paths(&["src/main.rs", "contents"]),
+            tree: true,
+            toc: true,
+            deleted: Vec::new(),
+        };
+        let md = preamble.to_markdown();
+
+        assert!(md.starts_with("# Project Tree\n\n```text\n.\n├── contents\n"));
+        assert!(!md.contains("# Contents"));
+        assert!(!md.contains("\n## "));
+        assert_eq!(preamble.headings(), [TREE_HEADING]);
+    }
+
+    #[test]
+    fn test_render_contents() {
+        let contents = render_contents(
+            &[TREE_HEADING],
+            &paths(&["src/main.rs", "project-tree", "contents"]),
+        );
+        assert_eq!(
+            contents,
+            "# Contents\n\n\
+             - [src/main.rs](#srcmainrs)\n\
+             - [project-tree](#project-tree-1)\n\
+             - [contents](#contents)\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_contents_escapes_link_text() {
+        assert!(
+            render_contents(&[], &paths(&["docs/[draft]_notes.md"]))
+                .contains("- [docs/\\[draft\\]\\_notes.md](#docsdraft_notesmd)\n")
+        );
+    }
+
+    #[test]
+    fn test_preamble_lists_deleted_files() {
+        let preamble = Preamble {
+            paths: paths(&["deleted-files", "src/lib.rs"]),
+            tree: false,
+            toc: true,
+            deleted: paths(&["src/old.rs", "docs/removed.md"]),
+        };
+        let md = preamble.to_markdown();
+
+        assert_eq!(
+            md,
+            "# Deleted Files\n\n- `src/old.rs`\n- `docs/removed.md`\n\n"
+        );
+        // The heading comes before the files, so it claims its anchor first
+        assert!(
+            render_contents(&preamble.headings(), &preamble.paths)
+                .contains("- [deleted-files](#deleted-files-1)\n")
+        );
+
+        let only_deleted = Preamble {
+            deleted: paths(&["gone.rs"]),
+            ..Preamble::default()
+        };
+        assert_eq!(
+            only_deleted.to_markdown(),
+            "# Deleted Files\n\n- `gone.rs`\n\n"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-file size and token statistics.
+//!
+//! [`collect_stats`] measures the entries returned by
+//! [`collect_files`](crate::filewalker::collect_files) without writing a
+//! bundle, so you can see which files would blow a model's context window
+//! before generating anything. Token counts come from
+//! [`estimate_tokens`].
+
+use crate::filewalker::is_recorded_symlink;
+use crate::generated::detect_generated_content;
+use crate::tokens::estimate_tokens;
+use crate::writer::{SourceContent, SourceFile, decode_source, map_file, read_source};
+use anyhow::Result;
+use ignore::DirEntry;
+use log::{debug, error};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Measurements for a single collected file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    /// Path relative to the project root.
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub bytes: u64,
+    /// Number of lines (0 for binary files).
+    pub lines: usize,
+    /// Estimated tokens of the file content (0 for binary files).
+    pub tokens: usize,
+    /// Whether the file was detected as binary.
+    pub binary: bool,
+}
+
+impl FileStats {
+    /// Reads and measures the file at `path`.
+    pub fn measure(path: &Path, project_root: &Path) -> Result<Self> {
+        Ok(Self::from_source(path, project_root, read_source(path)?))
+    }
+
+    /// Reads and measures the file at `path` like [`measure`](Self::measure),
+    /// or returns `None` for a minified or generated file unless
+    /// `include_generated` is true.
+    fn measure_collected(
+        path: &Path,
+        project_root: &Path,
+        include_generated: bool,
+    ) -> Result<Option<Self>> {
+        let (data, mode) = map_file(path)?;
+        if !include_generated && let Some(kind) = detect_generated_content(path, &data) {
+            debug!("Skipping {}: {kind}", path.display());
+            return Ok(None);
+        }
+        let source = decode_source(&data, mode, path);
+        Ok(Some(Self::from_source(path, project_root, source)))
+    }
+
+    /// Measures a file read from `path`.
+    fn from_source(path: &Path, project_root: &Path, source: SourceFile) -> Self {
+        let rel_path = path
+            .strip_prefix(project_root)
+            .unwrap_or(path)
+            .to_path_buf();
+
+        match source.content {
+            SourceContent::Text(content) => Self {
+                path: rel_path,
+                bytes: content.len() as u64,
+                lines: content.lines().count(),
+                tokens: estimate_tokens(&content),
+                binary: false,
+            },
+            SourceContent::Binary { size } => Self {
+                path: rel_path,
+                bytes: size,
+                lines: 0,
+                tokens: 0,
+                binary: true,
+            },
+        }
+    }
+}
+
+/// Statistics for a set of collected files.
+///
+/// Files are sorted by size, largest first. The [`Display`](fmt::Display)
+/// implementation renders the report as a plain-text table with totals.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsReport {
+    /// Per-file measurements, largest first.
+    pub files: Vec<FileStats>,
+}
+
+impl StatsReport {
+    /// Creates a report from measurements, sorting them by size.
+    pub fn new(mut files: Vec<FileStats>) -> Self {
+        files.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        Self { files }
+    }
+
+    /// Total size in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+
+    /// Total number of lines.
+    pub fn total_lines(&self) -> usize {
+        self.files.iter().map(|f| f.lines).sum()
+    }
+
+    /// Total estimated tokens.
+    pub fn total_tokens(&self) -> usize {
+        self.files.iter().map(|f| f.tokens).sum()
+    }
+}
+
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total_bytes = self.total_bytes().to_string();
+        let total_lines = self.total_lines().to_string();
+        let total_tokens = self.total_tokens().to_string();
+
+        // Totals are the widest value in each column
+        let bytes_width = total_bytes.len().max("Bytes".len());
+        let lines_width = total_lines.len().max("Lines".len());
+        let tokens_width = total_tokens.len().max("Tokens".len());
+
+        writeln!(
+            f,
+            "{:>bytes_width$}  {:>lines_width$}  {:>tokens_width$}  Path",
+            "Bytes", "Lines", "Tokens"
+        )?;
+        for file in &self.files {
+            let suffix = if file.binary { " (binary)" } else { "" };
+            writeln!(
+                f,
+                "{:>bytes_width$}  {:>lines_width$}  {:>tokens_width$}  {}{suffix}",
+                file.bytes,
+                file.lines,
+                file.tokens,
+                file.path.display()
+            )?;
+        }
+        writeln!(
+            f,
+            "{total_bytes:>bytes_width$}  {total_lines:>lines_width$}  {total_tokens:>tokens_width$}  total ({} files)",
+            self.files.len()
+        )
+    }
+}
+
+/// Measures every entry returned by [`collect_files`](crate::filewalker::collect_files).
+///
+/// Minified files, source maps and files marked as generated are left out
+/// like they are from a bundle, unless `include_generated` is true (see
+/// [`detect_generated`](crate::generated::detect_generated)).
+///
+/// If `fail_fast` is true, stops on the first file that cannot be read.
+/// Otherwise, logs the error and leaves the file out of the report.
+pub fn collect_stats(
+    entries: &[DirEntry],
+    project_root: &Path,
+    include_generated: bool,
+    fail_fast: bool,
+) -> Result<StatsReport> {
+    let mut files = Vec::with_capacity(entries.len());
+
+    // Recorded symbolic links carry no content
+    for entry in entries.iter().filter(|entry| !is_recorded_symlink(entry)) {
+        match FileStats::measure_collected(entry.path(), project_root, include_generated) {
+            Ok(Some(stats)) => files.push(stats),
+            Ok(None) => {}
+            Err(e) => {
+                if fail_fast {
+                    return Err(e);
+                }
+                error!("Failed to measure {}: {e}", entry.path().display());
+            }
+        }
+    }
+
+    Ok(StatsReport::new(files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(path: &str, bytes: u64, lines: usize, tokens: usize) -> FileStats {
+        FileStats {
+            path: PathBuf::from(path),
+            bytes,
+            lines,
+            tokens,
+            binary: false,
+        }
+    }
+
+    #[test]
+    fn test_report_sorted_by_size() {
+        let report = StatsReport::new(vec![
+            stats("b.rs", 10, 1, 3),
+            stats("a.rs", 200, 20, 50),
+            stats("c.rs", 10, 2, 4),
+        ]);
+        let paths: Vec<_> = report.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(paths, ["a.rs", "b.rs", "c.rs"].map(PathBuf::from));
+        assert_eq!(report.total_bytes(), 220);
+        assert_eq!(report.total_lines(), 23);
+        assert_eq!(report.total_tokens(), 57);
+    }
+
+    #[test]
+    fn test_report_display() {
+        let mut logo = stats("logo.png", 5, 0, 0);
+        logo.binary = true;
+        let report = StatsReport::new(vec![stats("src/main.rs", 12, 1, 4), logo]);
+
+        assert_eq!(
+            report.to_string(),
+            "Bytes  Lines  Tokens  Path\n\
+             \x20  12      1       4  src/main.rs\n\
+             \x20   5      0       0  logo.png (binary)\n\
+             \x20  17      1       4  total (2 files)\n"
+        );
+    }
+
+    #[test]
+    fn test_measure_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n// done\n")?;
+
+        let stats = FileStats::measure(&path, dir.path())?;
+        assert_eq!(stats.path, PathBuf::from("main.rs"));
+        assert_eq!(stats.bytes, 21);
+        assert_eq!(stats.lines, 2);
+        assert!(stats.tokens > 0);
+        assert!(!stats.binary);
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Token estimation.
+//!
+//! Language models count input in tokens rather than bytes. This module
+//! provides a fast, offline estimate that approximates byte-pair-encoding
+//! tokenizers such as the ones used by current chat models:
+//!
+//! - A run of letters is one token per ~6 characters, and a single leading
+//!   space is merged into the word that follows it
+//! - Digits are grouped in threes
+//! - Any other run of whitespace (indentation, blank lines) is one token
+//! - Punctuation is one token per two characters
+//! - Every non-ASCII character counts as one token
+//!
+//! The estimate is intentionally a little pessimistic for source code, so a
+//! budget based on it rarely overflows a real context window.
+
+/// Estimates the number of tokens a language model would need for `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut tokens = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        let start = i;
+
+        if b.is_ascii_alphabetic() {
+            while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            tokens += 1 + (i - start - 1) / 6;
+        } else if b.is_ascii_digit() {
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            tokens += (i - start).div_ceil(3);
+        } else if b == b' ' && bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic) {
+            // A single space is merged into the following word
+            i += 1;
+        } else if b.is_ascii_whitespace() {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                // Leave a final space to be merged into the next word
+                if bytes[i] == b' '
+                    && i > start
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic)
+                {
+                    break;
+                }
+                i += 1;
+            }
+            tokens += 1;
+        } else if b.is_ascii() {
+            while i < bytes.len()
+                && bytes[i].is_ascii()
+                && !bytes[i].is_ascii_alphanumeric()
+                && !bytes[i].is_ascii_whitespace()
+            {
+                i += 1;
+            }
+            tokens += (i - start).div_ceil(2);
+        } else {
+            // Multi-byte UTF-8 sequence: skip continuation bytes
+            i += 1;
+            while i < bytes.len() && (bytes[i] & 0xC0) == 0x80 {
+                i += 1;
+            }
+            tokens += 1;
+        }
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(estimate_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_words_merge_leading_space() {
+        assert_eq!(estimate_tokens("hello"), 1);
+        assert_eq!(estimate_tokens("hello world"), 2);
+        assert_eq!(estimate_tokens("the quick brown fox"), 4);
+    }
+
+    #[test]
+    fn test_long_identifiers_cost_more() {
+        assert!(estimate_tokens("internationalization") > estimate_tokens("intern"));
+    }
+
+    #[test]
+    fn test_digits_grouped() {
+        assert_eq!(estimate_tokens("123"), 1);
+        assert_eq!(estimate_tokens("1234567"), 3);
+    }
+
+    #[test]
+    fn test_code() {
+        let code = "fn main() {\n    println!(\"Hello, world!\");\n}\n";
+        let tokens = estimate_tokens(code);
+        assert!((10..=25).contains(&tokens), "got {tokens}");
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        assert_eq!(estimate_tokens("日本語"), 3);
+    }
+}
@@ -1,6 +1,14 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use sha2::{Digest, Sha256};
 use std::ffi::OsStr;
 use std::path::Path;
 
+/// Line width of base64 data embedded in Markdown bundles.
+const BASE64_LINE_WIDTH: usize = 76;
+
 /// Maps file extensions to Markdown language tags for syntax highlighting.
 ///
 /// Returns an empty string for unknown extensions, allowing the code block
@@ -223,11 +231,139 @@ pub fn get_language_tag(path: &Path) -> &'static str {
     }
 }
 
+/// Returns the lowercase hex SHA-256 digest of `data`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Encodes `data` as standard base64.
+pub fn encode_base64(data: &[u8]) -> String {
+    BASE64.encode(data)
+}
+
+/// Encodes `data` as standard base64 split into lines of 76 characters,
+/// each terminated by a newline.
+pub fn encode_base64_lines(data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / BASE64_LINE_WIDTH + 1);
+    // Base64 output is ASCII, so chunking bytes never splits a character
+    for line in encoded.as_bytes().chunks(BASE64_LINE_WIDTH) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push('\n');
+    }
+    out
+}
+
+/// Decodes standard base64, ignoring line breaks and other whitespace.
+pub fn decode_base64(encoded: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    let compact: String = encoded.split_whitespace().collect();
+    BASE64.decode(compact)
+}
+
+/// Decodes text that is not valid UTF-8.
+///
+/// UTF-16 is recognized by its byte order mark, which is kept as U+FEFF;
+/// legacy encodings such as ISO-8859-1 or Shift_JIS are guessed from the
+/// content. Returns `None` unless the text encodes back to exactly `bytes`,
+/// so a restored file is always identical to the original.
+pub fn decode_text(bytes: &[u8]) -> Option<(String, &'static Encoding)> {
+    let encoding = if bytes.starts_with(&[0xFF, 0xFE]) {
+        UTF_16LE
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        UTF_16BE
+    } else {
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        detector.guess(None, false)
+    };
+
+    let text = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+    if encode_text(&text, encoding)? != bytes {
+        return None;
+    }
+    Some((text.into_owned(), encoding))
+}
+
+/// Encodes `text` in `encoding`.
+///
+/// Returns `None` if the text contains characters the encoding cannot
+/// represent.
+pub fn encode_text(text: &str, encoding: &'static Encoding) -> Option<Vec<u8>> {
+    // encoding_rs decodes UTF-16 but has no encoder for it
+    if encoding == UTF_16LE {
+        return Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if encoding == UTF_16BE {
+        return Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+
+    let (bytes, _, unmappable) = encoding.encode(text);
+    (!unmappable).then(|| bytes.into_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_decode_legacy_text() {
+        let latin1 = b"/* Gr\xfc\xdfe aus K\xf6ln */\nint main(void) { return 0; }\n";
+        let (text, encoding) = decode_text(latin1).expect("latin-1 text");
+        assert!(text.contains("Grüße aus Köln"));
+        assert_eq!(encoding, encoding_rs::WINDOWS_1252);
+        assert_eq!(encode_text(&text, encoding).as_deref(), Some(&latin1[..]));
+
+        let sjis = encoding_rs::SHIFT_JIS
+            .encode("// こんにちは、世界。日本語のコメントです。\n")
+            .0;
+        let (text, encoding) = decode_text(&sjis).expect("shift_jis text");
+        assert_eq!(encoding, encoding_rs::SHIFT_JIS);
+        assert!(text.contains("日本語"));
+    }
+
+    #[test]
+    fn test_decode_utf16_keeps_bom() {
+        let bytes: Vec<u8> = "\u{feff}hi\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let (text, encoding) = decode_text(&bytes).expect("utf-16 text");
+        assert_eq!(text, "\u{feff}hi\r\n");
+        assert_eq!(encoding, UTF_16LE);
+        assert_eq!(encode_text(&text, encoding), Some(bytes));
+    }
+
+    #[test]
+    fn test_encode_text_unmappable() {
+        assert_eq!(
+            encode_text("€", encoding_rs::WINDOWS_1252),
+            Some(vec![0x80])
+        );
+        assert_eq!(encode_text("日本", encoding_rs::WINDOWS_1252), None);
+    }
+
+    #[test]
+    fn test_base64_lines_roundtrip() {
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = encode_base64_lines(&data);
+        assert!(encoded.lines().all(|line| line.len() <= 76));
+        assert!(encoded.ends_with('\n'));
+        assert_eq!(decode_base64(&encoded).unwrap(), data);
+        assert_eq!(encode_base64_lines(&[]), "");
+    }
+
     #[test]
     fn test_common_extensions() {
         assert_eq!(get_language_tag(Path::new("main.rs")), "rust");
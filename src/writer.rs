@@ -1,136 +1,1280 @@
-use crate::utils::get_language_tag;
+use crate::filewalker::{CollectedFile, VirtualContent, VirtualEntry, is_src2md_content};
+use crate::format::{BinaryFile, Contents, OutputFormat, OversizedFile, SymlinkFile, TextFile};
+use crate::generated::{GeneratedKind, detect_generated_content};
+#[cfg(feature = "git")]
+use crate::git::GitBlob;
+use crate::manifest::{FileMeta, Manifest, TextLayout};
+use crate::preamble::{Preamble, render_contents};
+use crate::redact::{Finding, Redaction, Redactor};
+use crate::tokens::estimate_tokens;
+use crate::utils::{decode_text, encode_base64_lines, get_language_tag, sha256_hex};
 use anyhow::{Context, Result};
 use content_inspector::{ContentType, inspect};
-use ignore::DirEntry;
-use log::debug;
-use memmap2::MmapOptions;
+use encoding_rs::Encoding;
+use log::{debug, info, warn};
+use memmap2::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fs::File as StdFile;
-use std::path::Path;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::str;
-use tokio::fs::File;
-use tokio::io::{AsyncWriteExt, BufWriter};
+use std::sync::Arc;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::task;
 
 /// Magic header that identifies files generated by src2md.
 /// This is used to prevent reading our own output files during collection.
 /// Format: HTML comment with version identifier for future compatibility.
-pub const OUTPUT_MAGIC_HEADER: &str = "<!-- src2md:v1 -->\n";
+pub const OUTPUT_MAGIC_HEADER: &str = "<!-- src2md:v2 -->\n";
 
 /// The byte sequence to check at the start of files to detect src2md output.
-pub const OUTPUT_MAGIC_BYTES: &[u8] = b"<!-- src2md:v1 -->";
+pub const OUTPUT_MAGIC_BYTES: &[u8] = b"<!-- src2md:v2 -->";
 
-/// A writer that generates Markdown output with syntax-highlighted code blocks.
+/// The magic header of format v1 bundles, which are still recognized and
+/// restored.
+pub const OUTPUT_MAGIC_BYTES_V1: &[u8] = b"<!-- src2md:v1 -->";
+
+/// Placeholder written in place of binary files that are not embedded.
+pub const BINARY_OMITTED_MARKER: &str = "(binary file omitted)";
+
+/// Start of the placeholder written in place of files that exceed the
+/// [`FileLimits`]; see [`OversizedFile::marker`].
+pub const OVERSIZED_OMITTED_PREFIX: &str = "(omitted: file too large";
+
+/// Start of the line that replaces the middle of a truncated text file, e.g.
+/// `[... truncated by src2md: 1200 lines (48213 bytes) omitted ...]`.
+pub const TRUNCATION_MARKER_PREFIX: &str = "[... truncated by src2md:";
+
+/// Info string of fenced blocks holding base64-encoded binary files.
+pub const BASE64_INFO_STRING: &str = "base64";
+
+/// Default size limit for embedded binary files (1 MiB), used unless
+/// [`Config::max_embed_size`](crate::Config::max_embed_size) sets another.
+pub const DEFAULT_MAX_EMBED_SIZE: u64 = 1024 * 1024;
+
+/// A bundle writer using the default Markdown layout.
+///
+/// This is the historical name of [`BundleWriter`]; `MarkdownWriter::new`
+/// produces a writer driving [`MarkdownFormat`].
+pub type MarkdownWriter<W> = BundleWriter<W>;
+
+/// Content of a source file after reading and binary detection.
+pub(crate) enum SourceContent {
+    Text(String),
+    Binary { size: u64 },
+}
+
+/// What is rendered for a collected entry.
+enum EntrySource {
+    /// A file and, for embedded binary files, its content.
+    File {
+        source: SourceFile,
+        embedded: Option<Vec<u8>>,
+        /// Whether the text content was cut down by [`FileLimits::truncate`].
+        truncated: bool,
+        /// Whether secrets in the text content were replaced.
+        redacted: bool,
+    },
+    /// A symbolic link and its target.
+    Symlink { target: String },
+    /// A file over the [`FileLimits`] that is left out.
+    Oversized { size: u64, lines: Option<usize> },
+    /// A minified or generated file that is skipped.
+    Generated(GeneratedKind),
+    /// A src2md output file that is skipped. Files on disk are left out
+    /// during collection, others once they are read.
+    Output,
+}
+
+/// A source file read from disk.
+pub(crate) struct SourceFile {
+    pub(crate) content: SourceContent,
+    /// Size of the file on disk in bytes.
+    pub(crate) size: u64,
+    /// Original encoding of a text file that is not UTF-8.
+    pub(crate) charset: Option<&'static Encoding>,
+    /// Hex SHA-256 of the file as stored on disk.
+    pub(crate) sha256: String,
+    /// Unix permission bits (`None` on other platforms).
+    pub(crate) mode: Option<u32>,
+}
+
+/// Reads a file from disk, detecting whether it is binary.
+///
+/// The file is memory-mapped and the first 8 KiB are inspected with
+/// `content_inspector`. Text that is not UTF-8 is transcoded with
+/// [`decode_text`]; files that cannot be decoded losslessly are treated as
+/// binary.
+pub(crate) fn read_source(path: &Path) -> Result<SourceFile> {
+    let (mmap, mode) = map_file(path)?;
+    Ok(decode_source(&mmap, mode, path))
+}
+
+/// Memory-maps a file for reading and returns it with its permission bits.
+pub(crate) fn map_file(path: &Path) -> Result<(Mmap, Option<u32>)> {
+    let file =
+        StdFile::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+
+    // SAFETY: We only read from the memory-mapped region and the file
+    // remains open for the duration of the mapping.
+    let mmap = unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .with_context(|| format!("Failed to mmap file: {}", path.display()))?
+    };
+    Ok((mmap, file_mode(&file)))
+}
+
+/// Detects whether the bytes of a file are binary and decodes text, like
+/// [`read_source`] for a file that is not on disk. `path` is only used for
+/// logging.
+pub(crate) fn decode_source(data: &[u8], mode: Option<u32>, path: &Path) -> SourceFile {
+    // Inspect file content to detect binary files
+    let sample_size = std::cmp::min(8192, data.len());
+    let content_type = inspect(&data[..sample_size]);
+
+    let size = data.len() as u64;
+    let sha256 = sha256_hex(data);
+
+    let text = match content_type {
+        ContentType::BINARY | ContentType::UTF_32LE | ContentType::UTF_32BE => None,
+        _ => match str::from_utf8(data) {
+            Ok(s) => Some((s.to_string(), None)),
+            Err(_) => {
+                let decoded = decode_text(data);
+                match &decoded {
+                    Some((_, encoding)) => {
+                        debug!("Decoded {} as {}", path.display(), encoding.name())
+                    }
+                    None => debug!(
+                        "Treating {} as binary: unknown text encoding",
+                        path.display()
+                    ),
+                }
+                decoded.map(|(text, encoding)| (text, Some(encoding)))
+            }
+        },
+    };
+
+    let (content, charset) = match text {
+        Some((text, charset)) => (SourceContent::Text(text), charset),
+        None => (SourceContent::Binary { size }, None),
+    };
+    SourceFile {
+        content,
+        size,
+        charset,
+        sha256,
+        mode,
+    }
+}
+
+/// Returns the permission bits of an open file.
+#[cfg(unix)]
+fn file_mode(file: &StdFile) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.metadata()
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+}
+
+/// Returns the permission bits of an open file.
+#[cfg(not(unix))]
+fn file_mode(_file: &StdFile) -> Option<u32> {
+    None
+}
+
+/// The default Markdown layout.
 ///
+/// Each file becomes a `## path` heading followed by a fenced code block.
 /// Uses dynamic backtick fencing to safely embed code that may contain
 /// triple backticks (like other Markdown files).
-pub struct MarkdownWriter<W: AsyncWriteExt + Unpin> {
+///
+/// When the checksum of a file is known, its content is written with LF line
+/// endings and without a byte order mark, and the original [`TextLayout`] is
+/// recorded in the metadata comment.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MarkdownFormat;
+
+impl OutputFormat for MarkdownFormat {
+    /// Writes the magic header that identifies this as a src2md output file,
+    /// followed by the manifest comment.
+    /// This header allows us to skip reading our own output during collection.
+    fn begin(&mut self, manifest: Option<&Manifest>, out: &mut String) -> Result<()> {
+        out.push_str(OUTPUT_MAGIC_HEADER);
+        if let Some(manifest) = manifest {
+            out.push_str(&manifest.to_comment()?);
+            out.push('\n');
+        }
+        out.push('\n');
+        Ok(())
+    }
+
+    /// Writes the project tree and the list of deleted files.
+    fn preamble(&mut self, preamble: &Preamble, out: &mut String) -> Result<()> {
+        out.push_str(&preamble.to_markdown());
+        Ok(())
+    }
+
+    /// Writes a table of contents linking to the sections of the part.
+    fn contents(&mut self, contents: &Contents<'_>, out: &mut String) -> Result<()> {
+        out.push_str(&render_contents(contents.headings, contents.paths));
+        Ok(())
+    }
+
+    fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()> {
+        // Redacted files have no checksum, but still record their layout
+        let meta = (file.sha256.is_some() || file.redacted).then(|| FileMeta {
+            size: file.size,
+            sha256: file.sha256.map(str::to_string),
+            mode: file.mode,
+            charset: file.charset.map(str::to_string),
+            truncated: file.truncated,
+            redacted: file.redacted,
+            ..FileMeta::default()
+        });
+        let layout = meta.as_ref().map(|_| TextLayout::detect(file.content));
+        let content = match layout {
+            Some(layout) => layout.normalize(file.content),
+            None => Cow::Borrowed(file.content),
+        };
+
+        // Calculate the minimum fence length needed to safely wrap this content
+        let fence = calculate_fence(&content);
+
+        push_heading(
+            file.path,
+            meta.zip(layout)
+                .map(|(meta, layout)| meta.with_layout(layout)),
+            out,
+        )?;
+        out.push_str(&format!("{}{}\n", fence, file.language));
+        out.push_str(&content);
+        // The extractor drops the line break before the closing fence, and
+        // the recorded layout tells it whether the file had one
+        if layout.is_none() || !content.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&format!("{}\n\n", fence));
+        Ok(())
+    }
+
+    /// Writes embedded files as a `base64` block carrying the size and
+    /// SHA-256 of the content, and other binary files as a placeholder.
+    fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()> {
+        push_heading(file.path, file_meta(file.size, file.sha256, file.mode), out)?;
+        match file.content {
+            Some(content) => {
+                out.push_str(&format!(
+                    "```{BASE64_INFO_STRING} size={} sha256={}\n",
+                    content.len(),
+                    sha256_hex(content)
+                ));
+                out.push_str(&encode_base64_lines(content));
+                out.push_str("```\n\n");
+            }
+            None => {
+                out.push_str(BINARY_OMITTED_MARKER);
+                out.push_str("\n\n");
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the link target to the metadata comment, where the extractor
+    /// reads it, and as a note for human readers.
+    fn symlink(&mut self, link: &SymlinkFile<'_>, out: &mut String) -> Result<()> {
+        let meta = FileMeta {
+            size: link.target.len() as u64,
+            symlink: Some(link.target.to_string()),
+            ..FileMeta::default()
+        };
+        push_heading(link.path, Some(meta), out)?;
+        out.push_str(&format!("(symbolic link to {})\n\n", link.target));
+        Ok(())
+    }
+
+    /// Writes the placeholder, and marks the entry as omitted in the
+    /// metadata comment so that the extractor does not restore it.
+    fn oversized(&mut self, file: &OversizedFile<'_>, out: &mut String) -> Result<()> {
+        let meta = FileMeta {
+            size: file.size,
+            omitted: true,
+            ..FileMeta::default()
+        };
+        push_heading(file.path, Some(meta), out)?;
+        out.push_str(&file.marker());
+        out.push_str("\n\n");
+        Ok(())
+    }
+}
+
+/// Returns the metadata to record for a file, if its checksum is known.
+fn file_meta(size: u64, sha256: Option<&str>, mode: Option<u32>) -> Option<FileMeta> {
+    sha256.map(|sha256| FileMeta {
+        size,
+        sha256: Some(sha256.to_string()),
+        mode,
+        ..FileMeta::default()
+    })
+}
+
+/// Writes a `## path` heading, followed by a metadata comment if given.
+fn push_heading(path: &Path, meta: Option<FileMeta>, out: &mut String) -> Result<()> {
+    out.push_str(&format!("## {}\n", path.display()));
+    if let Some(meta) = meta {
+        out.push_str(&meta.to_comment()?);
+        out.push('\n');
+    }
+    out.push('\n');
+    Ok(())
+}
+
+/// How binary files are written to a bundle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryMode {
+    /// Write a placeholder instead of the content.
+    #[default]
+    Omit,
+    /// Embed the content of files up to `max_size` bytes (no limit if
+    /// `None`); larger files are omitted.
+    Embed {
+        /// Largest file size to embed.
+        max_size: Option<u64>,
+    },
+}
+
+impl BinaryMode {
+    /// Returns true if a binary file of the given size is embedded.
+    pub fn embeds(self, size: u64) -> bool {
+        match self {
+            Self::Omit => false,
+            Self::Embed { max_size } => max_size.is_none_or(|max| size <= max),
+        }
+    }
+}
+
+/// Size limits for a single file.
+///
+/// A file over a limit is replaced by a placeholder (see
+/// [`OutputFormat::oversized`]) without being read, unless `truncate` is
+/// set: text files are then cut down to their first and last lines around
+/// an elision marker, and binary files are not embedded. Either way the
+/// entry is marked so that the extractor refuses to restore it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileLimits {
+    /// Largest file size in bytes.
+    pub max_size: Option<u64>,
+    /// Largest number of lines of a text file.
+    pub max_lines: Option<usize>,
+    /// Keep the head and tail of text files over a limit instead of
+    /// omitting them.
+    pub truncate: bool,
+}
+
+impl FileLimits {
+    /// Returns true if a file of the given size exceeds `max_size`.
+    pub fn exceeds_size(&self, size: u64) -> bool {
+        self.max_size.is_some_and(|max| size > max)
+    }
+
+    /// Returns true if a text file exceeds a limit.
+    fn exceeds(&self, size: u64, lines: Option<usize>) -> bool {
+        self.exceeds_size(size)
+            || self
+                .max_lines
+                .zip(lines)
+                .is_some_and(|(max, lines)| lines > max)
+    }
+
+    /// Cuts `content` down to at most half of each limit from its start and
+    /// half from its end, with a [`TRUNCATION_MARKER_PREFIX`] line in between.
+    ///
+    /// Only whole lines are kept, so a file made of a few very long lines
+    /// may be reduced to the marker alone. Returns `None` if the content is
+    /// within the limits.
+    pub fn truncate_text(&self, content: &str) -> Option<String> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let line_budget = self.max_lines.unwrap_or(usize::MAX);
+        let byte_budget = self
+            .max_size
+            .map_or(usize::MAX, |max| usize::try_from(max).unwrap_or(usize::MAX));
+
+        let mut head = 0;
+        let mut head_bytes = 0;
+        while head < lines.len()
+            && head < line_budget - line_budget / 2
+            && head_bytes + lines[head].len() <= byte_budget - byte_budget / 2
+        {
+            head_bytes += lines[head].len();
+            head += 1;
+        }
+
+        let mut tail = lines.len();
+        let mut tail_bytes = 0;
+        while tail > head
+            && lines.len() - tail < line_budget / 2
+            && tail_bytes + lines[tail - 1].len() <= byte_budget / 2
+        {
+            tail -= 1;
+            tail_bytes += lines[tail].len();
+        }
+
+        if head == tail {
+            return None;
+        }
+
+        let omitted = &lines[head..tail];
+        let omitted_bytes: usize = omitted.iter().map(|line| line.len()).sum();
+        let mut out = lines[..head].concat();
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "{TRUNCATION_MARKER_PREFIX} {} lines ({omitted_bytes} bytes) omitted ...]\n",
+            omitted.len()
+        ));
+        out.push_str(&lines[tail..].concat());
+        Some(out)
+    }
+}
+
+/// Size limits for a single bundle part.
+///
+/// When a limit is set, [`BundleWriter::create`] starts a new numbered part
+/// whenever the next file would push the current part over the limit. A file
+/// that exceeds the limit on its own is written to a part of its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartBudget {
+    /// Maximum estimated tokens per part (see [`estimate_tokens`]).
+    pub max_tokens: Option<usize>,
+    /// Maximum bytes per part.
+    pub max_bytes: Option<usize>,
+}
+
+impl PartBudget {
+    /// Returns true if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_tokens.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Returns true if a part of the given size would exceed a limit.
+    fn is_exceeded_by(&self, size: PartSize) -> bool {
+        self.max_bytes.is_some_and(|max| size.bytes > max)
+            || self.max_tokens.is_some_and(|max| size.tokens > max)
+    }
+
+    /// Measures rendered text against the limits that are set.
+    fn measure(&self, text: &str) -> PartSize {
+        PartSize {
+            bytes: text.len(),
+            tokens: if self.max_tokens.is_some() {
+                estimate_tokens(text)
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Accumulated size of a bundle part.
+#[derive(Debug, Clone, Copy, Default)]
+struct PartSize {
+    bytes: usize,
+    tokens: usize,
+}
+
+impl std::ops::Add for PartSize {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            tokens: self.tokens + other.tokens,
+        }
+    }
+}
+
+/// Returns the path of part `index` (1-based) of a split bundle.
+///
+/// The first part is `path` itself; later parts insert `.partN` before the
+/// extension, e.g. `bundle.md`, `bundle.part2.md`, `bundle.part3.md`.
+pub fn part_path(path: &Path, index: usize) -> PathBuf {
+    if index <= 1 {
+        return path.to_path_buf();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}.part{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.part{index}"),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Opens the file of the next part of a split bundle.
+type OpenPart<W> = fn(PathBuf) -> Pin<Box<dyn Future<Output = std::io::Result<W>> + Send>>;
+
+/// Removes the parts of the bundle at `path`, starting with part `first`,
+/// that are left over from an earlier run split into more parts, so that
+/// they are not restored along with this one. Stops at the first part that
+/// is missing or not a src2md bundle.
+async fn remove_stale_parts(path: &Path, first: usize) {
+    for index in first.. {
+        let part = part_path(path, index);
+        let mut head = [0; 64];
+        let read = match File::open(&part).await {
+            Ok(mut file) => file.read(&mut head).await.unwrap_or(0),
+            Err(_) => break,
+        };
+        if !is_src2md_content(&head[..read]) {
+            warn!(
+                "Leaving {} in place: it is not a src2md bundle",
+                part.display()
+            );
+            break;
+        }
+        match tokio::fs::remove_file(&part).await {
+            Ok(()) => info!("Removed stale part: {}", part.display()),
+            Err(err) => {
+                warn!("Failed to remove stale part {}: {err}", part.display());
+                break;
+            }
+        }
+    }
+}
+
+/// State for writing a bundle split across multiple files.
+struct SplitState<W> {
+    base_path: PathBuf,
+    budget: PartBudget,
+    open: OpenPart<W>,
+    paths: Vec<PathBuf>,
+    part_size: PartSize,
+    part_entries: usize,
+}
+
+/// Number of files read concurrently by [`BundleWriter::write_entries`]
+/// unless configured otherwise: the available parallelism of the machine.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Reads collected files as configured on a [`BundleWriter`]. Cheap to
+/// clone, so that every read task gets its own.
+#[derive(Debug, Clone, Default)]
+struct EntryReader {
+    binary_mode: BinaryMode,
+    limits: FileLimits,
+    redactor: Option<Arc<Redactor>>,
+    skip_generated: bool,
+}
+
+/// A collected entry read from disk or memory, ready to be rendered.
+struct PreparedEntry {
+    path: PathBuf,
+    rel_path: PathBuf,
+    source: EntrySource,
+    findings: Vec<Finding>,
+}
+
+/// Where the bytes of a collected file come from.
+#[derive(Clone, Copy)]
+enum Origin<'a> {
+    Disk(&'a Path),
+    Memory {
+        data: &'a [u8],
+        mode: Option<u32>,
+    },
+    #[cfg(feature = "git")]
+    Blob {
+        blob: &'a GitBlob,
+        mode: Option<u32>,
+    },
+}
+
+impl<'a> Origin<'a> {
+    /// Maps or borrows the bytes of the file, along with its permission bits.
+    fn load(self) -> Result<(Loaded<'a>, Option<u32>)> {
+        match self {
+            Self::Disk(path) => {
+                let (mmap, mode) = map_file(path)?;
+                Ok((Loaded::Mapped(mmap), mode))
+            }
+            Self::Memory { data, mode } => Ok((Loaded::Borrowed(data), mode)),
+            #[cfg(feature = "git")]
+            Self::Blob { blob, mode } => Ok((Loaded::Owned(blob.read()?), mode)),
+        }
+    }
+}
+
+/// The bytes of a collected file, read once and shared by decoding and
+/// binary embedding.
+enum Loaded<'a> {
+    Mapped(Mmap),
+    Borrowed(&'a [u8]),
+    #[cfg(feature = "git")]
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for Loaded<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => mmap,
+            Self::Borrowed(data) => data,
+            #[cfg(feature = "git")]
+            Self::Owned(data) => data,
+        }
+    }
+}
+
+impl EntryReader {
+    /// Reads a collected entry, or the target of a recorded symbolic link.
+    fn prepare(&self, entry: &impl CollectedFile, project_root: &Path) -> Result<PreparedEntry> {
+        let path = entry.path();
+        let rel_path = path.strip_prefix(project_root).unwrap_or(path);
+
+        debug!("Processing: {}", rel_path.display());
+
+        let (source, findings) = match entry.as_virtual() {
+            Some(VirtualEntry {
+                content: VirtualContent::Symlink(target),
+                ..
+            }) => (
+                EntrySource::Symlink {
+                    target: target.clone(),
+                },
+                Vec::new(),
+            ),
+            Some(VirtualEntry {
+                content: VirtualContent::File(data),
+                mode,
+                ..
+            }) => self.read(Origin::Memory { data, mode: *mode }, path, rel_path)?,
+            #[cfg(feature = "git")]
+            Some(VirtualEntry {
+                content: VirtualContent::Blob(blob),
+                mode,
+                ..
+            }) => self.read(Origin::Blob { blob, mode: *mode }, path, rel_path)?,
+            None if entry.is_recorded_symlink() => {
+                let target = std::fs::read_link(path)
+                    .with_context(|| format!("Failed to read link: {}", path.display()))?;
+                let target = target.to_string_lossy().into_owned();
+                (EntrySource::Symlink { target }, Vec::new())
+            }
+            None => self.read(Origin::Disk(path), path, rel_path)?,
+        };
+        Ok(PreparedEntry {
+            path: path.to_path_buf(),
+            rel_path: rel_path.to_path_buf(),
+            source,
+            findings,
+        })
+    }
+
+    /// Reads a file, skips it if it is generated and applies the redactor,
+    /// binary mode and file limits.
+    fn read(
+        &self,
+        origin: Origin<'_>,
+        path: &Path,
+        rel_path: &Path,
+    ) -> Result<(EntrySource, Vec<Finding>)> {
+        let limits = self.limits;
+        // Blobs are read as a whole, so those over the size limit are left
+        // out before they are read
+        #[cfg(feature = "git")]
+        if let Origin::Blob { blob, .. } = origin
+            && !limits.truncate
+            && limits.exceeds_size(blob.size())
+        {
+            let size = blob.size();
+            debug!("Omitting {}: {size} bytes", rel_path.display());
+            return Ok((EntrySource::Oversized { size, lines: None }, Vec::new()));
+        }
+
+        // Mapping is lazy: generated file detection reads the first 64 KiB
+        // only, and oversized files are not read at all
+        let (data, mode) = origin.load()?;
+        if !matches!(origin, Origin::Disk(_)) && is_src2md_content(&data) {
+            return Ok((EntrySource::Output, Vec::new()));
+        }
+        if self.skip_generated
+            && let Some(kind) = detect_generated_content(path, &data)
+        {
+            return Ok((EntrySource::Generated(kind), Vec::new()));
+        }
+
+        let size = data.len() as u64;
+        if !limits.truncate && limits.exceeds_size(size) {
+            debug!("Omitting {}: {size} bytes", rel_path.display());
+            return Ok((EntrySource::Oversized { size, lines: None }, Vec::new()));
+        }
+
+        let mut source = decode_source(&data, mode, path);
+        let mut truncated = false;
+        let mut findings = Vec::new();
+        if let (Some(redactor), SourceContent::Text(content)) =
+            (self.redactor.as_deref(), &mut source.content)
+        {
+            let (redacted, found) = redactor.redact(content);
+            if !found.is_empty() {
+                let redacted = redacted.into_owned();
+                *content = redacted;
+                findings = found;
+            }
+        }
+        if let SourceContent::Text(content) = &mut source.content {
+            let lines = limits.max_lines.map(|_| content.lines().count());
+            if limits.exceeds(source.size, lines) {
+                if !limits.truncate {
+                    debug!("Omitting {}: too many lines", rel_path.display());
+                    return Ok((
+                        EntrySource::Oversized {
+                            size: source.size,
+                            lines,
+                        },
+                        Vec::new(),
+                    ));
+                }
+                if let Some(head_and_tail) = limits.truncate_text(content) {
+                    debug!("Truncating {}", rel_path.display());
+                    *content = head_and_tail;
+                    truncated = true;
+                }
+            }
+        }
+
+        let embedded = match source.content {
+            SourceContent::Binary { size }
+                if self.binary_mode.embeds(size) && !limits.exceeds_size(size) =>
+            {
+                Some(data.to_vec())
+            }
+            _ => None,
+        };
+        let redacted = !findings.is_empty();
+        Ok((
+            EntrySource::File {
+                source,
+                embedded,
+                truncated,
+                redacted,
+            },
+            findings,
+        ))
+    }
+}
+
+/// A writer that reads collected files and renders them with an [`OutputFormat`].
+///
+/// The format receives a `begin` event before the first entry and an `end`
+/// event from [`finish`](Self::finish). Writers created with
+/// [`create`](BundleWriter::create) and a [`PartBudget`] repeat this sequence
+/// for every part, so each part is a complete bundle of its own.
+pub struct BundleWriter<W: AsyncWrite + Unpin> {
     writer: BufWriter<W>,
-    header_written: bool,
+    format: Box<dyn OutputFormat>,
+    buffer: String,
+    started: bool,
+    finished: bool,
+    reader: EntryReader,
+    redactions: Vec<Redaction>,
+    path: Option<PathBuf>,
+    manifest: Option<Manifest>,
+    header: String,
+    written: usize,
+    skipped_generated: usize,
+    preamble: Option<Preamble>,
+    toc: bool,
+    part_headings: Vec<&'static str>,
+    part_contents: Vec<PathBuf>,
+    split: Option<SplitState<W>>,
+}
+
+impl BundleWriter<File> {
+    /// Creates the output file at `path`.
+    ///
+    /// If `budget` sets a limit, the output rolls over to `name.part2.ext`,
+    /// `name.part3.ext` and so on (see [`part_path`]).
+    pub async fn create(
+        path: &Path,
+        format: Box<dyn OutputFormat>,
+        budget: PartBudget,
+    ) -> Result<Self> {
+        let file = File::create(path)
+            .await
+            .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+        let mut writer = Self::with_format(BufWriter::new(file), format);
+        writer.path = Some(path.to_path_buf());
+
+        if !budget.is_unlimited() {
+            writer.split = Some(SplitState {
+                base_path: path.to_path_buf(),
+                budget,
+                open: |path| Box::pin(File::create(path)),
+                paths: vec![path.to_path_buf()],
+                part_size: PartSize::default(),
+                part_entries: 0,
+            });
+        }
+
+        Ok(writer)
+    }
 }
 
-impl MarkdownWriter<tokio::fs::File> {
-    /// Creates a new MarkdownWriter wrapping the given buffered writer.
-    pub fn new(writer: BufWriter<File>) -> Self {
+impl<W: AsyncWrite + Unpin> BundleWriter<W> {
+    /// Creates a new writer producing the default Markdown layout.
+    pub fn new(writer: BufWriter<W>) -> Self {
+        Self::with_format(writer, Box::new(MarkdownFormat))
+    }
+
+    /// Creates a new writer rendering entries with the given format.
+    pub fn with_format(writer: BufWriter<W>, format: Box<dyn OutputFormat>) -> Self {
         Self {
             writer,
-            header_written: false,
+            format,
+            buffer: String::new(),
+            started: false,
+            finished: false,
+            reader: EntryReader::default(),
+            redactions: Vec::new(),
+            path: None,
+            manifest: None,
+            header: String::new(),
+            written: 0,
+            skipped_generated: 0,
+            preamble: None,
+            toc: false,
+            part_headings: Vec::new(),
+            part_contents: Vec::new(),
+            split: None,
         }
     }
 
-    /// Writes the magic header that identifies this as a src2md output file.
-    /// This header allows us to skip reading our own output during collection.
-    async fn ensure_header_written(&mut self) -> Result<()> {
-        if !self.header_written {
-            self.writer
-                .write_all(OUTPUT_MAGIC_HEADER.as_bytes())
+    /// Paths of all parts written so far, in order.
+    ///
+    /// Empty unless the writer was created with a [`PartBudget`].
+    pub fn part_paths(&self) -> &[PathBuf] {
+        self.split.as_ref().map_or(&[], |split| &split.paths)
+    }
+
+    /// Sets how binary files are written. Defaults to [`BinaryMode::Omit`].
+    pub fn set_binary_mode(&mut self, mode: BinaryMode) {
+        self.reader.binary_mode = mode;
+    }
+
+    /// Sets the size limits for single files. Defaults to no limits.
+    pub fn set_file_limits(&mut self, limits: FileLimits) {
+        self.reader.limits = limits;
+    }
+
+    /// Sets the redactor applied to text files. Defaults to none.
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.reader.redactor = Some(Arc::new(redactor));
+    }
+
+    /// Sets whether minified files, source maps and files marked as
+    /// generated are left out (see
+    /// [`detect_generated`](crate::generated::detect_generated)). They are
+    /// recognized while the file is read for writing. Defaults to false.
+    pub fn set_skip_generated(&mut self, skip: bool) {
+        self.reader.skip_generated = skip;
+    }
+
+    /// Secrets redacted so far, in the order they were found.
+    pub fn redactions(&self) -> &[Redaction] {
+        &self.redactions
+    }
+
+    /// Paths of all files written so far: the parts of a split bundle, or
+    /// the output file of a writer made with [`create`](BundleWriter::create).
+    pub fn output_paths(&self) -> Vec<PathBuf> {
+        match &self.split {
+            Some(split) => split.paths.clone(),
+            None => self.path.iter().cloned().collect(),
+        }
+    }
+
+    /// Sets the manifest passed to the format's `begin` event of every part.
+    ///
+    /// Must be called before the first entry is written. For writers made
+    /// with [`create`](BundleWriter::create), [`finish`](Self::finish)
+    /// corrects the `files` count of the manifest in every part if some
+    /// entries failed and were skipped.
+    pub fn set_manifest(&mut self, manifest: Manifest) {
+        self.manifest = Some(manifest);
+    }
+
+    /// Sets the overview written after the header of the first part.
+    ///
+    /// Must be called before the first entry is written.
+    pub fn set_preamble(&mut self, preamble: Preamble) {
+        self.preamble = Some(preamble);
+    }
+
+    /// Emits the format's `begin` event if it has not been emitted yet.
+    async fn ensure_started(&mut self) -> Result<()> {
+        if !self.started {
+            self.format
+                .begin(self.manifest.as_ref(), &mut self.buffer)?;
+            self.header.clone_from(&self.buffer);
+            if let Some(preamble) = self.preamble.take() {
+                self.format.preamble(&preamble, &mut self.buffer)?;
+                self.toc = preamble.toc;
+                self.part_headings = preamble.headings();
+            }
+            self.started = true;
+            self.write_buffer()
                 .await
                 .context("Failed to write output header")?;
-            self.writer
-                .write_all(b"\n")
-                .await
-                .context("Failed to write newline after header")?;
-            self.header_written = true;
         }
         Ok(())
     }
 
-    /// Writes a single file entry to the Markdown output.
+    /// Writes a single file entry to the output.
     ///
-    /// - Text files are wrapped in code blocks with appropriate language tags
-    /// - Binary files are marked with "(binary file omitted)"
-    /// - Dynamic fencing ensures safe embedding of files containing backticks
-    pub async fn write_entry(&mut self, entry: &DirEntry, project_root: &Path) -> Result<()> {
-        // Ensure the magic header is written first
-        self.ensure_header_written().await?;
+    /// - Text files are passed to [`OutputFormat::file`]
+    /// - Binary files are passed to [`OutputFormat::binary`]
+    /// - Recorded symbolic links are passed to [`OutputFormat::symlink`]
+    /// - Files over the [`FileLimits`] are passed to
+    ///   [`OutputFormat::oversized`], or truncated if the limits say so
+    /// - Minified and generated files are left out if
+    ///   [`set_skip_generated`](Self::set_skip_generated) says so
+    ///
+    /// Secrets in text files are redacted first if a [`Redactor`] is set;
+    /// see [`redactions`](Self::redactions).
+    pub async fn write_entry(
+        &mut self,
+        entry: &impl CollectedFile,
+        project_root: &Path,
+    ) -> Result<()> {
+        // Ensure the header is written first
+        self.ensure_started().await?;
 
-        let path = entry.path();
-        let rel_path = path.strip_prefix(project_root).unwrap_or(path);
+        let prepared = self.reader.prepare(entry, project_root)?;
+        self.write_prepared(prepared).await
+    }
 
-        debug!("Processing: {}", rel_path.display());
+    /// Writes entries like [`write_entry`](Self::write_entry), reading up to
+    /// `jobs` files ahead on tokio's blocking thread pool.
+    ///
+    /// Entries are still rendered and written one at a time, in the order
+    /// given, so the output does not depend on `jobs`: formats keep state
+    /// between entries and must see them in bundle order.
+    ///
+    /// The error of a failed entry is passed to `on_error`, which returns
+    /// it to stop writing or `Ok` to skip the entry.
+    pub async fn write_entries<E, F>(
+        &mut self,
+        entries: &[E],
+        project_root: &Path,
+        jobs: usize,
+        mut on_error: F,
+    ) -> Result<()>
+    where
+        E: CollectedFile,
+        F: FnMut(&E, anyhow::Error) -> Result<()>,
+    {
+        self.ensure_started().await?;
 
-        // Write the file header
-        self.writer
-            .write_all(format!("## {}\n\n", rel_path.display()).as_bytes())
+        let mut pending = VecDeque::new();
+        let mut queued = entries.iter();
+        loop {
+            while pending.len() < jobs.max(1)
+                && let Some(entry) = queued.next()
+            {
+                let reader = self.reader.clone();
+                let task_entry = entry.clone();
+                let root = project_root.to_path_buf();
+                let task = task::spawn_blocking(move || reader.prepare(&task_entry, &root));
+                pending.push_back((entry, task));
+            }
+            let Some((entry, task)) = pending.pop_front() else {
+                break;
+            };
+
+            let result = match task.await {
+                Ok(Ok(prepared)) => self.write_prepared(prepared).await,
+                Ok(Err(err)) => Err(err),
+                Err(err) => Err(anyhow::anyhow!(
+                    "Failed to read {}: {err}",
+                    entry.path().display()
+                )),
+            };
+            if let Err(err) = result {
+                on_error(entry, err)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders and writes an entry read by [`EntryReader::prepare`].
+    async fn write_prepared(&mut self, entry: PreparedEntry) -> Result<()> {
+        let PreparedEntry {
+            path,
+            rel_path,
+            source,
+            findings,
+        } = entry;
+        let (path, rel_path) = (path.as_path(), rel_path.as_path());
+        if let EntrySource::Generated(kind) = source {
+            debug!("Skipping {}: {kind}", rel_path.display());
+            self.skipped_generated += 1;
+            return Ok(());
+        }
+        if let EntrySource::Output = source {
+            debug!("Skipping src2md output file: {}", rel_path.display());
+            return Ok(());
+        }
+
+        for Finding { line, kind } in findings {
+            warn!("Redacted {kind} in {}:{line}", rel_path.display());
+            self.redactions.push(Redaction {
+                path: rel_path.to_path_buf(),
+                line,
+                kind,
+            });
+        }
+        self.render(&source, path, rel_path)?;
+        let mut footer = self.measure_footer(rel_path)?;
+
+        if self.needs_new_part(footer) {
+            self.start_next_part().await?;
+            // Render again so that stateful formats start the part fresh
+            self.render(&source, path, rel_path)?;
+            footer = self.measure_footer(rel_path)?;
+        }
+
+        if let Some(split) = &mut self.split {
+            split.part_entries += 1;
+            let size = split.budget.measure(&self.buffer) + footer;
+            if split.part_entries == 1 && split.budget.is_exceeded_by(split.part_size + size) {
+                warn!(
+                    "{} exceeds the part budget on its own; writing it to a separate part",
+                    rel_path.display()
+                );
+            }
+        }
+
+        self.write_buffer()
             .await
-            .with_context(|| format!("Failed to write heading for {}", rel_path.display()))?;
+            .with_context(|| format!("Failed to write content for {}", rel_path.display()))?;
+        if self.toc {
+            self.part_contents.push(rel_path.to_path_buf());
+        }
+        self.written += 1;
 
-        // Memory-map the file for efficient reading
-        let file = StdFile::open(path)
-            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        debug!("Completed: {}", rel_path.display());
+        Ok(())
+    }
 
-        // SAFETY: We only read from the memory-mapped region and the file
-        // remains open for the duration of the mapping.
-        let mmap = unsafe {
-            MmapOptions::new()
-                .map(&file)
-                .with_context(|| format!("Failed to mmap file: {}", path.display()))?
-        };
+    /// Renders an entry into the buffer.
+    fn render(&mut self, source: &EntrySource, path: &Path, rel_path: &Path) -> Result<()> {
+        // Discard anything left over from a previously failed entry
+        self.buffer.clear();
 
-        // Inspect file content to detect binary files
-        let sample_size = std::cmp::min(8192, mmap.len());
-        let content_type = inspect(&mmap[..sample_size]);
+        let (source, embedded, truncated, redacted) = match source {
+            EntrySource::File {
+                source,
+                embedded,
+                truncated,
+                redacted,
+            } => (source, embedded.as_deref(), *truncated, *redacted),
+            EntrySource::Symlink { target } => {
+                let link = SymlinkFile {
+                    path: rel_path,
+                    target,
+                };
+                return self.format.symlink(&link, &mut self.buffer);
+            }
+            EntrySource::Oversized { size, lines } => {
+                let file = OversizedFile {
+                    path: rel_path,
+                    size: *size,
+                    lines: *lines,
+                };
+                return self.format.oversized(&file, &mut self.buffer);
+            }
+            EntrySource::Generated(_) | EntrySource::Output => return Ok(()),
+        };
 
-        if content_type == ContentType::BINARY {
-            self.writer
-                .write_all(b"(binary file omitted)\n\n")
-                .await
-                .with_context(|| {
-                    format!("Failed to write binary marker for {}", rel_path.display())
-                })?;
-            return Ok(());
+        match &source.content {
+            SourceContent::Text(content) => {
+                // The size and checksum of the original would give away the
+                // redacted secrets, so redacted files only describe `content`
+                let (size, sha256) = if redacted {
+                    (content.len() as u64, None)
+                } else {
+                    (source.size, Some(source.sha256.as_str()))
+                };
+                let file = TextFile {
+                    size,
+                    charset: source.charset.map(Encoding::name),
+                    sha256,
+                    mode: source.mode,
+                    truncated,
+                    redacted,
+                    ..TextFile::new(rel_path, get_language_tag(path), content)
+                };
+                self.format.file(&file, &mut self.buffer)
+            }
+            SourceContent::Binary { size } => {
+                let file = BinaryFile {
+                    content: embedded,
+                    sha256: Some(&source.sha256),
+                    mode: source.mode,
+                    ..BinaryFile::new(rel_path, *size)
+                };
+                self.format.binary(&file, &mut self.buffer)
+            }
         }
+    }
 
-        let lang = get_language_tag(path);
+    /// Renders the end of the current part: its table of contents, if one
+    /// was asked for, and the format's `end` event. `next` is the path of an
+    /// entry about to be added to the part.
+    fn render_footer(&mut self, next: Option<&Path>, out: &mut String) -> Result<()> {
+        if self.toc {
+            if let Some(next) = next {
+                self.part_contents.push(next.to_path_buf());
+            }
+            let contents = Contents {
+                paths: &self.part_contents,
+                headings: &self.part_headings,
+            };
+            let result = self.format.contents(&contents, out);
+            if next.is_some() {
+                self.part_contents.pop();
+            }
+            result?;
+        }
+        self.format.end(out)
+    }
 
-        // Convert to string, with fallback for edge cases
-        let content: String = match str::from_utf8(&mmap) {
-            Ok(s) => s.to_string(),
-            Err(_) => std::fs::read_to_string(path)
-                .with_context(|| format!("Fallback read failed for {}", path.display()))?,
+    /// Measures the footer that would end the part after the rendered entry
+    /// at `rel_path`, which has to fit into the part as well.
+    fn measure_footer(&mut self, rel_path: &Path) -> Result<PartSize> {
+        let Some(budget) = self.split.as_ref().map(|split| split.budget) else {
+            return Ok(PartSize::default());
         };
-        let text = content.as_str();
+        let mut footer = String::new();
+        self.render_footer(Some(rel_path), &mut footer)?;
+        Ok(budget.measure(&footer))
+    }
 
-        // Calculate the minimum fence length needed to safely wrap this content
-        let fence = calculate_fence(text);
+    /// Returns true if the rendered entry and the `footer` after it do not
+    /// fit into the current part.
+    fn needs_new_part(&self, footer: PartSize) -> bool {
+        self.split.as_ref().is_some_and(|split| {
+            split.part_entries > 0
+                && split
+                    .budget
+                    .is_exceeded_by(split.part_size + split.budget.measure(&self.buffer) + footer)
+        })
+    }
 
-        // Write opening fence with language tag
+    /// Ends the current part and opens the next one.
+    async fn start_next_part(&mut self) -> Result<()> {
+        let mut footer = String::new();
+        self.render_footer(None, &mut footer)?;
         self.writer
-            .write_all(format!("{}{}\n", fence, lang).as_bytes())
+            .write_all(footer.as_bytes())
             .await
-            .with_context(|| format!("Failed to write opening fence for {}", rel_path.display()))?;
+            .context("Failed to write output footer")?;
+        self.flush().await?;
+        self.part_headings.clear();
+        self.part_contents.clear();
 
-        // Write file content
-        self.writer
-            .write_all(text.as_bytes())
+        let Some(split) = &mut self.split else {
+            return Ok(());
+        };
+
+        let next_path = part_path(&split.base_path, split.paths.len() + 1);
+        debug!("Starting new part: {}", next_path.display());
+
+        let file = (split.open)(next_path.clone())
             .await
-            .with_context(|| format!("Failed to write content for {}", rel_path.display()))?;
+            .with_context(|| format!("Failed to create output file: {}", next_path.display()))?;
+        split.paths.push(next_path);
+        split.part_size = PartSize::default();
+        split.part_entries = 0;
+        self.writer = BufWriter::new(file);
 
-        // Write closing fence
+        let mut header = String::new();
+        self.format.begin(self.manifest.as_ref(), &mut header)?;
+        if let Some(split) = &mut self.split {
+            split.part_size = split.budget.measure(&header);
+        }
         self.writer
-            .write_all(format!("\n{}\n\n", fence).as_bytes())
+            .write_all(header.as_bytes())
             .await
-            .with_context(|| format!("Failed to write closing fence for {}", rel_path.display()))?;
+            .context("Failed to write output header")?;
+        Ok(())
+    }
 
-        debug!("Completed: {}", rel_path.display());
+    /// Emits the format's `end` event and flushes the output.
+    ///
+    /// For writers made with [`create`](BundleWriter::create), parts left
+    /// over from an earlier run that was split into more parts are removed.
+    /// Calling this more than once has no further effect on the output.
+    pub async fn finish(&mut self) -> Result<()> {
+        self.ensure_started().await?;
+        if !self.finished {
+            let mut footer = String::new();
+            self.render_footer(None, &mut footer)?;
+            self.buffer = footer;
+            self.finished = true;
+            if self.skipped_generated > 0 {
+                debug!(
+                    "Skipped {} minified or generated file(s)",
+                    self.skipped_generated
+                );
+            }
+            self.write_buffer()
+                .await
+                .context("Failed to write output footer")?;
+            if let Some(path) = &self.path {
+                remove_stale_parts(path, self.output_paths().len() + 1).await;
+            }
+            self.flush().await?;
+            self.patch_manifest().await?;
+        }
+        self.flush().await
+    }
+
+    /// Rewrites the header of every part if the manifest counts entries that
+    /// were not written.
+    ///
+    /// The new header is never longer than the old one, so the parts stay
+    /// within their budget.
+    async fn patch_manifest(&mut self) -> Result<()> {
+        let Some(manifest) = &mut self.manifest else {
+            return Ok(());
+        };
+        if self.path.is_none() || manifest.files == self.written {
+            return Ok(());
+        }
+        manifest.files = self.written;
+        let mut header = String::new();
+        self.format.begin(Some(manifest), &mut header)?;
+
+        for path in self.output_paths() {
+            if !replace_header(&path, &self.header, &header).await? {
+                warn!("Not updating the manifest of {}", path.display());
+            }
+        }
         Ok(())
     }
 
@@ -138,13 +1282,62 @@ impl MarkdownWriter<tokio::fs::File> {
     pub async fn flush(&mut self) -> Result<()> {
         self.writer.flush().await.context("Failed to flush output")
     }
+
+    /// Writes and clears the rendered text buffer.
+    async fn write_buffer(&mut self) -> std::io::Result<()> {
+        if let Some(split) = &mut self.split {
+            split.part_size = split.part_size + split.budget.measure(&self.buffer);
+        }
+        let result = self.writer.write_all(self.buffer.as_bytes()).await;
+        self.buffer.clear();
+        result
+    }
+}
+
+/// Replaces the header `old` at the start of the file at `path` by `new`.
+///
+/// The rest of the file is streamed into a temporary file next to it, which
+/// then replaces the original, so the file is never held in memory. Returns
+/// `false` if the file does not start with `old`.
+async fn replace_header(path: &Path, old: &str, new: &str) -> Result<bool> {
+    let read_context = || format!("Failed to read output file: {}", path.display());
+    let write_context = || format!("Failed to write output file: {}", path.display());
+    let mut file = File::open(path).await.with_context(read_context)?;
+    let mut start = vec![0; old.len()];
+    match file.read_exact(&mut start).await {
+        Ok(_) if start == old.as_bytes() => {}
+        Ok(_) => return Ok(false),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+        Err(err) => return Err(err).with_context(read_context),
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{file_name}.tmp"));
+    let copied = async {
+        let mut temp = BufWriter::new(File::create(&temp_path).await?);
+        temp.write_all(new.as_bytes()).await?;
+        tokio::io::copy(&mut file, &mut temp).await?;
+        temp.flush().await
+    }
+    .await;
+    // The original must be closed before it can be replaced on Windows
+    drop(file);
+    let result = match copied {
+        Ok(()) => fs::rename(&temp_path, path).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(err).with_context(write_context);
+    }
+    Ok(true)
 }
 
 /// Calculates the appropriate fence string for wrapping content.
 ///
 /// Returns a fence with at least 3 backticks, or more if the content
 /// contains backtick sequences that would interfere with parsing.
-fn calculate_fence(content: &str) -> String {
+pub(crate) fn calculate_fence(content: &str) -> String {
     let max_backtick_run = content
         .lines()
         .filter_map(|line| {
@@ -205,11 +1398,155 @@ mod tests {
         assert_eq!(calculate_fence(content), "```");
     }
 
+    #[test]
+    fn test_markdown_format_file() -> Result<()> {
+        let mut out = String::new();
+        let file = TextFile::new(Path::new("src/main.rs"), "rust", "fn main() {}");
+        MarkdownFormat.file(&file, &mut out)?;
+        assert_eq!(out, "## src/main.rs\n\n```rust\nfn main() {}\n```\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_format_binary() -> Result<()> {
+        let mut out = String::new();
+        let file = BinaryFile::new(Path::new("logo.png"), 42);
+        MarkdownFormat.binary(&file, &mut out)?;
+        assert_eq!(out, "## logo.png\n\n(binary file omitted)\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_format_embedded_binary() -> Result<()> {
+        let mut out = String::new();
+        let file = BinaryFile {
+            content: Some(b"abc"),
+            ..BinaryFile::new(Path::new("data.bin"), 3)
+        };
+        MarkdownFormat.binary(&file, &mut out)?;
+        assert_eq!(
+            out,
+            "## data.bin\n\n```base64 size=3 sha256=ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\nYWJj\n```\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_mode_embeds() {
+        assert!(!BinaryMode::Omit.embeds(0));
+        assert!(BinaryMode::Embed { max_size: None }.embeds(u64::MAX));
+        assert!(BinaryMode::Embed { max_size: Some(10) }.embeds(10));
+        assert!(!BinaryMode::Embed { max_size: Some(10) }.embeds(11));
+    }
+
+    #[test]
+    fn test_markdown_format_oversized() -> Result<()> {
+        let mut out = String::new();
+        let file = OversizedFile {
+            path: Path::new("dump.sql"),
+            size: 4096,
+            lines: None,
+        };
+        MarkdownFormat.oversized(&file, &mut out)?;
+        assert_eq!(
+            out,
+            "## dump.sql\n<!-- src2md:file {\"size\":4096,\"omitted\":true} -->\n\n(omitted: file too large, 4096 bytes)\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_limits_exceeded() {
+        let limits = FileLimits {
+            max_size: Some(10),
+            max_lines: Some(2),
+            truncate: false,
+        };
+        assert!(!limits.exceeds(10, Some(2)));
+        assert!(limits.exceeds(11, Some(2)));
+        assert!(limits.exceeds(10, Some(3)));
+        assert!(!limits.exceeds(10, None));
+        assert!(!FileLimits::default().exceeds(u64::MAX, Some(usize::MAX)));
+    }
+
+    #[test]
+    fn test_truncate_text_by_lines() {
+        let limits = FileLimits {
+            max_lines: Some(4),
+            ..FileLimits::default()
+        };
+        let content = "1\n2\n3\n4\n5\n6\n7";
+        assert_eq!(
+            limits.truncate_text(content).as_deref(),
+            Some("1\n2\n[... truncated by src2md: 3 lines (6 bytes) omitted ...]\n6\n7")
+        );
+        assert_eq!(limits.truncate_text("1\n2\n3\n4\n"), None);
+    }
+
+    #[test]
+    fn test_truncate_text_by_bytes() {
+        let limits = FileLimits {
+            max_size: Some(8),
+            ..FileLimits::default()
+        };
+        let content = "aa\nbb\ncc\ndd\nee\n";
+        assert_eq!(
+            limits.truncate_text(content).as_deref(),
+            Some("aa\n[... truncated by src2md: 3 lines (9 bytes) omitted ...]\nee\n")
+        );
+
+        // A single line over the limit leaves only the marker
+        assert_eq!(
+            limits.truncate_text("0123456789abcdef").as_deref(),
+            Some("[... truncated by src2md: 1 lines (16 bytes) omitted ...]\n")
+        );
+    }
+
+    #[test]
+    fn test_part_path() {
+        let base = Path::new("out/bundle.md");
+        assert_eq!(part_path(base, 1), PathBuf::from("out/bundle.md"));
+        assert_eq!(part_path(base, 2), PathBuf::from("out/bundle.part2.md"));
+        assert_eq!(part_path(base, 10), PathBuf::from("out/bundle.part10.md"));
+        assert_eq!(
+            part_path(Path::new("bundle"), 3),
+            PathBuf::from("bundle.part3")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("bundle.md");
+        std::fs::write(&path, "<!-- files: 10 -->\nrest\n")?;
+
+        assert!(replace_header(&path, "<!-- files: 10 -->\n", "<!-- files: 9 -->\n").await?);
+        assert_eq!(std::fs::read_to_string(&path)?, "<!-- files: 9 -->\nrest\n");
+        // Files that do not start with the old header are left alone
+        assert!(!replace_header(&path, "<!-- files: 10 -->\n", "").await?);
+        assert!(!replace_header(&path, &"x".repeat(100), "").await?);
+        assert_eq!(std::fs::read_to_string(&path)?, "<!-- files: 9 -->\nrest\n");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_budget_limits() {
+        let budget = PartBudget {
+            max_tokens: None,
+            max_bytes: Some(10),
+        };
+        assert!(!budget.is_unlimited());
+        assert!(!budget.is_exceeded_by(budget.measure("0123456789")));
+        assert!(budget.is_exceeded_by(budget.measure("0123456789a")));
+        assert!(PartBudget::default().is_unlimited());
+    }
+
     #[test]
     fn test_magic_header_format() {
         // Ensure the magic header is a valid HTML comment
         assert!(OUTPUT_MAGIC_HEADER.starts_with("<!--"));
         assert!(OUTPUT_MAGIC_HEADER.contains("src2md"));
-        assert!(OUTPUT_MAGIC_HEADER.contains("v1"));
+        assert!(OUTPUT_MAGIC_HEADER.contains("v2"));
     }
 }
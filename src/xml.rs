@@ -0,0 +1,271 @@
+//! XML-tagged bundle format for LLM prompts.
+//!
+//! Each file is wrapped in a `<document>` element:
+//!
+//! ```xml
+//! <!-- src2md:v1 -->
+//! <documents>
+//! <document path="src/main.rs" language="rust">
+//! <source><![CDATA[fn main() {}
+//! ]]></source>
+//! </document>
+//! <document path="logo.png" binary="true" size="1024"/>
+//! </documents>
+//! ```
+//!
+//! The `<documents>` element carries the [`Manifest`] of the bundle, if any,
+//! as `version`, `generated`, `root`, `commit` and `files` attributes.
+//!
+//! Embedded binary files carry `encoding="base64"` and their base64 content
+//! in a `<source>` element. Text files that are not UTF-8 carry their
+//! original encoding in a `charset` attribute, and recorded symbolic links
+//! are empty elements with a `symlink` attribute. Text files cut down by the
+//! [`FileLimits`](crate::writer::FileLimits) carry `truncated="true"`, and
+//! files left out for their size are empty elements with `omitted="true"`.
+//! Files whose secrets were replaced with placeholders carry
+//! `redacted="true"`.
+//!
+//! Attribute values are entity-escaped and file content is embedded in CDATA
+//! sections. A `]]>` sequence inside the content is split across two CDATA
+//! sections, and characters that are not allowed in XML (control characters
+//! other than tab, newline and carriage return) are replaced with U+FFFD, so
+//! the output is always well-formed.
+//!
+//! The document starts with [`OUTPUT_MAGIC_HEADER`], which is a valid XML
+//! comment, so XML bundles are excluded from collection like Markdown ones.
+
+use crate::format::{BinaryFile, OutputFormat, OversizedFile, SymlinkFile, TextFile};
+use crate::manifest::Manifest;
+use crate::utils::encode_base64;
+use crate::writer::{BASE64_INFO_STRING, OUTPUT_MAGIC_HEADER};
+use anyhow::Result;
+
+/// Renders a bundle as `<document>` elements inside a `<documents>` root.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct XmlFormat;
+
+impl OutputFormat for XmlFormat {
+    fn begin(&mut self, manifest: Option<&Manifest>, out: &mut String) -> Result<()> {
+        out.push_str(OUTPUT_MAGIC_HEADER);
+        out.push_str("<documents");
+        if let Some(manifest) = manifest {
+            let mut attrs = vec![
+                ("version", manifest.version.as_str()),
+                ("generated", manifest.generated.as_str()),
+                ("root", manifest.root.as_str()),
+            ];
+            if let Some(commit) = &manifest.commit {
+                attrs.push(("commit", commit));
+            }
+            for (name, value) in attrs {
+                out.push_str(&format!(" {name}=\""));
+                push_escaped_attr(value, out);
+                out.push('"');
+            }
+            out.push_str(&format!(" files=\"{}\"", manifest.files));
+        }
+        out.push_str(">\n");
+        for path in manifest.iter().flat_map(|manifest| &manifest.deleted) {
+            out.push_str("<deleted path=\"");
+            push_escaped_attr(path, out);
+            out.push_str("\"/>\n");
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, file: &TextFile<'_>, out: &mut String) -> Result<()> {
+        out.push_str("<document path=\"");
+        push_escaped_attr(&file.path.display().to_string(), out);
+        out.push('"');
+        if !file.language.is_empty() {
+            out.push_str(" language=\"");
+            push_escaped_attr(file.language, out);
+            out.push('"');
+        }
+        if let Some(charset) = file.charset {
+            out.push_str(" charset=\"");
+            push_escaped_attr(charset, out);
+            out.push('"');
+        }
+        if file.truncated {
+            out.push_str(" truncated=\"true\"");
+        }
+        if file.redacted {
+            out.push_str(" redacted=\"true\"");
+        }
+        out.push_str(">\n<source>");
+        push_cdata(file.content, out);
+        out.push_str("</source>\n</document>\n");
+        Ok(())
+    }
+
+    fn binary(&mut self, file: &BinaryFile<'_>, out: &mut String) -> Result<()> {
+        out.push_str("<document path=\"");
+        push_escaped_attr(&file.path.display().to_string(), out);
+        out.push_str(&format!("\" binary=\"true\" size=\"{}\"", file.size));
+        match file.content {
+            Some(content) => {
+                // Base64 never needs escaping
+                out.push_str(&format!(
+                    " encoding=\"{BASE64_INFO_STRING}\">\n<source>{}</source>\n</document>\n",
+                    encode_base64(content)
+                ));
+            }
+            None => out.push_str("/>\n"),
+        }
+        Ok(())
+    }
+
+    fn symlink(&mut self, link: &SymlinkFile<'_>, out: &mut String) -> Result<()> {
+        out.push_str("<document path=\"");
+        push_escaped_attr(&link.path.display().to_string(), out);
+        out.push_str("\" symlink=\"");
+        push_escaped_attr(link.target, out);
+        out.push_str("\"/>\n");
+        Ok(())
+    }
+
+    fn oversized(&mut self, file: &OversizedFile<'_>, out: &mut String) -> Result<()> {
+        out.push_str("<document path=\"");
+        push_escaped_attr(&file.path.display().to_string(), out);
+        out.push_str(&format!("\" size=\"{}\" omitted=\"true\"/>\n", file.size));
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut String) -> Result<()> {
+        out.push_str("</documents>\n");
+        Ok(())
+    }
+}
+
+/// Returns true for characters permitted by the XML 1.0 `Char` production.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}')
+        || c >= '\u{10000}'
+}
+
+/// Appends `value` escaped for use inside a double-quoted attribute.
+fn push_escaped_attr(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Preserve whitespace that attribute normalization would collapse
+            '\t' => out.push_str("&#9;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            c if is_xml_char(c) => out.push(c),
+            _ => out.push('\u{FFFD}'),
+        }
+    }
+}
+
+/// Appends `content` wrapped in one or more CDATA sections.
+fn push_cdata(content: &str, out: &mut String) {
+    out.push_str("<![CDATA[");
+    for c in content.chars() {
+        if is_xml_char(c) {
+            out.push(c);
+        } else {
+            out.push('\u{FFFD}');
+        }
+        // Split a `]]>` terminator so that it ends up in two adjacent sections
+        if c == '>' && out.ends_with("]]>") {
+            out.truncate(out.len() - 1);
+            out.push_str("]]><![CDATA[>");
+        }
+    }
+    out.push_str("]]>");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_xml_format_document() -> Result<()> {
+        let mut out = String::new();
+        let mut format = XmlFormat;
+        format.begin(None, &mut out)?;
+        format.file(
+            &TextFile::new(Path::new("src/main.rs"), "rust", "fn main() {}\n"),
+            &mut out,
+        )?;
+        format.binary(&BinaryFile::new(Path::new("logo.png"), 12), &mut out)?;
+        format.binary(
+            &BinaryFile {
+                content: Some(b"abc"),
+                ..BinaryFile::new(Path::new("data.bin"), 3)
+            },
+            &mut out,
+        )?;
+        format.end(&mut out)?;
+
+        assert!(out.starts_with(OUTPUT_MAGIC_HEADER));
+        assert!(out.contains(
+            "<document path=\"src/main.rs\" language=\"rust\">\n<source><![CDATA[fn main() {}\n]]></source>\n</document>\n"
+        ));
+        assert!(out.contains("<document path=\"logo.png\" binary=\"true\" size=\"12\"/>\n"));
+        assert!(out.contains(
+            "<document path=\"data.bin\" binary=\"true\" size=\"3\" encoding=\"base64\">\n<source>YWJj</source>\n</document>\n"
+        ));
+        assert!(out.ends_with("</documents>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_format_omits_empty_language() -> Result<()> {
+        let mut out = String::new();
+        XmlFormat.file(&TextFile::new(Path::new("notes"), "", "text"), &mut out)?;
+        assert!(out.starts_with("<document path=\"notes\">"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_format_marks_incomplete_files() -> Result<()> {
+        let mut out = String::new();
+        XmlFormat.file(
+            &TextFile {
+                truncated: true,
+                ..TextFile::new(Path::new("big.log"), "", "a\n")
+            },
+            &mut out,
+        )?;
+        XmlFormat.oversized(
+            &OversizedFile {
+                path: Path::new("dump.sql"),
+                size: 4096,
+                lines: None,
+            },
+            &mut out,
+        )?;
+        assert!(out.starts_with("<document path=\"big.log\" truncated=\"true\">"));
+        assert!(out.ends_with("<document path=\"dump.sql\" size=\"4096\" omitted=\"true\"/>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_attribute() {
+        let mut out = String::new();
+        push_escaped_attr("a&b<c>\"d'\te", &mut out);
+        assert_eq!(out, "a&amp;b&lt;c&gt;&quot;d&apos;&#9;e");
+    }
+
+    #[test]
+    fn test_cdata_splits_terminator() {
+        let mut out = String::new();
+        push_cdata("if a[b[0]]>1 {}", &mut out);
+        assert_eq!(out, "<![CDATA[if a[b[0]]]]><![CDATA[>1 {}]]>");
+    }
+
+    #[test]
+    fn test_cdata_replaces_invalid_characters() {
+        let mut out = String::new();
+        push_cdata("bell\u{7}\r\n", &mut out);
+        assert_eq!(out, "<![CDATA[bell\u{FFFD}\r\n]]>");
+    }
+}
@@ -0,0 +1,25 @@
+//! Git helpers shared by the unit tests of the `git` and `diff` modules and
+//! the smoke tests.
+
+use git2::{IndexAddOption, Repository, Signature};
+
+/// Stages all files in the working tree and commits them.
+pub fn commit_all(repo: &Repository, message: &str) -> anyhow::Result<()> {
+    let mut index = repo.index()?;
+    index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"], None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = Signature::now("src2md", "src2md@example.com")?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?;
+    Ok(())
+}